@@ -0,0 +1,114 @@
+//! Throughput of I, F and E editing through `FortranIterWriter`,
+//! compared with the editing the writer did before it formatted into
+//! stack buffers: nested `format!` calls for every item.
+//!
+//! Run with `cargo bench`.
+
+extern crate f77_io;
+
+use std::hint::black_box;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use f77_io::format::parse_format;
+use f77_io::write::FortranIterWriter;
+
+const ITEMS: usize = 200_000;
+
+fn previous_i(out: &mut Vec<u8>, val: i32, w: usize) {
+    let s = format!("{}", val);
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(d) => (true, d.to_string()),
+        None => (false, s),
+    };
+    let sign = if negative { "-" } else { "" };
+    let mut s = format!("{:>w$}", format!("{}{}", sign, digits), w=w);
+    if s.len() > w {
+        s = format!("{:*>w$}", "", w=w);
+    }
+    write!(out, "{}", s).unwrap();
+}
+
+fn previous_f(out: &mut Vec<u8>, val: f64, w: usize, d: usize) {
+    let sign = if val.is_sign_negative() { "-" } else { "" };
+    let body = format!("{:.d$}", val.abs(), d=d);
+    let s = format!("{}{}", sign, body);
+    if s.len() <= w {
+        write!(out, "{:>w$}", s, w=w).unwrap();
+    } else {
+        write!(out, "{:*>w$}", "", w=w).unwrap();
+    }
+}
+
+fn previous_e(out: &mut Vec<u8>, val: f64, w: usize, d: usize) {
+    let sign = if val.is_sign_negative() { "-" } else { "" };
+    let abs = val.abs();
+    let s = format!("{:.*e}", d - 1, abs);
+    let e = s.find('e').unwrap();
+    let digits: String = s[..e].chars().filter(|&c| c != '.').collect();
+    let x = s[e + 1..].parse::<isize>().unwrap() + 1;
+    let exp = if abs == 0.0 { 0 } else { x };
+    let exp_sign = if exp < 0 { '-' } else { '+' };
+    let mantissa = format!("0.{}", digits);
+    let s = format!("{}{}", sign, mantissa + &format!("E{}{:02}", exp_sign, exp.unsigned_abs()));
+    if s.len() <= w {
+        write!(out, "{:>w$}", s, w=w).unwrap();
+    } else {
+        write!(out, "{:*>w$}", "", w=w).unwrap();
+    }
+}
+
+fn writer<T: f77_io::write::FortranWrite>(fmt: &str, vals: &[T], out: &mut Vec<u8>) {
+    let fmt = parse_format(fmt).unwrap();
+    let mut writer = FortranIterWriter::new(&fmt);
+    for v in vals {
+        writer.write_constants(out, true).unwrap();
+        writer.write_value(out, v).unwrap();
+    }
+    writer.write_constants(out, false).unwrap();
+}
+
+fn measure<F: FnMut(&mut Vec<u8>)>(mut f: F) -> (Duration, usize) {
+    let mut out = Vec::with_capacity(ITEMS * 16);
+    // warm up, then take the best of a few runs
+    f(&mut out);
+    let mut best = Duration::MAX;
+    for _ in 0..5 {
+        out.clear();
+        let start = Instant::now();
+        f(&mut out);
+        best = best.min(start.elapsed());
+        black_box(&out);
+    }
+    (best, out.len())
+}
+
+fn report(name: &str, (time, bytes): (Duration, usize)) {
+    let secs = time.as_secs_f64();
+    println!("{:<24} {:>8.1} ns/item {:>8.1} MB/s", name,
+             secs * 1e9 / ITEMS as f64, bytes as f64 / secs / 1e6);
+}
+
+fn main() {
+    let ints: Vec<i32> = (0..ITEMS as i32).map(|i| i.wrapping_mul(7919) - 1_000_000).collect();
+    let reals: Vec<f64> = (0..ITEMS).map(|i| (i as f64 - 1000.0) * 0.123_456_7).collect();
+
+    report("I12 writer", measure(|out| writer("(8I12)", &ints, out)));
+    report("I12 previous", measure(|out| {
+        for &v in &ints {
+            previous_i(out, v, 12);
+        }
+    }));
+    report("F15.7 writer", measure(|out| writer("(8F15.7)", &reals, out)));
+    report("F15.7 previous", measure(|out| {
+        for &v in &reals {
+            previous_f(out, v, 15, 7);
+        }
+    }));
+    report("E15.7 writer", measure(|out| writer("(8E15.7)", &reals, out)));
+    report("E15.7 previous", measure(|out| {
+        for &v in &reals {
+            previous_e(out, v, 15, 7);
+        }
+    }));
+}
@@ -64,6 +64,29 @@ fn main() {
         f77_read!(buffer, *, &mut ary[..])
             .expect("could not read array");
         println!("input: {:?}", input);
+        println!("values: {:?}\n", &ary);
+    }
+
+    {
+        let input = "   42  3.1416 T\n";
+        let mut buffer = BufReader::new(input.as_bytes());
+        let mut i = 0i32;
+        let mut x = 0.0f64;
+        let mut l = false;
+        f77_read!(buffer, "(I5, F8.4, L2)", i, x, l)
+            .expect("could not read formatted values");
+        println!("input: {:?}", input);
+        println!("values: {}, {}, {}\n", i, x, l);
+    }
+
+    {
+        use f77_io::internal::InternalReader;
+        let records = ["  1.5  2.5", "  3.5"];
+        let mut inp = InternalReader::new(&records[..]);
+        let mut ary = [0.0f64; 4];
+        f77_read!(inp, "(2F5.1)", &mut ary[..])
+            .expect("could not read internal file");
+        println!("input: {:?}", records);
         println!("values: {:?}", &ary);
     }
 }
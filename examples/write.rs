@@ -11,4 +11,37 @@ fn main() {
         .expect("failed to write star format");
     f77_write!(*, *, ary)
         .expect("failed to write star array");
+
+    let mut lines = vec![String::new(); 2];
+    f77_write!(f77_io::internal::InternalWriter::new(&mut lines, 8), "(I8.3)", 7, 8)
+        .expect("failed to write an internal file");
+    println!("{:?}", lines);
+
+    let cell = fortran_format!("(F8.3, I4)", 2.5, 7)
+        .expect("failed to format a string");
+    let star = fortran_format!(*, 1, true)
+        .expect("failed to format a string with star format");
+    println!("[{}] [{}]", cell, star);
+
+    f77_write!(6, "('unit 6:', I3)", 42)
+        .expect("failed to write to unit 6");
+    let path = std::env::temp_dir().join("f77-io-example.dat");
+    let options = f77_io::units::OpenOptions {
+        status: f77_io::units::Status::Replace,
+        ..Default::default()
+    };
+    f77_io::units::open(10, Some(&path), options)
+        .expect("failed to open unit 10");
+    f77_write!(10, "(2F6.2)", 1.25, 2.5)
+        .expect("failed to write to unit 10");
+    f77_io::units::close(10, false)
+        .expect("failed to close unit 10");
+    let (mut a, mut b) = (0.0f64, 0.0f64);
+    f77_io::units::open(10, Some(&path), Default::default())
+        .expect("failed to open unit 10");
+    f77_read!(10, "(2F6.2)", a, b)
+        .expect("failed to read from unit 10");
+    f77_io::units::close(10, true)
+        .expect("failed to close unit 10");
+    println!("unit 10: {} {}", a, b);
 }
@@ -0,0 +1,440 @@
+//! Converts fixed-width formatted files to CSV or JSON lines and back
+//!
+//! Each statement of the format reads one row: the values its data
+//! edit descriptors read become the columns. Going back, the cells of
+//! each row are written with the format, taking the type of their
+//! descriptor.
+
+extern crate f77_io;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
+use f77_io::format::{parse_format, FormatNode};
+use f77_io::json::{self, Json};
+use f77_io::program::{FormatProgram, ProgramCursor};
+use f77_io::read::{FortranValueRecords, ReaderConfig};
+use f77_io::value::FortranValue;
+use f77_io::write::{FortranIterWriter, WriterConfig};
+
+const USAGE: &str = "usage: f77conv [--to csv|jsonl | --from csv|jsonl] [--names A,B,...] \
+                     [--lenient] FORMAT [FILE]";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Table {
+    Csv,
+    JsonLines,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Direction {
+    To(Table),
+    From(Table),
+}
+
+struct Options {
+    direction: Direction,
+    names: Vec<String>,
+    strict: bool,
+    format: FormatNode,
+    path: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let table = |s: &str| match s {
+        "csv" => Ok(Table::Csv),
+        "jsonl" | "json" => Ok(Table::JsonLines),
+        x => Err(format!("unknown table format {}", x)),
+    };
+    let (mut direction, mut names, mut strict) = (Direction::To(Table::Csv), vec![], true);
+    let mut free = vec![];
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--to" => direction = Direction::To(table(value()?)?),
+            "--from" => direction = Direction::From(table(value()?)?),
+            "--names" => names = value()?.split(',').map(|s| s.trim().to_string()).collect(),
+            "--lenient" => strict = false,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("unknown option {}\n{}", arg, USAGE));
+            },
+            _ => free.push(arg.clone()),
+        }
+    }
+    if free.is_empty() || free.len() > 2 {
+        return Err(USAGE.to_string());
+    }
+    let format = parse_format(&free[0]).map_err(|e| format!("bad format {}: {:?}", free[0], e))?;
+    let path = free.get(1).filter(|p| p.as_str() != "-").cloned();
+    Ok(Options { direction, names, strict, format, path })
+}
+
+/// The name of column `i`, counted from zero.
+fn column(names: &[String], i: usize) -> String {
+    names.get(i).cloned().unwrap_or_else(|| format!("c{}", i + 1))
+}
+
+/// A value as a CSV cell, quoted when needed.
+fn csv_cell(value: &FortranValue) -> String {
+    let text = match *value {
+        FortranValue::Bool(x) => x.to_string(),
+        FortranValue::Strin(ref s) => s.clone(),
+        ref x => x.to_string(),
+    };
+    if text.contains(['"', ',', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// A value as JSON, numbers that are not finite as `null`.
+fn json_value(out: &mut String, value: &FortranValue) {
+    use std::fmt::Write;
+    let _ = match *value {
+        FortranValue::Bool(x) => write!(out, "{}", x),
+        FortranValue::Strin(ref s) => json::write_str(out, s),
+        FortranValue::Real8(x) if !x.is_finite() => write!(out, "null"),
+        FortranValue::Real4(x) if !x.is_finite() => write!(out, "null"),
+        FortranValue::Array(ref v) => {
+            out.push('[');
+            for (i, x) in v.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                json_value(out, x);
+            }
+            out.push(']');
+            Ok(())
+        },
+        ref x => write!(out, "{}", x),
+    };
+}
+
+/// Read the formatted input, writing a row per statement.
+fn to_table<R: BufRead>(input: &mut R, out: &mut dyn Write, opts: &Options, table: Table) -> io::Result<bool> {
+    let config = ReaderConfig { strict: opts.strict, ..ReaderConfig::default() };
+    let mut sound = true;
+    let mut header = table == Table::Csv;
+    for rv in FortranValueRecords::with_config(input, &opts.format, config) {
+        let values = match rv {
+            Ok(values) => values,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                sound = false;
+                continue;
+            },
+        };
+        match table {
+            Table::Csv => {
+                if header {
+                    let names: Vec<String> = (0..values.len().max(opts.names.len()))
+                        .map(|i| csv_cell(&FortranValue::Strin(column(&opts.names, i))))
+                        .collect();
+                    writeln!(out, "{}", names.join(","))?;
+                    header = false;
+                }
+                let cells: Vec<String> = values.iter().map(csv_cell).collect();
+                writeln!(out, "{}", cells.join(","))?;
+            },
+            Table::JsonLines => {
+                let mut line = String::from("{");
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        line.push(',');
+                    }
+                    let _ = json::write_str(&mut line, &column(&opts.names, i));
+                    line.push(':');
+                    json_value(&mut line, value);
+                }
+                line.push('}');
+                writeln!(out, "{}", line)?;
+            },
+        }
+    }
+    Ok(sound)
+}
+
+/// The cells of the next CSV row, `None` at the end of the input.
+/// Quoted cells may span lines.
+fn csv_row<R: BufRead>(input: &mut R) -> io::Result<Option<Vec<String>>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let (mut cells, mut cell, mut quoted) = (vec![], String::new(), false);
+    loop {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    cell.push('"');
+                },
+                '"' => quoted = !quoted,
+                ',' if !quoted => cells.push(::std::mem::take(&mut cell)),
+                '\r' | '\n' if !quoted => {},
+                c => cell.push(c),
+            }
+        }
+        if !quoted {
+            break;
+        }
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unterminated quoted cell"));
+        }
+    }
+    cells.push(cell);
+    Ok(Some(cells))
+}
+
+/// A cell as a value of the type of `template`. Cells past the data
+/// descriptors of the format are integers, reals or strings by their text.
+fn parse_cell(template: Option<&FortranValue>, text: &str) -> Result<FortranValue, String> {
+    let t = text.trim();
+    let bad = || format!("cannot read {:?} as {:?}", text, template);
+    Ok(match template {
+        Some(&FortranValue::Int8(_)) => FortranValue::Int8(t.parse().map_err(|_| bad())?),
+        Some(&FortranValue::Real8(_)) => FortranValue::Real8(t.parse().map_err(|_| bad())?),
+        Some(&FortranValue::Bool(_)) => FortranValue::Bool(match t.to_lowercase().trim_matches('.') {
+            "t" | "true" => true,
+            "f" | "false" => false,
+            _ => return Err(bad()),
+        }),
+        Some(_) => FortranValue::Strin(text.to_string()),
+        None => match (t.parse::<i64>(), t.parse::<f64>()) {
+            (Ok(x), _) => FortranValue::Int8(x),
+            (_, Ok(x)) => FortranValue::Real8(x),
+            _ => FortranValue::Strin(text.to_string()),
+        },
+    })
+}
+
+/// A JSON value as a value of the type of `template`.
+fn parse_json(template: Option<&FortranValue>, json: &Json) -> Result<FortranValue, String> {
+    match *json {
+        Json::Str(ref s) => parse_cell(template.or(Some(&FortranValue::Strin(String::new()))), s),
+        Json::Bool(b) => parse_cell(template, if b { "T" } else { "F" }),
+        Json::Number(x) => match template {
+            Some(&FortranValue::Real8(_)) => Ok(FortranValue::Real8(x)),
+            _ => parse_cell(template, &json.to_string()),
+        },
+        Json::Null => match template {
+            Some(&FortranValue::Real8(_)) => Ok(FortranValue::Real8(f64::NAN)),
+            _ => Err("cannot write null".to_string()),
+        },
+        ref x => Err(format!("cannot write {}", x)),
+    }
+}
+
+/// Write a row of values as one statement of the format, compiled
+/// once into `program` for all the rows.
+fn write_row(out: &mut dyn Write, opts: &Options, program: &FormatProgram,
+             values: &[FortranValue]) -> Result<(), String> {
+    let config = WriterConfig { strict: opts.strict, ..WriterConfig::default() };
+    let mut record = vec![];
+    let mut writer = FortranIterWriter::from_program(program, config);
+    for value in values {
+        writer.write_constants(&mut record, true)
+            .and_then(|_| writer.write_value(&mut record, value))
+            .map_err(|e| format!("{:?}", e))?;
+    }
+    writer.write_constants(&mut record, false).map_err(|e| format!("{:?}", e))?;
+    out.write_all(&record).map_err(|e| e.to_string())
+}
+
+/// The types of the data descriptors in the order the writer takes
+/// them, reverting at the end of the format as it does, and the
+/// program the rows are written with.
+struct Templates {
+    program: FormatProgram,
+    cursor: ProgramCursor,
+    values: Vec<FortranValue>,
+}
+
+impl Templates {
+    fn new(fmt: &FormatNode) -> Templates {
+        let program = FormatProgram::compile(fmt);
+        Templates { cursor: program.cursor(), program, values: vec![] }
+    }
+
+    /// The templates of the first `n` items, fewer if the format
+    /// runs out of data descriptors.
+    fn first(&mut self, n: usize) -> &[FortranValue] {
+        let mut reverted = None;
+        while self.values.len() < n {
+            match self.cursor.next(&self.program) {
+                Some(node) => self.values.extend(FortranValue::for_edit(node)),
+                // a pass without data descriptors would repeat forever
+                None if reverted == Some(self.values.len()) => break,
+                None => {
+                    reverted = Some(self.values.len());
+                    self.cursor.revert(&self.program);
+                },
+            }
+        }
+        &self.values[..n.min(self.values.len())]
+    }
+}
+
+/// Read the table, writing a statement of the format per row.
+fn from_table<R: BufRead>(input: &mut R, out: &mut dyn Write, opts: &Options, table: Table) -> io::Result<bool> {
+    let mut templates = Templates::new(&opts.format);
+    let mut names = opts.names.clone();
+    let mut sound = true;
+    let mut line = 0;
+    if table == Table::Csv {
+        line += 1;
+        match csv_row(input)? {
+            Some(header) if names.is_empty() => names = header,
+            _ => {},
+        }
+    }
+    loop {
+        line += 1;
+        let values = match table {
+            Table::Csv => match csv_row(input)? {
+                Some(cells) => {
+                    let templates = templates.first(cells.len());
+                    cells.iter().enumerate()
+                        .map(|(i, c)| parse_cell(templates.get(i), c))
+                        .collect::<Result<Vec<_>, String>>()
+                },
+                None => break,
+            },
+            Table::JsonLines => {
+                let mut text = String::new();
+                if input.read_line(&mut text)? == 0 {
+                    break;
+                }
+                if text.trim().is_empty() {
+                    continue;
+                }
+                json_row(&text, &names, &mut templates)
+            },
+        };
+        if let Err(e) = values.and_then(|values| write_row(out, opts, &templates.program, &values)) {
+            eprintln!("line {}: {}", line, e);
+            sound = false;
+        }
+    }
+    Ok(sound)
+}
+
+/// The values of a JSON line, either an array or an object whose
+/// members are taken in the order of `names`, or their own order.
+fn json_row(text: &str, names: &[String], templates: &mut Templates) -> Result<Vec<FortranValue>, String> {
+    let json = Json::parse(text).map_err(|e| format!("{:?}", e))?;
+    let cells: Vec<&Json> = match json {
+        Json::Array(ref v) => v.iter().collect(),
+        Json::Object(ref members) if names.is_empty() => members.iter().map(|m| &m.1).collect(),
+        Json::Object(_) => names.iter()
+            .map(|n| json.get(n).ok_or_else(|| format!("no member {}", n)))
+            .collect::<Result<_, _>>()?,
+        ref x => return Err(format!("expected an object or an array, got {}", x)),
+    };
+    let templates = templates.first(cells.len());
+    cells.iter().enumerate().map(|(i, c)| parse_json(templates.get(i), c)).collect()
+}
+
+fn run(opts: &Options) -> io::Result<bool> {
+    let stdin = io::stdin();
+    let mut input: Box<dyn BufRead> = match opts.path {
+        Some(ref path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(stdin.lock()),
+    };
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let sound = match opts.direction {
+        Direction::To(table) => to_table(&mut input, &mut out, opts, table)?,
+        Direction::From(table) => from_table(&mut input, &mut out, opts, table)?,
+    };
+    out.flush()?;
+    Ok(sound)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        },
+    };
+    match run(&opts) {
+        Ok(true) => {},
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_table, parse_args, to_table, Direction, Options, Table};
+
+    fn options(args: &[&str]) -> Options {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        parse_args(&args).unwrap()
+    }
+
+    fn convert(opts: &Options, input: &str) -> (bool, String) {
+        let mut out = vec![];
+        let sound = match opts.direction {
+            Direction::To(table) => to_table(&mut input.as_bytes(), &mut out, opts, table),
+            Direction::From(table) => from_table(&mut input.as_bytes(), &mut out, opts, table),
+        };
+        (sound.unwrap(), String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn csv() {
+        let text = "   1  2.50 T a,b\n  -2 -0.25 F \"q\"\n";
+        let opts = options(&["--names", "n,x", "(I4, F6.2, L2, 1X, A3)"]);
+        let (sound, csv) = convert(&opts, text);
+        assert!(sound);
+        assert_eq!(csv, "n,x,c3,c4\n1,2.5,true,\"a,b\"\n-2,-0.25,false,\"\"\"q\"\"\"\n");
+
+        let opts = options(&["--from", "csv", "(I4, F6.2, L2, 1X, A3)"]);
+        assert_eq!(convert(&opts, &csv), (true, text.to_string()));
+
+        let (sound, out) = convert(&opts, "n,x\nx,1\n3,1\n");
+        assert!(!sound);
+        assert_eq!(out, "   3  1.00\n");
+    }
+
+    #[test]
+    fn json_lines() {
+        let text = "  1  2.5abc\n 10 -1.0   \n";
+        let opts = options(&["--to", "jsonl", "--names", "i,x,s", "(I3, F5.1, A3)"]);
+        let (_, jsonl) = convert(&opts, text);
+        assert_eq!(jsonl, "{\"i\":1,\"x\":2.5,\"s\":\"abc\"}\n{\"i\":10,\"x\":-1.0,\"s\":\"   \"}\n");
+
+        let opts = options(&["--from", "jsonl", "--names", "i,x,s", "(I3, F5.1, A3)"]);
+        assert_eq!(convert(&opts, &jsonl), (true, text.to_string()));
+        let (_, out) = convert(&opts, "{\"s\": \"z\", \"x\": 3, \"i\": 4}\n[5, 0.5, \"y\"]\n");
+        assert_eq!(out, "  4  3.0  z\n  5  0.5  y\n");
+
+        // repeats and reversion give the types of later items
+        let opts = options(&["--from", "jsonl", "(A2, 2(I2, F4.1))"]);
+        let (sound, out) = convert(&opts, "[\"ab\", 1, 2, 3, 4, 5, 6]\n");
+        assert!(sound);
+        assert_eq!(out, "ab 1 2.0 3 4.0\n 5 6.0\n");
+    }
+
+    #[test]
+    fn arguments() {
+        let args = |a: &[&str]| parse_args(&a.iter().map(|s| s.to_string()).collect::<Vec<_>>()).err();
+        assert!(args(&[]).is_some());
+        assert!(args(&["--to", "xml", "(I3)"]).is_some());
+        assert!(args(&["(I3"]).is_some());
+        assert_eq!(options(&["--from", "jsonl", "(I3)", "-"]).direction, Direction::From(Table::JsonLines));
+    }
+}
@@ -0,0 +1,374 @@
+//! Walks the records of an unformatted sequential file
+//!
+//! Prints the index, offset and length of every record, flags markers
+//! that do not match or records cut short, and decodes the records with
+//! a type or a schema when given one. The byte order and the size of
+//! the record markers are detected unless given.
+
+extern crate f77_io;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::process;
+use f77_io::decl::parse_type;
+use f77_io::schema::{Decoder, Schema};
+use f77_io::stream::{Endian, FortranBinary};
+use f77_io::types::{FortranTag, FortranType};
+use f77_io::value::FortranValue;
+
+const USAGE: &str = "usage: f77dump [--endian little|big] [--marker 4|8] \
+                     [--type TYPE | --schema FILE] [--max N] FILE";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Framing {
+    endian: Endian,
+    marker: usize,
+}
+
+#[derive(Default)]
+struct Options {
+    endian: Option<Endian>,
+    marker: Option<usize>,
+    tag: Option<FortranTag>,
+    schema: Option<Schema>,
+    max: usize,
+    path: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options { max: 8, ..Options::default() };
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--endian" => opts.endian = Some(match value()?.as_str() {
+                "little" => Endian::Little,
+                "big" => Endian::Big,
+                x => return Err(format!("bad byte order: {}", x)),
+            }),
+            "--marker" => opts.marker = Some(match value()?.as_str() {
+                "4" => 4,
+                "8" => 8,
+                x => return Err(format!("bad marker size: {}", x)),
+            }),
+            "--type" => {
+                let ty = value()?;
+                opts.tag = Some(parse_type(ty).map_err(|e| format!("bad type {}: {:?}", ty, e))?);
+            },
+            "--schema" => {
+                let path = value()?;
+                let src = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+                opts.schema = Some(Schema::parse(&src).map_err(|e| format!("{}: {:?}", path, e))?);
+            },
+            "--max" => opts.max = value()?.parse().map_err(|_| "bad --max".to_string())?,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ if opts.path.is_none() => opts.path = Some(arg.clone()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    if opts.tag.is_some() && opts.schema.is_some() {
+        return Err("--type and --schema cannot be used together".to_string());
+    }
+    Ok(opts)
+}
+
+/// A record marker, `None` at the end of the file.
+fn read_marker(r: &mut dyn Read, framing: Framing) -> io::Result<Option<i64>> {
+    let mut bytes = [0u8; 8];
+    let bytes = &mut bytes[..framing.marker];
+    let mut read = 0;
+    while read < bytes.len() {
+        match r.read(&mut bytes[read..])? {
+            0 if read == 0 => return Ok(None),
+            0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "marker cut short")),
+            n => read += n,
+        }
+    }
+    let value = match (framing.marker, framing.endian) {
+        (4, Endian::Little) => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+        (4, Endian::Big) => i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+        (_, Endian::Little) => i64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3],
+                                                   bytes[4], bytes[5], bytes[6], bytes[7]]),
+        (_, Endian::Big) => i64::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3],
+                                                bytes[4], bytes[5], bytes[6], bytes[7]]),
+    };
+    Ok(Some(value))
+}
+
+/// How many of the first records of `r`, `len` bytes long, are framed
+/// by `framing`: their markers match and they fit in the file.
+fn fits<R: Read + Seek>(r: &mut R, len: u64, framing: Framing) -> io::Result<usize> {
+    r.seek(SeekFrom::Start(0))?;
+    let (mut pos, mut records) = (0u64, 0);
+    while records < 3 {
+        let head = match read_marker(r, framing) {
+            Ok(Some(head)) => head.unsigned_abs(),
+            _ => break,
+        };
+        if pos + 2 * framing.marker as u64 + head > len {
+            break;
+        }
+        r.seek(SeekFrom::Current(head as i64))?;
+        match read_marker(r, framing)? {
+            Some(tail) if tail.unsigned_abs() == head => {},
+            _ => break,
+        }
+        pos += 2 * framing.marker as u64 + head;
+        records += 1;
+    }
+    Ok(records)
+}
+
+/// The framing allowed by the options that fits the most records.
+fn detect<R: Read + Seek>(r: &mut R, len: u64, opts: &Options) -> io::Result<Option<Framing>> {
+    let mut best = (0, None);
+    for &marker in [4, 8].iter() {
+        for &endian in [Endian::Little, Endian::Big].iter() {
+            if opts.marker.is_some_and(|m| m != marker) || opts.endian.is_some_and(|e| e != endian) {
+                continue;
+            }
+            let framing = Framing { endian, marker };
+            let records = fits(r, len, framing)?;
+            if records > best.0 {
+                best = (records, Some(framing));
+            }
+        }
+    }
+    Ok(best.1)
+}
+
+/// `value` with arrays cut to `max` elements.
+fn show(value: &FortranValue, max: usize) -> String {
+    match *value {
+        FortranValue::Array(ref v) if v.len() > max => {
+            let head: Vec<String> = v[..max].iter().map(|x| x.to_string()).collect();
+            format!("[{}, ... {} values]", head.join(", "), v.len())
+        },
+        ref x => x.to_string(),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Print the payload decoded as an array of `tag`, returning whether
+/// it could be decoded.
+fn show_typed(out: &mut dyn Write, data: &[u8], tag: FortranTag, endian: Endian, max: usize) -> io::Result<bool> {
+    let size = tag.size().unwrap_or(1).max(1);
+    let count = data.len() / size;
+    let mut value = FortranValue::for_type(&FortranType { tag, dim: Some(vec![count]) });
+    if let Err(e) = value.read_binary(&mut &data[..count * size], endian) {
+        writeln!(out, "    decoding failed: {}", e)?;
+        return Ok(false);
+    }
+    write!(out, "    {}", show(&value, max))?;
+    if !data.len().is_multiple_of(size) {
+        write!(out, " + {} bytes", data.len() % size)?;
+    }
+    writeln!(out)?;
+    Ok(true)
+}
+
+/// Write a line per record of `r` to `out`, returning whether the file
+/// is sound.
+fn dump<R: Read + Seek>(r: &mut R, len: u64, opts: &Options, out: &mut dyn Write) -> io::Result<bool> {
+    if len == 0 {
+        writeln!(out, "empty file")?;
+        return Ok(true);
+    }
+    let framing = match detect(r, len, opts)? {
+        Some(framing) => framing,
+        None => {
+            writeln!(out, "no record markers found, try --endian and --marker")?;
+            return Ok(false);
+        },
+    };
+    writeln!(out, "{:?}-endian, {}-byte markers", framing.endian, framing.marker)?;
+    let mut decoder: Option<Decoder> = opts.schema.as_ref().map(|s| s.decoder());
+    let decode = opts.tag.is_some() || decoder.is_some();
+    let m = framing.marker as u64;
+    let mut sound = true;
+
+    r.seek(SeekFrom::Start(0))?;
+    let (mut pos, mut index) = (0u64, 0);
+    loop {
+        let head = match read_marker(r, framing) {
+            Ok(Some(head)) => head,
+            Ok(None) => break,
+            Err(_) => {
+                writeln!(out, "#{} offset {}: marker cut short", index, pos)?;
+                sound = false;
+                break;
+            },
+        };
+        let n = head.unsigned_abs();
+        if pos + 2 * m + n > len {
+            writeln!(out, "#{} offset {} length {}: TRUNCATED, {} bytes left",
+                     index, pos, n, len - pos - m)?;
+            sound = false;
+            break;
+        }
+        let preview = if decode { n } else { n.min(16) };
+        let mut data = vec![0; preview as usize];
+        r.read_exact(&mut data)?;
+        r.seek(SeekFrom::Current((n - preview) as i64))?;
+        let tail = read_marker(r, framing)?.unwrap_or(0);
+
+        write!(out, "#{} offset {} length {}", index, pos, n)?;
+        if head < 0 {
+            write!(out, " (continued)")?;
+        }
+        if tail.unsigned_abs() != n {
+            writeln!(out, " MISMATCH: tail marker {}", tail)?;
+            sound = false;
+            break;
+        }
+        if !decode {
+            let more = if n > preview { " ..." } else { "" };
+            writeln!(out, "  {}{}", hex(&data), more)?;
+        } else {
+            writeln!(out)?;
+        }
+
+        if let Some(tag) = opts.tag {
+            if !show_typed(out, &data, tag, framing.endian, opts.max)? {
+                sound = false;
+            }
+        }
+        if let Some(ref mut decoder) = decoder {
+            match decoder.is_done() {
+                Ok(true) => writeln!(out, "    past the end of the schema: {}", hex(&data[..data.len().min(16)]))?,
+                _ => match decoder.decode(&data) {
+                    Ok(record) => {
+                        writeln!(out, "    {}", record.name)?;
+                        for (name, value) in record.fields.iter() {
+                            writeln!(out, "      {} = {}", name, show(value, opts.max))?;
+                        }
+                    },
+                    Err(e) => {
+                        writeln!(out, "    decoding failed: {:?}", e)?;
+                        sound = false;
+                    },
+                },
+            }
+        }
+        pos += 2 * m + n;
+        index += 1;
+    }
+    if let Some(ref mut decoder) = decoder {
+        if sound {
+            if let Err(e) = decoder.finish() {
+                writeln!(out, "file ended early: {:?}", e)?;
+                sound = false;
+            }
+        }
+    }
+    writeln!(out, "{} records, {} bytes", index, pos)?;
+    Ok(sound)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+        Ok(ref opts) if opts.path.is_none() => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        },
+    };
+    let path = opts.path.clone().unwrap();
+    let result = File::open(&path).and_then(|file| {
+        let len = file.metadata()?.len();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        dump(&mut BufReader::new(file), len, &opts, &mut out)
+    });
+    match result {
+        Ok(true) => {},
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(2);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use f77_io::schema::Schema;
+    use f77_io::stream::Endian;
+    use f77_io::types::FortranTag;
+    use super::{dump, Options};
+
+    fn framed(records: &[&[u8]], marker: usize, endian: Endian) -> Vec<u8> {
+        let mut file = vec![];
+        for r in records {
+            let len = r.len() as u64;
+            let bytes = match endian {
+                Endian::Little => len.to_le_bytes()[..marker].to_vec(),
+                Endian::Big => len.to_be_bytes()[8 - marker..].to_vec(),
+            };
+            file.extend(&bytes);
+            file.extend(*r);
+            file.extend(&bytes);
+        }
+        file
+    }
+
+    fn run(file: &[u8], opts: &Options) -> (bool, String) {
+        let mut out = vec![];
+        let sound = dump(&mut Cursor::new(file), file.len() as u64, opts, &mut out).unwrap();
+        (sound, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn walk() {
+        let file = framed(&[&[1, 0, 0, 0, 2, 0, 0, 0], &[]], 8, Endian::Big);
+        let (sound, out) = run(&file, &Options { max: 8, ..Options::default() });
+        assert!(sound);
+        assert_eq!(out, "Big-endian, 8-byte markers\n\
+                         #0 offset 0 length 8  01 00 00 00 02 00 00 00\n\
+                         #1 offset 24 length 0  \n\
+                         2 records, 40 bytes\n");
+
+        let opts = Options { max: 1, tag: Some(FortranTag::Int4), endian: Some(Endian::Big), ..Options::default() };
+        let (_, out) = run(&file, &opts);
+        assert!(out.contains("#0 offset 0 length 8\n    [16777216, ... 2 values]\n"));
+
+        let mut file = framed(&[&[1, 2, 3], &[4, 5]], 4, Endian::Little);
+        let last = file.len() - 4;
+        file[last] = 9;
+        let (sound, out) = run(&file, &Options::default());
+        assert!(!sound);
+        assert!(out.contains("#1 offset 11 length 2 MISMATCH: tail marker 9\n"));
+        file.truncate(last);
+        let (_, out) = run(&file, &Options::default());
+        assert!(out.contains("#1 offset 11 length 2: TRUNCATED, 2 bytes left\n"));
+
+        let file = framed(&[b"caf\xe9", b"ok  "], 4, Endian::Little);
+        let opts = Options { tag: Some(FortranTag::Char(4)), max: 8, ..Options::default() };
+        let (sound, out) = run(&file, &opts);
+        assert!(sound);
+        assert!(out.contains("#0 offset 0 length 4\n    ['caf\u{e9}']\n#1 offset 12 length 4\n    ['ok  ']\n"));
+    }
+
+    #[test]
+    fn schema() {
+        let schema = Schema::parse(r#"{"records": [{"name": "head", "fields": ["INTEGER N"]},
+            {"name": "data", "repeat": "n", "fields": ["INTEGER*2 X(2)"]}]}"#).unwrap();
+        let file = framed(&[&[2, 0, 0, 0], &[1, 0, 2, 0]], 4, Endian::Little);
+        let (sound, out) = run(&file, &Options { schema: Some(schema), max: 8, ..Options::default() });
+        assert!(!sound);
+        assert!(out.contains("    head\n      N = 2\n"));
+        assert!(out.contains("    data\n      X = [1, 2]\n"));
+        assert!(out.contains("file ended early: EndOfFile(\"data\")"));
+    }
+}
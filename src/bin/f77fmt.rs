@@ -0,0 +1,401 @@
+//! Checks format specifications and prints them canonically
+//!
+//! Reads a format per line, either alone, as in `(1X, I5)`, or as a
+//! FORMAT statement, as in `100 FORMAT(1X,I5)`. Each line is printed
+//! back with the format written canonically, and warnings about the
+//! format are reported on the standard error. Other lines are printed
+//! as they are. A FORMAT statement continued in column 6 of the next
+//! lines, as in fixed form sources, is checked as a whole and printed
+//! as it is.
+
+extern crate f77_io;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
+use f77_io::decl::parse_type;
+use f77_io::format::{FormatNode, FormatParser, IntFormat, ParseError, RealFormat};
+use f77_io::source::{FormatTable, SourceError, SourceForm};
+use f77_io::types::FortranTag;
+
+const USAGE: &str = "usage: f77fmt [--check] [--input | --output] [--type TYPE]... [FILE]...";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Direction {
+    Any,
+    Input,
+    Output,
+}
+
+struct Options {
+    check: bool,
+    direction: Direction,
+    /// The types the items are declared with, as given and parsed.
+    types: Vec<(String, FortranTag)>,
+    paths: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options { check: false, direction: Direction::Any, types: vec![], paths: vec![] };
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--check" => opts.check = true,
+            "--input" => opts.direction = Direction::Input,
+            "--output" => opts.direction = Direction::Output,
+            "--type" => {
+                let ty = it.next().ok_or("--type needs a value")?;
+                let tag = parse_type(ty).map_err(|e| format!("bad type {}: {:?}", ty, e))?;
+                opts.types.push((ty.to_uppercase(), tag));
+            },
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("unknown option {}\n{}", arg, USAGE));
+            },
+            _ => opts.paths.push(arg.clone()),
+        }
+    }
+    Ok(opts)
+}
+
+#[derive(Debug, PartialEq)]
+struct Diagnostic {
+    /// Column of the line, counted from one.
+    column: usize,
+    error: bool,
+    message: String,
+}
+
+impl Diagnostic {
+    fn warning(column: usize, message: String) -> Diagnostic {
+        Diagnostic { column, error: false, message }
+    }
+}
+
+/// Visit the edit descriptors of `node` once each, however often
+/// they are repeated.
+fn walk<F: FnMut(&FormatNode)>(node: &FormatNode, f: &mut F) {
+    match *node {
+        FormatNode::Group(ref v) => v.iter().for_each(|n| walk(n, f)),
+        FormatNode::Repeat(_, ref n) | FormatNode::UnlimitedRepeat(ref n) => walk(n, f),
+        ref n => f(n),
+    }
+}
+
+/// Characters needed to write any integer of type `tag` with `t`.
+fn int_width(t: IntFormat, tag: FortranTag) -> usize {
+    let bits = tag.size().unwrap_or(0) * 8;
+    let signed = !matches!(tag, FortranTag::Uint2 | FortranTag::Uint4 | FortranTag::Uint8);
+    match t {
+        IntFormat::I => {
+            // digits of the largest magnitude, with a sign for negatives
+            let max = if signed { 1u128 << (bits - 1) } else { (1u128 << bits) - 1 };
+            max.to_string().len() + signed as usize
+        },
+        IntFormat::B => bits,
+        IntFormat::O => bits.div_ceil(3),
+        IntFormat::Z => bits / 4,
+    }
+}
+
+/// Digits needed by the largest exponent of reals of type `tag`.
+fn exponent_digits(tag: FortranTag) -> usize {
+    match tag {
+        FortranTag::Real8 | FortranTag::Complex8 => 3,
+        _ => 2,
+    }
+}
+
+/// Warnings about the edit descriptor `node` of a format.
+fn lint_node(node: &FormatNode, opts: &Options, warn: &mut dyn FnMut(String)) {
+    use f77_io::format::FormatNode::*;
+    match *node {
+        Int(t, w, m) if w > 0 => {
+            if m.is_some_and(|m| m > w) {
+                warn(format!("{} has more digits than its width", node));
+            }
+            for &(ref name, tag) in opts.types.iter().filter(|t| t.1.is_integer()) {
+                let needed = int_width(t, tag);
+                if w < needed {
+                    warn(format!("{} is too narrow for {}, which needs {} characters", node, name, needed));
+                }
+            }
+        },
+        Real(t, w, Some(d), oe) if w > 0 && t != RealFormat::EX => {
+            // a sign and the point, with E+xx for the E forms
+            let e = oe.unwrap_or(2);
+            let needed = match t {
+                RealFormat::F => d + 2,
+                RealFormat::ES => d + e + 5,
+                RealFormat::EN => d + e + 7,
+                _ => d + e + 4,
+            };
+            if w < needed {
+                warn(format!("{} is too narrow for {} digits, it needs {} characters", node, d, needed));
+            }
+            if t != RealFormat::F {
+                for &(ref name, tag) in opts.types.iter().filter(|t| t.1.is_real() || t.1.is_complex()) {
+                    if oe.is_some_and(|e| e < exponent_digits(tag)) {
+                        warn(format!("the exponent of {} is too narrow for {}", node, name));
+                    }
+                }
+            }
+        },
+        RemainingChars if opts.direction == Direction::Output => warn("Q in an output format".to_string()),
+        SuppressNewLine if opts.direction == Direction::Output => warn("$ in an output format".to_string()),
+        Literal(_) | Hollerith(_) if opts.direction == Direction::Input => {
+            warn(format!("{} in an input format", node));
+        },
+        _ => {},
+    }
+}
+
+/// A line split into its label, whether it has the FORMAT keyword,
+/// and the byte offset where the format specification starts.
+fn split_statement(line: &str) -> (Option<&str>, bool, usize) {
+    let rest = line.trim_start();
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let label = if digits > 0 { Some(&rest[..digits]) } else { None };
+    let rest = rest[digits..].trim_start();
+    let keyword = rest.get(..6).is_some_and(|k| k.eq_ignore_ascii_case("format"));
+    let rest = if keyword { rest[6..].trim_start() } else { rest };
+    (label, keyword, line.len() - rest.len())
+}
+
+/// The line with its format written canonically, and what was found
+/// wrong with it. Lines holding no format are kept as they are.
+fn lint_line(line: &str, opts: &Options) -> (String, Vec<Diagnostic>) {
+    let (label, keyword, start) = split_statement(line);
+    let spec = &line[start..];
+    if !keyword && !spec.starts_with('(') {
+        return (line.to_string(), vec![]);
+    }
+    let column = line[..start].chars().count() + 1;
+    let mut it = spec.chars().peekable();
+    let mut parser = FormatParser::new(&mut it);
+    parser.set_lenient(true);
+    let parsed = parser.parse();
+    let consumed = parser.position();
+    let missing = parser.missing_commas().to_vec();
+    let node = match parsed {
+        Ok(node) => node,
+        Err(e) => {
+            let message = format!("{:?}", e);
+            return (line.to_string(), vec![Diagnostic { column: column + e.position(), error: true, message }]);
+        },
+    };
+    let rest: String = spec.chars().skip(consumed).collect();
+    let trailing = rest.trim_start();
+    if !trailing.is_empty() && !trailing.starts_with('!') {
+        let at = column + consumed + rest.chars().count() - trailing.chars().count();
+        let message = format!("unexpected text after the format: {}", trailing.trim_end());
+        return (line.to_string(), vec![Diagnostic { column: at, error: true, message }]);
+    }
+
+    let mut diagnostics: Vec<Diagnostic> = missing.iter()
+        .map(|&p| Diagnostic::warning(column + p, "missing comma, accepted as an extension".to_string()))
+        .collect();
+    walk(&node, &mut |n| lint_node(n, opts, &mut |m| diagnostics.push(Diagnostic::warning(column, m))));
+
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let mut out = indent.to_string();
+    if let Some(label) = label {
+        out.push_str(label);
+        out.push(' ');
+    }
+    if keyword {
+        out.push_str("FORMAT");
+    }
+    out.push_str(&node.to_string());
+    if !trailing.is_empty() {
+        out.push(' ');
+        out.push_str(trailing.trim_end());
+    }
+    (out, diagnostics)
+}
+
+/// Whether `line` starts a FORMAT statement whose format goes on
+/// past the end of the line.
+fn format_goes_on(line: &str) -> bool {
+    let (_, keyword, start) = split_statement(line);
+    let spec = line[start..].trim_end();
+    if !keyword || spec.is_empty() {
+        return keyword;
+    }
+    let mut it = spec.chars().peekable();
+    let mut parser = FormatParser::new(&mut it);
+    parser.set_lenient(true);
+    matches!(parser.parse(), Err(ParseError::UnexpectedEOF(_)))
+}
+
+/// Whether `line` continues the statement before it in fixed form,
+/// having a character other than a blank or a zero in column 6.
+fn is_continuation(line: &str) -> bool {
+    let mut chars = line.chars();
+    chars.by_ref().take(5).all(|c| c == ' ') && chars.next().is_some_and(|c| c != ' ' && c != '0')
+}
+
+/// What was found wrong with a FORMAT statement continued on the lines
+/// after the first, read as `FormatTable` reads fixed form sources,
+/// each with the index of its line among `lines`.
+fn lint_statement(lines: &[String], opts: &Options) -> Vec<(usize, Diagnostic)> {
+    let table = FormatTable::parse(&lines.join("\n"), SourceForm::Fixed);
+    let mut diagnostics = vec![];
+    for e in table.errors() {
+        let (span, message) = match *e {
+            SourceError::Parse(_, span, ref e) => (span, format!("{:?}", e)),
+            SourceError::Trailing(_, span) => (span, "unexpected text after the format".to_string()),
+            SourceError::IoErr(_) | SourceError::DuplicateLabel(_, _) => continue,
+        };
+        diagnostics.push((span.line - 1, Diagnostic { column: span.column, error: true, message }));
+    }
+    for stmt in table.statements() {
+        let span = stmt.span;
+        walk(&stmt.format, &mut |n| {
+            lint_node(n, opts, &mut |m| diagnostics.push((span.line - 1, Diagnostic::warning(span.column, m))))
+        });
+    }
+    diagnostics
+}
+
+/// Check the lines of `input`, returning the number of diagnostics.
+fn lint<R: BufRead>(input: R, name: &str, opts: &Options, out: &mut dyn Write) -> io::Result<usize> {
+    let lines = input.lines().collect::<io::Result<Vec<String>>>()?;
+    let mut count = 0;
+    let mut i = 0;
+    while i < lines.len() {
+        let mut end = i + 1;
+        if format_goes_on(&lines[i]) {
+            while end < lines.len() && is_continuation(&lines[end]) {
+                end += 1;
+            }
+        }
+        let (canonical, diagnostics) = if end > i + 1 {
+            (lines[i..end].join("\n"), lint_statement(&lines[i..end], opts))
+        } else {
+            let (canonical, diagnostics) = lint_line(&lines[i], opts);
+            (canonical, diagnostics.into_iter().map(|d| (0, d)).collect())
+        };
+        for &(line, ref d) in diagnostics.iter() {
+            let level = if d.error { "error" } else { "warning" };
+            eprintln!("{}:{}:{}: {}: {}", name, i + line + 1, d.column, level, d.message);
+        }
+        count += diagnostics.len();
+        if !opts.check {
+            writeln!(out, "{}", canonical)?;
+        }
+        i = end;
+    }
+    Ok(count)
+}
+
+fn run(opts: &Options) -> io::Result<usize> {
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let mut count = 0;
+    if opts.paths.is_empty() {
+        let stdin = io::stdin();
+        count += lint(stdin.lock(), "<stdin>", opts, &mut out)?;
+    }
+    for path in opts.paths.iter() {
+        let file = File::open(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+        count += lint(BufReader::new(file), path, opts, &mut out)?;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        },
+    };
+    match run(&opts) {
+        Ok(0) => {},
+        Ok(_) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, lint_line, lint_statement, parse_args, Diagnostic, Options};
+
+    fn options(args: &[&str]) -> Options {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        parse_args(&args).unwrap()
+    }
+
+    fn messages(line: &str, opts: &Options) -> Vec<String> {
+        lint_line(line, opts).1.into_iter().map(|d| d.message).collect()
+    }
+
+    #[test]
+    fn canonical() {
+        let opts = options(&[]);
+        assert_eq!(lint_line("  100 format (1X,i5,  1PE12.4)", &opts),
+                   ("  100 FORMAT(1X, I5, 1P, E12.4)".to_string(), vec![]));
+        assert_eq!(lint_line("(a10/ f8.3) ! header", &opts).0, "(A10/F8.3) ! header");
+        assert_eq!(lint_line("      CALL FOO", &opts).0, "      CALL FOO");
+        assert_eq!(lint_line("", &opts), (String::new(), vec![]));
+        // labeled statements other than FORMAT
+        assert_eq!(lint_line("   10 CONTINUE", &opts), ("   10 CONTINUE".to_string(), vec![]));
+        assert_eq!(lint_line("   20 FORMAT ! nothing", &opts).1.len(), 1);
+    }
+
+    #[test]
+    fn continued() {
+        let opts = options(&["--type", "integer*4"]);
+        let src = "  100 FORMAT(1X, I5,\n     &       F10.3)\n  200 FORMAT(I5,\n     &  W3)\n      (i2)\n";
+        let mut out = vec![];
+        // I5 too narrow, W3 unexpected, I2 too narrow
+        assert_eq!(lint(src.as_bytes(), "test", &opts, &mut out).unwrap(), 3);
+        assert_eq!(String::from_utf8(out).unwrap(), src.replace("(i2)", "(I2)"));
+
+        let lines: Vec<String> = src.lines().skip(2).take(2).map(|s| s.to_string()).collect();
+        let diagnostics = lint_statement(&lines, &options(&[]));
+        assert_eq!(diagnostics, vec![(1, Diagnostic { column: 9, error: true,
+                                                      message: "UnexpectedChar(7, 'W')".to_string() })]);
+    }
+
+    #[test]
+    fn errors() {
+        let opts = options(&[]);
+        let error = |column, message: &str| vec![Diagnostic { column, error: true, message: message.to_string() }];
+        assert_eq!(lint_line("10 FORMAT(I5, W3)", &opts).1, error(15, "UnexpectedChar(6, 'W')"));
+        assert_eq!(lint_line("10 FORMAT(I5) X", &opts).1, error(15, "unexpected text after the format: X"));
+        assert_eq!(lint_line("FORMA\u{e9}(I5)", &opts).1, vec![]);
+        assert_eq!(lint_line("10 FORMAT('\u{e9}', W3)", &opts).1, error(16, "UnexpectedChar(7, 'W')"));
+    }
+
+    #[test]
+    fn warnings() {
+        let opts = options(&["--type", "integer*4", "--type", "real*8"]);
+        assert_eq!(messages("(I3, I11, Z8, O10, F5.1, E8.6, E12.4E2, 2X'a'I5)", &opts), vec![
+            "missing comma, accepted as an extension",
+            "missing comma, accepted as an extension",
+            "I3 is too narrow for INTEGER*4, which needs 11 characters",
+            "O10 is too narrow for INTEGER*4, which needs 11 characters",
+            "E8.6 is too narrow for 6 digits, it needs 12 characters",
+            "the exponent of E12.4E2 is too narrow for REAL*8",
+            "I5 is too narrow for INTEGER*4, which needs 11 characters",
+        ]);
+        let missing = lint_line("(I5F10.3)", &options(&[])).1;
+        assert_eq!(missing[0].column, 4);
+        assert!(messages("(1P5E14.6, 2P3F8.2)", &options(&[])).is_empty());
+
+        let output = options(&["--output"]);
+        assert_eq!(messages("(Q, A, $)", &output), vec!["Q in an output format", "$ in an output format"]);
+        let input = options(&["--input"]);
+        assert_eq!(messages("('x=', I5, 2Hab)", &input), vec!["'x=' in an input format", "2Hab in an input format"]);
+        assert!(messages("(Q, 'x=', $)", &options(&[])).is_empty());
+    }
+}
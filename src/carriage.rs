@@ -0,0 +1,150 @@
+//! Carriage control for line-printer output
+//!
+//! Records written for a line printer start with a control character:
+//! blank to print on the next line, `0` to skip a line first, `1` to
+//! start a new page and `+` to print over the previous line. A
+//! `CarriageControl` turns such records into plain text.
+
+use std::io::{self, Write};
+
+/// Writes records with carriage control to `W` as plain lines: the
+/// control character is removed, a new page becomes a form feed and
+/// a double space a blank line. Overprinted records are merged into
+/// the line they print over, their non-blank characters replacing
+/// those of the line. Other control characters act like a blank.
+///
+/// A line is held back until the next one shows it is not overprinted;
+/// `finish`, or dropping the writer, writes the last one.
+pub struct CarriageControl<W: Write> {
+    inner: W,
+    // the record being received, with its control character
+    current: Vec<u8>,
+    // the last complete line, not yet written
+    held: Option<String>,
+}
+
+impl<W: Write> CarriageControl<W> {
+    pub fn new(inner: W) -> CarriageControl<W> {
+        CarriageControl {
+            inner,
+            current: Vec::new(),
+            held: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Write the line held back and any record not ended by a newline.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.current.is_empty() {
+            self.end_record()?;
+        }
+        if let Some(line) = self.held.take() {
+            self.inner.write_all(line.as_bytes())?;
+            self.inner.write_all(b"\n")?;
+        }
+        self.inner.flush()
+    }
+
+    fn end_record(&mut self) -> io::Result<()> {
+        let control = self.current.first().cloned().unwrap_or(b' ');
+        let text = String::from_utf8_lossy(self.current.get(1..).unwrap_or(&[])).into_owned();
+        self.current.clear();
+        if control == b'+' {
+            if let Some(ref mut line) = self.held {
+                *line = overprint(line, &text);
+                return Ok(());
+            }
+        }
+        if let Some(line) = self.held.take() {
+            self.inner.write_all(line.as_bytes())?;
+            self.inner.write_all(b"\n")?;
+        }
+        match control {
+            b'0' => self.inner.write_all(b"\n")?,
+            b'1' => self.inner.write_all(b"\x0c")?,
+            _ => {},
+        }
+        self.held = Some(text);
+        Ok(())
+    }
+}
+
+/// `line` with the non-blank characters of `over` printed over it.
+fn overprint(line: &str, over: &str) -> String {
+    let mut chars: Vec<char> = line.chars().collect();
+    for (i, c) in over.chars().enumerate() {
+        if i >= chars.len() {
+            chars.push(' ');
+        }
+        if c != ' ' {
+            chars[i] = c;
+        }
+    }
+    chars.into_iter().collect()
+}
+
+impl<W: Write> Write for CarriageControl<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &b in buf {
+            if b == b'\n' {
+                self.end_record()?;
+            } else {
+                self.current.push(b);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Flush `W`, the line held back stays until the next record.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for CarriageControl<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::carriage::CarriageControl;
+    use ::format::parse_format;
+    use ::write::FortranIterWriter;
+
+    #[test]
+    fn controls() {
+        let fmt = parse_format("('1', 'TITLE' / '0', I3 / '+', 3X, '_' / I4 / 'x', I3)").unwrap();
+        let mut out = CarriageControl::new(Vec::new());
+        {
+            let mut writer = FortranIterWriter::new(&fmt);
+            writer.write_constants(&mut out, true).unwrap();
+            writer.write_value(&mut out, &1).unwrap();
+            writer.write_constants(&mut out, true).unwrap();
+            writer.write_value(&mut out, &2).unwrap();
+            writer.write_constants(&mut out, true).unwrap();
+            writer.write_value(&mut out, &3).unwrap();
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        // the overprint is only known after the next record
+        assert_eq!(out.get_ref().as_slice(), b"\x0cTITLE\n\n  1_\n  2\n");
+        out.finish().unwrap();
+        assert_eq!(String::from_utf8(out.get_ref().clone()).unwrap(), "\x0cTITLE\n\n  1_\n  2\n  3\n");
+    }
+
+    #[test]
+    fn unterminated() {
+        let mut out = CarriageControl::new(Vec::new());
+        {
+            use std::io::Write;
+            out.write_all(b"+over\n ab\n+ _c\n").unwrap();
+            out.write_all(b"0tail").unwrap();
+        }
+        out.finish().unwrap();
+        assert_eq!(String::from_utf8(out.get_ref().clone()).unwrap(), "over\na_c\n\ntail\n");
+    }
+}
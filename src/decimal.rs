@@ -0,0 +1,357 @@
+//! Decimal digits of reals under the Fortran rounding modes
+//!
+//! Digits are kept in an `EditBuf` with an exponent `x` such that the
+//! value is `0.digits * 10^x`, the sign is handled by the caller.
+
+use std::cmp::{Ordering};
+use std::fmt;
+use format::{RoundType};
+
+const STACK_LEN: usize = 128;
+
+/// Characters of one edited value, kept on the stack unless they
+/// outgrow it, e.g. the exact digits of a real under directed rounding.
+pub struct EditBuf {
+    stack: [u8; STACK_LEN],
+    len: usize,
+    // holds everything once the stack is too small
+    heap: Vec<u8>,
+}
+
+impl Default for EditBuf {
+    fn default() -> EditBuf {
+        EditBuf::new()
+    }
+}
+
+impl EditBuf {
+    pub fn new() -> EditBuf {
+        EditBuf {
+            stack: [0; STACK_LEN],
+            len: 0,
+            heap: Vec::new(),
+        }
+    }
+
+    fn spilled(&self) -> bool {
+        !self.heap.is_empty()
+    }
+
+    fn spill(&mut self) {
+        if !self.spilled() {
+            self.heap.extend_from_slice(&self.stack[..self.len]);
+            self.len = 0;
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.spilled() { &self.heap } else { &self.stack[..self.len] }
+    }
+
+    pub fn as_mut_bytes(&mut self) -> &mut [u8] {
+        if self.spilled() { &mut self.heap } else { &mut self.stack[..self.len] }
+    }
+
+    pub fn as_str(&self) -> &str {
+        ::std::str::from_utf8(self.as_bytes()).unwrap()
+    }
+
+    pub fn len(&self) -> usize {
+        if self.spilled() { self.heap.len() } else { self.len }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.len = 0;
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if self.spilled() {
+            self.heap.truncate(len);
+        } else {
+            self.len = self.len.min(len);
+        }
+    }
+
+    pub fn push_bytes(&mut self, s: &[u8]) {
+        if !self.spilled() && self.len + s.len() <= STACK_LEN {
+            self.stack[self.len..self.len + s.len()].copy_from_slice(s);
+            self.len += s.len();
+        } else {
+            self.spill();
+            self.heap.extend_from_slice(s);
+        }
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        self.push_bytes(s.as_bytes());
+    }
+
+    /// Insert `count` copies of `c` at `pos`.
+    pub fn insert(&mut self, pos: usize, c: u8, count: usize) {
+        if !self.spilled() && self.len + count <= STACK_LEN {
+            self.stack.copy_within(pos..self.len, pos + count);
+            self.stack[pos..pos + count].iter_mut().for_each(|b| *b = c);
+            self.len += count;
+        } else {
+            self.spill();
+            self.heap.splice(pos..pos, ::std::iter::repeat_n(c, count));
+        }
+    }
+
+    pub fn push_repeat(&mut self, c: u8, count: usize) {
+        let len = self.len();
+        self.insert(len, c, count);
+    }
+
+    pub fn remove(&mut self, pos: usize) {
+        if self.spilled() {
+            self.heap.remove(pos);
+        } else {
+            self.stack.copy_within(pos + 1..self.len, pos);
+            self.len -= 1;
+        }
+    }
+
+    /// Append formatted text; writing to an `EditBuf` never fails.
+    pub fn fmt(&mut self, args: fmt::Arguments) {
+        let _ = fmt::Write::write_fmt(self, args);
+    }
+}
+
+impl fmt::Write for EditBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+/// Turn `d.ddde-x` as written by `{:e}` into its digits,
+/// returning the exponent of `0.digits`.
+fn from_scientific(buf: &mut EditBuf) -> isize {
+    let bytes = buf.as_mut_bytes();
+    let e = bytes.iter().position(|&c| c == b'e').unwrap();
+    let x = ::std::str::from_utf8(&bytes[e + 1..]).unwrap().parse::<isize>().unwrap() + 1;
+    let len = if e > 1 && bytes[1] == b'.' {
+        bytes.copy_within(2..e, 1);
+        e - 1
+    } else {
+        e
+    };
+    buf.truncate(len);
+    x
+}
+
+/// Exact decimal digits of a finite, non-negative `val` without trailing
+/// zeros. Zero has no digits.
+pub fn exact_digits(val: f64, out: &mut EditBuf) -> isize {
+    out.clear();
+    if val == 0.0 {
+        return 0;
+    }
+    // no f64 has more than 767 significant digits
+    out.fmt(format_args!("{:.767e}", val));
+    let x = from_scientific(out);
+    let len = out.as_bytes().iter().rposition(|&c| c != b'0').map_or(0, |p| p + 1);
+    out.truncate(len);
+    x
+}
+
+/// Round the exact decimal `0.digits * 10^x` of a value of sign `negative`
+/// to a multiple of `10^q`, in place. The result has `x - q` digits,
+/// the returned exponent grows by one if rounding carries into a new digit.
+pub fn round_digits(digits: &mut EditBuf, x: isize, q: isize, negative: bool, round: RoundType) -> isize {
+    let n = x - q;
+    let bytes = digits.as_bytes();
+    let len = bytes.len() as isize;
+    let tail_nonzero = len > n.max(0);
+    let tail_half = if n < 0 || !tail_nonzero {
+        Ordering::Less
+    } else {
+        match bytes[n as usize].cmp(&b'5') {
+            Ordering::Equal if len > n + 1 => Ordering::Greater,
+            o => o,
+        }
+    };
+    let last_odd = n > 0 && n <= len && bytes[n as usize - 1] % 2 == 1;
+    let up = tail_nonzero && match round {
+        RoundType::RZ => false,
+        RoundType::RU => !negative,
+        RoundType::RD => negative,
+        RoundType::RC => tail_half != Ordering::Less,
+        RoundType::RN | RoundType::RP => {
+            tail_half == Ordering::Greater || (tail_half == Ordering::Equal && last_odd)
+        },
+    };
+
+    if n <= 0 {
+        digits.clear();
+        if up {
+            digits.push_str("1");
+            return q + 1;
+        }
+        return q;
+    }
+
+    let n = n as usize;
+    if digits.len() > n {
+        digits.truncate(n);
+    } else {
+        let missing = n - digits.len();
+        digits.push_repeat(b'0', missing);
+    }
+    if up {
+        let kept = digits.as_mut_bytes();
+        match kept.iter().rposition(|&c| c != b'9') {
+            Some(p) => {
+                kept[p] += 1;
+                kept[p + 1..].fill(b'0');
+            },
+            None => {
+                // 999 becomes 1000
+                kept.fill(b'0');
+                kept[0] = b'1';
+                digits.push_str("0");
+                return x + 1;
+            },
+        }
+    }
+    x
+}
+
+/// A non-negative `val` rounded to `n` significant digits.
+pub fn sig_digits(val: f64, n: usize, negative: bool, round: RoundType, out: &mut EditBuf) -> isize {
+    out.clear();
+    if val == 0.0 {
+        out.push_repeat(b'0', n);
+        return 0;
+    }
+    match round {
+        RoundType::RN | RoundType::RP => {
+            // the standard library rounds exactly, with ties to even
+            out.fmt(format_args!("{:.*e}", n.saturating_sub(1), val));
+            from_scientific(out)
+        },
+        _ => {
+            let x = exact_digits(val, out);
+            let x = round_digits(out, x, x - n as isize, negative, round);
+            // a carry adds a trailing zero
+            out.truncate(n);
+            x
+        },
+    }
+}
+
+/// A non-negative `val` rounded to `d` decimal places, with the decimal
+/// point always written.
+pub fn fixed_digits(val: f64, d: usize, negative: bool, round: RoundType, out: &mut EditBuf) {
+    out.clear();
+    match round {
+        RoundType::RN | RoundType::RP => {
+            out.fmt(format_args!("{:.d$}", val, d=d));
+            if d == 0 {
+                out.push_str(".");
+            }
+        },
+        _ => {
+            let x = exact_digits(val, out);
+            let x = if out.is_empty() {
+                -(d as isize)
+            } else {
+                round_digits(out, x, -(d as isize), negative, round)
+            };
+            if x > 0 {
+                out.insert(x as usize, b'.', 1);
+            } else {
+                out.insert(0, b'0', -x as usize);
+                out.insert(0, b'.', 1);
+                out.insert(0, b'0', 1);
+            }
+        },
+    }
+}
+
+/// Normalize the decimal `mantissa * 10^exp`, where the mantissa
+/// has an optional sign and decimal point, into digits and an exponent.
+pub fn parse_digits(mantissa: &str, exp: isize) -> (String, isize) {
+    let unsigned = mantissa.trim_start_matches(['+', '-']);
+    let (int, frac) = match unsigned.find('.') {
+        Some(p) => (&unsigned[..p], &unsigned[p + 1..]),
+        None => (unsigned, ""),
+    };
+    let all = format!("{}{}", int, frac);
+    let digits = all.trim_start_matches('0');
+    let x = int.len() as isize + exp - (all.len() - digits.len()) as isize;
+    let digits = digits.trim_end_matches('0');
+    if digits.is_empty() {
+        return (String::new(), 0);
+    }
+    (digits.to_string(), x)
+}
+
+/// Compare two non-negative decimals given as digits and exponents.
+pub fn cmp_digits(a: (&str, isize), b: (&str, isize)) -> Ordering {
+    match (a.0.is_empty(), b.0.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::decimal::*;
+    use ::format::RoundType::*;
+
+    fn fixed(val: f64, d: usize, negative: bool, round: ::format::RoundType) -> String {
+        let mut out = EditBuf::new();
+        fixed_digits(val, d, negative, round, &mut out);
+        out.as_str().to_string()
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let cases = [
+            (RU, "2.13", "-2.12"), (RD, "2.12", "-2.13"), (RZ, "2.12", "-2.12"),
+            (RN, "2.12", "-2.12"), (RC, "2.13", "-2.13"), (RP, "2.12", "-2.12"),
+        ];
+        // 2.125 is exact in binary, a tie between 2.12 and 2.13
+        for &(round, pos, neg) in cases.iter() {
+            assert_eq!(fixed(2.125, 2, false, round), pos);
+            assert_eq!(format!("-{}", fixed(2.125, 2, true, round)), neg);
+        }
+    }
+
+    #[test]
+    fn carries() {
+        let mut out = EditBuf::new();
+        assert_eq!(sig_digits(9.995, 3, false, RU, &mut out), 2);
+        assert_eq!(out.as_str(), "100");
+        assert_eq!(fixed(0.001, 2, false, RU), "0.01");
+        assert_eq!(fixed(0.001, 2, false, RD), "0.00");
+        assert_eq!(fixed(99.5, 0, false, RC), "100.");
+    }
+
+    #[test]
+    fn spills_to_heap() {
+        let mut out = EditBuf::new();
+        assert_eq!(exact_digits(0.1, &mut out), 0);
+        assert_eq!(out.len(), 55);
+        assert!(out.as_str().starts_with("1000000000000000055511151231257827"));
+        assert_eq!(fixed(1e200, 1, false, RZ).len(), 202);
+    }
+
+    #[test]
+    fn compare() {
+        let a = parse_digits("-001.2500", 1);
+        assert_eq!(a, ("125".to_string(), 2));
+        let mut exact = EditBuf::new();
+        let x = exact_digits(12.5, &mut exact);
+        assert_eq!(cmp_digits((&a.0, a.1), (exact.as_str(), x)), ::std::cmp::Ordering::Equal);
+    }
+}
@@ -0,0 +1,501 @@
+//! Fortran type declarations
+//!
+//! Parses declarations copied from Fortran source into a `FortranType`
+//! for each entity declared:
+//!
+//! ```
+//! use f77_io::decl::parse_declaration;
+//! use f77_io::types::{FortranTag, FortranType};
+//! let decl = parse_declaration("REAL*8 X(10,20), Y").unwrap();
+//! assert_eq!(decl[0], ("X".to_string(), FortranType { tag: FortranTag::Real8, dim: Some(vec![10, 20]) }));
+//! assert_eq!(decl[1], ("Y".to_string(), FortranType { tag: FortranTag::Real8, dim: None }));
+//! ```
+//!
+//! Both the old form with a star length (`INTEGER*2`, `CHARACTER*16`)
+//! and the kind form (`INTEGER(KIND=8) :: N`, `CHARACTER(LEN=16)`) are
+//! accepted, with the attributes of the `::` form, of which only
+//! DIMENSION matters. Initial values are skipped.
+
+use types::{FortranTag, FortranType};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeclError {
+    UnexpectedEOF(usize),
+    UnexpectedChar(usize, char),
+    ExpectedName(usize),
+    ExpectedNumber(usize),
+    ExpectedParen(usize),
+    UnknownType(usize, String),
+    /// A kind or length that has no `FortranTag`.
+    UnsupportedKind(usize, usize),
+    /// A dimension without an explicit size, as `(:)` or `(*)`.
+    UnsupportedDim(usize),
+    NumberTooBig(usize),
+}
+
+/// The extent of a dimension.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Extent {
+    Fixed(usize),
+    /// Given by the variable of this name.
+    Named(String),
+}
+
+/// An entity declared with extents that may be variables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub name: String,
+    pub tag: FortranTag,
+    pub dim: Option<Vec<Extent>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Number(usize),
+    Punct(char),
+    DoubleColon,
+}
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>, DeclError> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_alphabetic() {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((start, Token::Name(chars[start..i].iter().collect())));
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().collect();
+            let n = digits.parse().map_err(|_| DeclError::NumberTooBig(start))?;
+            tokens.push((start, Token::Number(n)));
+        } else if c == ':' && chars.get(i + 1) == Some(&':') {
+            i += 2;
+            tokens.push((start, Token::DoubleColon));
+        } else if "(),*:=-+".contains(c) {
+            i += 1;
+            tokens.push((start, Token::Punct(c)));
+        } else if c == '\'' || c == '"' {
+            // only in initial values, which are skipped
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err(DeclError::UnexpectedEOF(i));
+            }
+            i += 1;
+            tokens.push((start, Token::Punct('\'')));
+        } else {
+            // other characters can only be part of an initial value
+            i += 1;
+            tokens.push((start, Token::Punct(c)));
+        }
+    }
+    Ok(tokens)
+}
+
+// extents with their offsets in the source
+type Dims = Vec<(usize, Extent)>;
+
+struct DeclParser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl DeclParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.1)
+    }
+
+    /// Offset in the source of the next token.
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |t| t.0)
+    }
+
+    fn unexpected(&self) -> DeclError {
+        match self.tokens.get(self.pos) {
+            Some(&(at, Token::Punct(c))) => DeclError::UnexpectedChar(at, c),
+            Some(&(at, Token::DoubleColon)) => DeclError::UnexpectedChar(at, ':'),
+            Some(&(at, Token::Name(ref s))) => DeclError::UnexpectedChar(at, s.chars().next().unwrap()),
+            Some(&(at, Token::Number(_))) => DeclError::ExpectedName(at),
+            None => DeclError::UnexpectedEOF(self.end),
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), DeclError> {
+        if self.eat(c) {
+            Ok(())
+        } else if c == '(' || c == ')' {
+            Err(DeclError::ExpectedParen(self.offset()))
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Take the next name if it is `keyword`, in any case.
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Name(s)) if s.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn name(&mut self) -> Result<String, DeclError> {
+        match self.peek().cloned() {
+            Some(Token::Name(s)) => {
+                self.pos += 1;
+                Ok(s)
+            },
+            Some(_) => Err(DeclError::ExpectedName(self.offset())),
+            None => Err(DeclError::UnexpectedEOF(self.end)),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, DeclError> {
+        match self.peek() {
+            Some(&Token::Number(n)) => {
+                self.pos += 1;
+                Ok(n)
+            },
+            Some(&Token::Punct('*')) | Some(&Token::Punct(':')) => Err(DeclError::UnsupportedDim(self.offset())),
+            Some(_) => Err(DeclError::ExpectedNumber(self.offset())),
+            None => Err(DeclError::UnexpectedEOF(self.end)),
+        }
+    }
+
+    fn signed(&mut self) -> Result<isize, DeclError> {
+        let negative = self.eat('-');
+        if !negative {
+            self.eat('+');
+        }
+        let at = self.offset();
+        let n = self.number()? as isize;
+        if n < 0 {
+            return Err(DeclError::NumberTooBig(at));
+        }
+        Ok(if negative { -n } else { n })
+    }
+
+    /// `(kind)`, `(KIND=kind)` or, for CHARACTER, `(LEN=len, KIND=kind)`,
+    /// giving the kind or the length.
+    fn selector(&mut self) -> Result<usize, DeclError> {
+        self.expect('(')?;
+        let mut value = None;
+        loop {
+            let keyword = match self.tokens.get(self.pos + 1) {
+                Some(&(_, Token::Punct('='))) => Some(self.name()?.to_ascii_uppercase()),
+                _ => None,
+            };
+            if keyword.is_some() {
+                self.expect('=')?;
+            }
+            let n = self.number()?;
+            // the length of CHARACTER comes first, its kind is ignored
+            if value.is_none() || keyword.as_ref().is_some_and(|k| k == "LEN") {
+                value = Some(n);
+            }
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(')')?;
+        Ok(value.unwrap())
+    }
+
+    /// `*len`, with the length possibly in parentheses.
+    fn star_len(&mut self) -> Result<Option<usize>, DeclError> {
+        if !self.eat('*') {
+            return Ok(None);
+        }
+        if self.eat('(') {
+            let n = self.number()?;
+            self.expect(')')?;
+            Ok(Some(n))
+        } else {
+            self.number().map(Some)
+        }
+    }
+
+    /// The extents of `(d1, d2, ...)`, each `upper`, `lower:upper` or
+    /// the name of a variable, with their offsets in the source.
+    fn dims(&mut self) -> Result<Dims, DeclError> {
+        self.expect('(')?;
+        let mut dims = vec![];
+        loop {
+            let at = self.offset();
+            if let Some(Token::Name(_)) = self.peek() {
+                dims.push((at, Extent::Named(self.name()?)));
+                if !self.eat(',') {
+                    break;
+                }
+                continue;
+            }
+            let first = self.signed()?;
+            let (lower, upper) = if self.eat(':') { (first, self.signed()?) } else { (1, first) };
+            if upper < lower - 1 {
+                return Err(DeclError::UnsupportedDim(at));
+            }
+            dims.push((at, Extent::Fixed((upper - lower + 1) as usize)));
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(')')?;
+        Ok(dims)
+    }
+
+    fn type_spec(&mut self) -> Result<FortranTag, DeclError> {
+        use types::FortranTag::*;
+        let at = self.offset();
+        let name = self.name()?.to_ascii_uppercase();
+        let name = match name.as_str() {
+            "DOUBLE" if self.eat_keyword("PRECISION") => "DOUBLEPRECISION".to_string(),
+            "DOUBLE" if self.eat_keyword("COMPLEX") => "DOUBLECOMPLEX".to_string(),
+            _ => name,
+        };
+        let known = ["INTEGER", "REAL", "COMPLEX", "LOGICAL", "CHARACTER", "BYTE", "DOUBLEPRECISION", "DOUBLECOMPLEX"];
+        if !known.contains(&name.as_str()) {
+            return Err(DeclError::UnknownType(at, name));
+        }
+        let (kind, star) = match self.peek() {
+            Some(Token::Punct('*')) => (self.star_len()?, true),
+            Some(Token::Punct('(')) => (Some(self.selector()?), false),
+            _ => (None, false),
+        };
+        let unsupported = DeclError::UnsupportedKind(at, kind.unwrap_or(0));
+        let tag = match (name.as_str(), kind) {
+            ("INTEGER", None) | ("INTEGER", Some(4)) => Int4,
+            ("INTEGER", Some(1)) | ("BYTE", None) => Byte,
+            ("INTEGER", Some(2)) => Int2,
+            ("INTEGER", Some(8)) => Int8,
+            ("REAL", None) | ("REAL", Some(4)) => Real4,
+            ("REAL", Some(8)) | ("DOUBLEPRECISION", None) => Real8,
+            // the star gives the size of both parts, the kind that of one
+            ("COMPLEX", None) => Complex4,
+            ("COMPLEX", Some(8)) => if star { Complex4 } else { Complex8 },
+            ("COMPLEX", Some(4)) if !star => Complex4,
+            ("COMPLEX", Some(16)) if star => Complex8,
+            ("DOUBLECOMPLEX", None) => Complex8,
+            ("LOGICAL", None) => Bool,
+            ("LOGICAL", Some(2)) => Bool2,
+            ("LOGICAL", Some(4)) => Bool4,
+            ("LOGICAL", Some(8)) => Bool8,
+            ("CHARACTER", len) => Char(len.unwrap_or(1)),
+            _ => return Err(unsupported),
+        };
+        Ok(tag)
+    }
+
+    /// Skip an initial value, up to the next entity.
+    fn skip_value(&mut self) {
+        let mut depth = 0;
+        while let Some(t) = self.peek() {
+            match *t {
+                Token::Punct('(') => depth += 1,
+                Token::Punct(')') => depth -= 1,
+                Token::Punct(',') if depth == 0 => break,
+                _ => {},
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn declaration(&mut self) -> Result<Vec<(String, FortranTag, Option<Dims>)>, DeclError> {
+        let tag = self.type_spec()?;
+        let mut dim = None;
+        let has_colons = self.tokens.iter().any(|t| t.1 == Token::DoubleColon);
+        if has_colons {
+            while self.eat(',') {
+                if self.eat_keyword("DIMENSION") {
+                    dim = Some(self.dims()?);
+                } else {
+                    // INTENT(IN), SAVE, PARAMETER...
+                    self.name()?;
+                    if self.peek() == Some(&Token::Punct('(')) {
+                        self.skip_parens();
+                    }
+                }
+            }
+            if self.peek() != Some(&Token::DoubleColon) {
+                return Err(self.unexpected());
+            }
+            self.pos += 1;
+        } else {
+            // the old form allows a comma after the length: CHARACTER*8, A
+            self.eat(',');
+        }
+
+        let mut entities = vec![];
+        loop {
+            let name = self.name()?;
+            let mut ty = (tag, dim.clone());
+            if self.peek() == Some(&Token::Punct('(')) {
+                ty.1 = Some(self.dims()?);
+            }
+            if let Some(len) = self.star_len()? {
+                ty.0 = match tag {
+                    FortranTag::Char(_) => FortranTag::Char(len),
+                    _ => return Err(DeclError::UnsupportedKind(self.offset(), len)),
+                };
+            }
+            if self.eat('=') {
+                self.skip_value();
+            }
+            entities.push((name, ty.0, ty.1));
+            if !self.eat(',') {
+                break;
+            }
+        }
+        if self.peek().is_some() {
+            return Err(self.unexpected());
+        }
+        Ok(entities)
+    }
+
+    fn skip_parens(&mut self) {
+        let mut depth = 0;
+        while let Some(t) = self.peek().cloned() {
+            self.pos += 1;
+            match t {
+                Token::Punct('(') => depth += 1,
+                Token::Punct(')') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
+fn parser(src: &str) -> Result<DeclParser, DeclError> {
+    Ok(DeclParser {
+        tokens: tokenize(src)?,
+        pos: 0,
+        end: src.chars().count(),
+    })
+}
+
+/// Parse a declaration statement into the name and type of every
+/// entity it declares, in order. Names keep the case of the source.
+pub fn parse_declaration(src: &str) -> Result<Vec<(String, FortranType)>, DeclError> {
+    parser(src)?.declaration()?.into_iter().map(|(name, tag, dim)| {
+        let dim = match dim {
+            Some(dim) => Some(dim.into_iter().map(|(at, e)| match e {
+                Extent::Fixed(n) => Ok(n),
+                Extent::Named(_) => Err(DeclError::UnsupportedDim(at)),
+            }).collect::<Result<Vec<_>, _>>()?),
+            None => None,
+        };
+        Ok((name, FortranType { tag, dim }))
+    }).collect()
+}
+
+/// Parse a declaration statement like `parse_declaration`, also accepting
+/// extents given by variables, as in `REAL X(N)`.
+pub fn parse_entities(src: &str) -> Result<Vec<Entity>, DeclError> {
+    Ok(parser(src)?.declaration()?.into_iter().map(|(name, tag, dim)| Entity {
+        name,
+        tag,
+        dim: dim.map(|d| d.into_iter().map(|e| e.1).collect()),
+    }).collect())
+}
+
+/// Parse a type alone, such as `REAL*8`, `LOGICAL(4)` or `DOUBLE COMPLEX`.
+pub fn parse_type(src: &str) -> Result<FortranTag, DeclError> {
+    let mut parser = parser(src)?;
+    let tag = parser.type_spec()?;
+    if parser.peek().is_some() {
+        return Err(parser.unexpected());
+    }
+    Ok(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use ::decl::{parse_declaration, parse_entities, parse_type, DeclError, Entity, Extent};
+    use ::types::FortranTag::*;
+    use ::types::{FortranTag, FortranType};
+
+    fn ty(tag: FortranTag, dim: Option<Vec<usize>>) -> FortranType {
+        FortranType { tag, dim }
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(parse_type("integer").unwrap(), Int4);
+        assert_eq!(parse_type("INTEGER*2").unwrap(), Int2);
+        assert_eq!(parse_type("INTEGER(KIND=8)").unwrap(), Int8);
+        assert_eq!(parse_type("REAL*8").unwrap(), Real8);
+        assert_eq!(parse_type("real(8)").unwrap(), Real8);
+        assert_eq!(parse_type("DOUBLE PRECISION").unwrap(), Real8);
+        assert_eq!(parse_type("DOUBLEPRECISION").unwrap(), Real8);
+        assert_eq!(parse_type("COMPLEX*16").unwrap(), Complex8);
+        assert_eq!(parse_type("COMPLEX(8)").unwrap(), Complex8);
+        assert_eq!(parse_type("COMPLEX*8").unwrap(), Complex4);
+        assert_eq!(parse_type("double complex").unwrap(), Complex8);
+        assert_eq!(parse_type("LOGICAL").unwrap(), Bool);
+        assert_eq!(parse_type("LOGICAL(4)").unwrap(), Bool4);
+        assert_eq!(parse_type("BYTE").unwrap(), Byte);
+        assert_eq!(parse_type("CHARACTER").unwrap(), Char(1));
+        assert_eq!(parse_type("CHARACTER*16").unwrap(), Char(16));
+        assert_eq!(parse_type("CHARACTER*(8)").unwrap(), Char(8));
+        assert_eq!(parse_type("CHARACTER(LEN=20, KIND=1)").unwrap(), Char(20));
+        assert_eq!(parse_type("CHARACTER(KIND=1, LEN=20)").unwrap(), Char(20));
+
+        assert_eq!(parse_type("REAL*16"), Err(DeclError::UnsupportedKind(0, 16)));
+        assert_eq!(parse_type("CHARACTER*(*)"), Err(DeclError::UnsupportedDim(11)));
+        assert_eq!(parse_type("TYPE(point)"), Err(DeclError::UnknownType(0, "TYPE".to_string())));
+        assert_eq!(parse_type("REAL X"), Err(DeclError::UnexpectedChar(5, 'X')));
+    }
+
+    #[test]
+    fn declarations() {
+        assert_eq!(parse_declaration("CHARACTER*16 NAMES(5), TITLE*40").unwrap(),
+                   [("NAMES".to_string(), ty(Char(16), Some(vec![5]))),
+                    ("TITLE".to_string(), ty(Char(40), None))]);
+        assert_eq!(parse_declaration("INTEGER(KIND=8) :: N").unwrap(),
+                   [("N".to_string(), ty(Int8, None))]);
+        assert_eq!(parse_declaration("real(8), dimension(3, 0:9), intent(in) :: a, b(2) = (/ 1, 2 /)").unwrap(),
+                   [("a".to_string(), ty(Real8, Some(vec![3, 10]))),
+                    ("b".to_string(), ty(Real8, Some(vec![2])))]);
+        assert_eq!(parse_declaration("LOGICAL*4 FLAGS(-1:1), OK").unwrap(),
+                   [("FLAGS".to_string(), ty(Bool4, Some(vec![3]))),
+                    ("OK".to_string(), ty(Bool4, None))]);
+        assert_eq!(parse_declaration("INTEGER, PARAMETER :: N = 10, M = MAX(N, 2)").unwrap().len(), 2);
+
+        assert_eq!(parse_declaration("REAL X(:)"), Err(DeclError::UnsupportedDim(7)));
+        assert_eq!(parse_declaration("REAL X(3, N)"), Err(DeclError::UnsupportedDim(10)));
+        assert_eq!(parse_entities("REAL X(3, N)").unwrap(),
+                   [Entity { name: "X".to_string(), tag: Real4, dim: Some(vec![Extent::Fixed(3), Extent::Named("N".to_string())]) }]);
+        assert_eq!(parse_declaration("REAL*8"), Err(DeclError::UnexpectedEOF(6)));
+        assert_eq!(parse_declaration("REAL X Y"), Err(DeclError::UnexpectedChar(7, 'Y')));
+    }
+}
@@ -0,0 +1,263 @@
+//! Direct access: records of a fixed length addressed by number
+//!
+//! A `DirectFile` holds records of RECL= bytes each, record `n` starting
+//! at byte `(n - 1) * recl`, with no markers or newlines between them.
+//! Records shorter than RECL= are padded with blanks if they are
+//! formatted and with zeros if they are not.
+//!
+//! Unformatted records are read and written by number. Formatted records
+//! are read and written through `BufRead` and `Write` at the current
+//! record, which REC= sets: each record read ends with a newline, and
+//! each newline written ends a record; either goes on to the next record.
+
+use std::io::{self, BufRead, Read, Seek, Write};
+use positioned::{read_buffered, PositionedBuf};
+use units::Form;
+
+pub struct DirectFile<S: Read + Seek> {
+    inner: PositionedBuf<S>,
+    form: Form,
+    recl: usize,
+    // the record read or written next, from 1
+    rec: u64,
+    // the current record as it is read, with its newline
+    line: Vec<u8>,
+    line_pos: usize,
+    // the current record as it is written, up to its newline
+    pending: Vec<u8>,
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+impl<S: Read + Seek> DirectFile<S> {
+    /// A file of `form` records of `recl` bytes, at its first record.
+    pub fn new(inner: S, form: Form, recl: usize) -> io::Result<DirectFile<S>> {
+        if recl == 0 {
+            return Err(invalid_input("RECL= must be positive"));
+        }
+        Ok(DirectFile {
+            inner: PositionedBuf::new(inner)?,
+            form,
+            recl,
+            rec: 1,
+            line: vec![],
+            line_pos: 0,
+            pending: vec![],
+        })
+    }
+
+    pub fn form(&self) -> Form {
+        self.form
+    }
+
+    pub fn recl(&self) -> usize {
+        self.recl
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.inner.get_ref()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner.into_inner()
+    }
+
+    /// The number of the record read or written next, as INQUIRE
+    /// NEXTREC= gives it.
+    pub fn rec(&self) -> u64 {
+        self.rec
+    }
+
+    fn offset(&self, rec: u64) -> io::Result<u64> {
+        if rec == 0 {
+            return Err(invalid_input("REC= counts from 1"));
+        }
+        (rec - 1).checked_mul(self.recl as u64).ok_or_else(|| invalid_input("REC= too large"))
+    }
+
+    /// Read record `rec`, `None` if the file ends before it.
+    pub fn read_record(&mut self, rec: u64) -> io::Result<Option<Vec<u8>>> {
+        let offset = self.offset(rec)?;
+        self.line.clear();
+        self.line_pos = 0;
+        self.inner.seek_to(offset)?;
+        let mut data = Vec::with_capacity(self.recl);
+        (&mut self.inner).take(self.recl as u64).read_to_end(&mut data)?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+        if data.len() < self.recl {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "record cut short"));
+        }
+        self.rec = rec + 1;
+        Ok(Some(data))
+    }
+}
+
+impl<S: Read + Write + Seek> DirectFile<S> {
+    /// Go to record `rec`, as REC= does, ending the record being written.
+    pub fn set_rec(&mut self, rec: u64) -> io::Result<()> {
+        self.offset(rec)?;
+        self.end_record()?;
+        self.line.clear();
+        self.line_pos = 0;
+        self.rec = rec;
+        Ok(())
+    }
+
+    /// Write `data` as record `rec`, padded to RECL= bytes.
+    pub fn write_record(&mut self, rec: u64, data: &[u8]) -> io::Result<()> {
+        if data.len() > self.recl {
+            return Err(invalid_input("record longer than RECL="));
+        }
+        let offset = self.offset(rec)?;
+        self.line.clear();
+        self.line_pos = 0;
+        let pad = if self.form == Form::Formatted { b' ' } else { 0 };
+        let mut record = data.to_vec();
+        record.resize(self.recl, pad);
+        self.inner.seek_to(offset)?;
+        self.inner.write_all(&record)?;
+        self.rec = rec + 1;
+        Ok(())
+    }
+
+    // write the record being written, if any, and go on to the next one
+    fn end_record(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let pending = ::std::mem::take(&mut self.pending);
+        let rec = self.rec;
+        self.write_record(rec, &pending)
+    }
+}
+
+impl<S: Read + Seek> Read for DirectFile<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read_buffered(self, buf)
+    }
+}
+
+impl<S: Read + Seek> BufRead for DirectFile<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if !self.pending.is_empty() {
+            return Err(invalid_input("cannot read a record being written"));
+        }
+        if self.line.is_empty() {
+            let rec = self.rec;
+            if let Some(mut data) = self.read_record(rec)? {
+                if self.form == Form::Formatted {
+                    data.push(b'\n');
+                }
+                // the record is passed once it is consumed
+                self.rec = rec;
+                self.line = data;
+            }
+        }
+        Ok(&self.line[self.line_pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.line_pos = (self.line_pos + amt).min(self.line.len());
+        if !self.line.is_empty() && self.line_pos == self.line.len() {
+            self.line.clear();
+            self.line_pos = 0;
+            self.rec += 1;
+        }
+    }
+}
+
+impl<S: Read + Write + Seek> Write for DirectFile<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // a record being read is left for the one written
+        self.line.clear();
+        self.line_pos = 0;
+        let (len, end) = match buf.iter().position(|&c| c == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (buf.len(), false),
+        };
+        let data = if end { &buf[..len - 1] } else { buf };
+        if self.pending.len() + data.len() > self.recl {
+            return Err(invalid_input("record longer than RECL="));
+        }
+        self.pending.extend_from_slice(data);
+        if end {
+            if self.pending.is_empty() {
+                let rec = self.rec;
+                self.write_record(rec, &[])?;
+            } else {
+                self.end_record()?;
+            }
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // a partial record is written as it is, and stays current
+        if !self.pending.is_empty() {
+            let (rec, pending) = (self.rec, self.pending.clone());
+            self.write_record(rec, &pending)?;
+            self.rec = rec;
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use ::direct::DirectFile;
+    use ::format::parse_format;
+    use ::read::FortranIterReader;
+    use ::units::Form;
+    use ::write::FortranIterWriter;
+
+    #[test]
+    fn unformatted() {
+        let mut file = DirectFile::new(Cursor::new(vec![]), Form::Unformatted, 4).unwrap();
+        file.write_record(2, b"ab").unwrap();
+        file.write_record(1, b"wxyz").unwrap();
+        assert_eq!(file.get_ref().get_ref(), b"wxyzab\0\0");
+        assert_eq!(file.rec(), 2);
+        assert_eq!(file.read_record(2).unwrap().unwrap(), b"ab\0\0");
+        assert_eq!(file.read_record(3).unwrap(), None);
+        assert!(file.write_record(1, b"abcde").is_err());
+        assert!(file.read_record(0).is_err());
+        assert!(DirectFile::new(Cursor::new(vec![]), Form::Unformatted, 0).is_err());
+
+        let mut file = DirectFile::new(Cursor::new(b"abcdef".to_vec()), Form::Unformatted, 4).unwrap();
+        assert!(file.read_record(2).is_err());
+    }
+
+    #[test]
+    fn formatted() {
+        let fmt = parse_format("(I3)").unwrap();
+        let mut file = DirectFile::new(Cursor::new(vec![]), Form::Formatted, 5).unwrap();
+        for &(rec, val) in [(3, 30), (1, 10)].iter() {
+            file.set_rec(rec).unwrap();
+            let mut writer = FortranIterWriter::new(&fmt);
+            writer.write_value(&mut file, &val).unwrap();
+            writer.write_constants(&mut file, false).unwrap();
+        }
+        file.write_all(b"20\n").unwrap();
+        assert_eq!(file.get_ref().get_ref(), b" 10  20    30  ");
+        assert!(file.write_all(b"123456\n").is_err());
+
+        let read = |file: &mut DirectFile<Cursor<Vec<u8>>>| {
+            let mut val = 0;
+            let mut reader = FortranIterReader::new(&fmt, file);
+            reader.read_value(&mut val).unwrap();
+            reader.consume_constants(false).unwrap();
+            val
+        };
+        file.set_rec(3).unwrap();
+        assert_eq!(read(&mut file), 30);
+        file.set_rec(1).unwrap();
+        assert_eq!(read(&mut file), 10);
+        assert_eq!(read(&mut file), 20);
+        assert_eq!(file.rec(), 3);
+    }
+}
@@ -16,6 +16,7 @@
 //!
 
 use ::std::fmt::{Write};
+use types::{FortranTag};
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum RealFormat {
@@ -23,6 +24,9 @@ pub enum RealFormat {
     E,
     D,
     G,
+    ES,
+    EN,
+    EX,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -30,6 +34,7 @@ pub enum IntFormat {
     I,
     O,
     Z,
+    B,
 }
 
 impl From<char> for RealFormat {
@@ -45,25 +50,29 @@ impl From<char> for RealFormat {
     }
 }
 
-impl<'a> Into<char> for &'a RealFormat {
-    fn into(self) -> char {
+impl From<&RealFormat> for &'static str {
+    fn from(val: &RealFormat) -> Self {
         use self::RealFormat::*;
-        match *self {
-            F => 'F',
-            E => 'E',
-            D => 'D',
-            G => 'G',
+        match *val {
+            F => "F",
+            E => "E",
+            D => "D",
+            G => "G",
+            ES => "ES",
+            EN => "EN",
+            EX => "EX",
         }
     }
 }
 
-impl<'a> Into<char> for &'a IntFormat {
-    fn into(self) -> char {
+impl From<&IntFormat> for char {
+    fn from(val: &IntFormat) -> Self {
         use self::IntFormat::*;
-        match *self {
+        match *val {
             I => 'I',
             O => 'O',
             Z => 'Z',
+            B => 'B',
         }
     }
 }
@@ -75,6 +84,34 @@ pub enum BlankType {
     B,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignType {
+    S,
+    SP,
+    SS,
+}
+
+/// Rounding of reals to the digits of their editing: up, down,
+/// toward zero, to nearest with ties to even, to nearest with ties
+/// away from zero, and processor-dependent, which is RN here.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RoundType {
+    RU,
+    RD,
+    RZ,
+    RN,
+    RC,
+    RP,
+}
+
+/// The decimal symbol of reals: a comma for DC, a point for DP.
+/// List-directed values are separated by semicolons under DC.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DecimalType {
+    DC,
+    DP,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TabType {
     T,
@@ -90,12 +127,16 @@ pub enum FormatNode {
     RemainingChars,
     Terminate,
     BlankControl(BlankType),
+    SignControl(SignType),
+    RoundControl(RoundType),
+    DecimalControl(DecimalType),
     AbsColumn(usize),
     RelColumn(isize),
     Radix(usize),
     Scale(isize),
 
     Literal(String),
+    Hollerith(String),
 
     Str(Option<usize>),
     Bool(Option<usize>),
@@ -104,9 +145,26 @@ pub enum FormatNode {
 
     Group(Vec<FormatNode>),
     Repeat(usize, Box<FormatNode>),
+    UnlimitedRepeat(Box<FormatNode>),
 }
 
 impl FormatNode {
+    /// Check whether this data edit descriptor is the standard editing
+    /// for items of type `tag`: I/O/Z/B for integers, F/E/D/ES/EN/EX for
+    /// reals, L for logicals and A for characters. G is accepted for all.
+    pub fn accepts(&self, tag: FortranTag) -> bool {
+        use self::FormatNode::*;
+        match *self {
+            Real(RealFormat::G, _, _, _) => true,
+            Real(_, _, _, _) => tag.is_real() || tag.is_complex(),
+            Int(_, _, _) => tag.is_integer(),
+            Bool(_) => tag.is_logical(),
+            Str(_) => tag.is_character(),
+            RemainingChars => tag.is_integer(),
+            _ => false,
+        }
+    }
+
     pub fn write_string<W>(&self, out: &mut W) -> Result<(), ::std::fmt::Error>
         where W: Write
     {
@@ -120,6 +178,11 @@ impl FormatNode {
             BlankControl(BlankType::B) => out.write_char('B'),
             BlankControl(BlankType::BZ) => out.write_str("BZ"),
             BlankControl(BlankType::BN) => out.write_str("BN"),
+            SignControl(SignType::S) => out.write_char('S'),
+            SignControl(SignType::SP) => out.write_str("SP"),
+            SignControl(SignType::SS) => out.write_str("SS"),
+            RoundControl(r) => write!(out, "{:?}", r),
+            DecimalControl(d) => write!(out, "{:?}", d),
             AbsColumn(w) => write!(out, "T{}", w),
             RelColumn(w) => {
                 if w >= 0 { write!(out, "TR{}", w) }
@@ -132,6 +195,7 @@ impl FormatNode {
                 let escaped = x.split('\'').collect::<Vec<&str>>().join("''");
                 write!(out, "'{}'", escaped)
             },
+            Hollerith(ref x) => write!(out, "{}H{}", x.chars().count(), x),
             Str(ow) => {
                 match ow {
                     None => write!(out, "A"),
@@ -152,7 +216,7 @@ impl FormatNode {
                 }
             },
             Real(ref f, w, od, oe) => {
-                let c: char = f.into();
+                let c: &str = f.into();
                 match (w, od, oe) {
                     (w, None, _) => write!(out, "{}{}", c, w),
                     (w, Some(d), None) => write!(out, "{}{}.{}", c, w, d),
@@ -160,44 +224,40 @@ impl FormatNode {
                 }
             },
             Group(ref v) => {
-                try!(out.write_char('('));
+                out.write_char('(')?;
                 let mut skip_comma = true;
                 let mut need_comma = true;
-                let mut it = v.iter();
-                loop {
-                    let node = match it.next() {
-                        Some(x) => x,
-                        None => break,
-                    };
-
+                for node in v.iter() {
                     if *node == NewLine {
                         skip_comma = true;
                     }
 
                     if !skip_comma && need_comma {
-                        try!(out.write_str(", "));
+                        out.write_str(", ")?;
                     }
 
                     skip_comma = false;
                     need_comma = !(*node == NewLine || *node == Terminate);
-                    try!(node.write_string(out));
+                    node.write_string(out)?;
                 }
 
                 out.write_char(')')
             },
             Repeat(n, ref b) => {
-                try!(write!(out, "{}", n));
+                write!(out, "{}", n)?;
+                b.write_string(out)
+            },
+            UnlimitedRepeat(ref b) => {
+                out.write_char('*')?;
                 b.write_string(out)
             },
         }
     }
 }
 
-impl ToString for FormatNode {
-    fn to_string(&self) -> String {
-        let mut rv = String::new();
-        self.write_string(&mut rv).unwrap();
-        rv
+impl ::std::fmt::Display for FormatNode {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        self.write_string(f)
     }
 }
 
@@ -214,18 +274,47 @@ pub enum ParseError {
     RepeatingColon(usize),
     RepeatingStr(usize),
     RepeatingBlankControl(usize),
+    RepeatingSignControl(usize),
     RepeatingTab(usize),
     RepeatingQ(usize),
     ExtraComma(usize),
     MissingScale(usize),
     UnexpectedChar(usize, char),
     MissingRadix(usize),
+    MissingHollerithCount(usize),
+    UnlimitedWithoutData(usize),
     RadixOutOfRange(usize, usize),
 }
 
+impl ParseError {
+    /// Number of characters of the source before the error, or before
+    /// the character an `UnexpectedChar` names.
+    pub fn position(&self) -> usize {
+        use self::ParseError::*;
+        match *self {
+            UnexpectedChar(p, _) => p.saturating_sub(1),
+            UnexpectedEOF(p) | ExpectedNumber(p) | ExpectedNonZero(p) | ExpectedParen(p) |
+            ExpectedComma(p) | ExpectedScaleControl(p) | NumberTooBig(p) | RepeatingDollar(p) |
+            RepeatingColon(p) | RepeatingStr(p) | RepeatingBlankControl(p) | RepeatingSignControl(p) |
+            RepeatingTab(p) | RepeatingQ(p) | ExtraComma(p) | MissingScale(p) |
+            MissingRadix(p) | MissingHollerithCount(p) | UnlimitedWithoutData(p) | RadixOutOfRange(p, _) => p,
+        }
+    }
+}
+
 use ::std::iter::{Peekable};
 use ::std::str::{Chars};
 
+fn has_data_editing(node: &FormatNode) -> bool {
+    use self::FormatNode::*;
+    match *node {
+        Str(_) | Bool(_) | Int(_, _, _) | Real(_, _, _, _) | RemainingChars => true,
+        Group(ref v) => v.iter().any(has_data_editing),
+        Repeat(_, ref n) | UnlimitedRepeat(ref n) => has_data_editing(n),
+        _ => false,
+    }
+}
+
 pub fn parse_format(source: &str) -> Result<FormatNode, ParseError> {
     let mut it = source.chars().peekable();
     FormatParser::new(&mut it).parse()
@@ -234,19 +323,39 @@ pub fn parse_format(source: &str) -> Result<FormatNode, ParseError> {
 pub struct FormatParser<'a> {
     it: &'a mut Peekable<Chars<'a>>,
     pos: usize,
+    lenient: bool,
+    missing_commas: Vec<usize>,
 }
 
 impl<'a> FormatParser<'a> {
     pub fn new(it: &'a mut Peekable<Chars<'a>>) -> FormatParser<'a> {
         FormatParser {
-            it: it,
+            it,
             pos: 0,
+            lenient: false,
+            missing_commas: vec![],
         }
     }
 
+    /// Accept edit descriptors that are not separated by a comma where
+    /// the standard requires one, as most compilers do.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// Positions of the commas a lenient parser found missing.
+    pub fn missing_commas(&self) -> &[usize] {
+        &self.missing_commas
+    }
+
+    /// Number of characters taken from the input.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
     #[inline(always)]
     fn peek(&mut self) -> Option<char> {
-        self.it.peek().map(|v| *v)
+        self.it.peek().copied()
     }
 
     #[inline(always)]
@@ -259,11 +368,11 @@ impl<'a> FormatParser<'a> {
         let mut s = String::new();
 
         let start = self.pos;
-        while self.peek().map(|c| '0' <= c && c <= '9') == Some(true) {
+        while self.peek().map(|c: char| c.is_ascii_digit()) == Some(true) {
             s.push(self.next().unwrap());
         }
 
-        if s.len() != 0 {
+        if !s.is_empty() {
             match s.parse::<usize>() {
                 Ok(x) => Ok(Some(x)),
                 Err(_) => Err(ParseError::NumberTooBig(start)),
@@ -275,12 +384,31 @@ impl<'a> FormatParser<'a> {
 
     fn yield_whitespace(&mut self) {
         // TODO: use char::is_whitespace?
-        const WHITESPACE: &'static str = " \t\r\n";
+        const WHITESPACE: &str = " \t\r\n";
         while self.peek().map(|c| WHITESPACE.contains(c)) == Some(true) {
             let _ = self.next();
         }
     }
 
+    /// Skip the comma after an edit descriptor, telling whether there was one.
+    fn yield_comma(&mut self) -> bool {
+        self.yield_whitespace();
+        let comma = self.peek() == Some(',');
+        if comma {
+            let _ = self.next();
+        }
+        comma
+    }
+
+    /// Whether an F, E, D or G descriptor follows, with or without a
+    /// repeat count, which may come after a scale factor without a comma.
+    fn real_follows(&mut self) -> bool {
+        self.yield_whitespace();
+        let mut ahead = self.it.clone();
+        ahead.find(|&c| !c.is_ascii_digit() && !" \t\r\n".contains(c))
+            .is_some_and(|c| "FEDGfedg".contains(c))
+    }
+
     fn yield_string(&mut self, dst: &mut String, e: char) -> Result<(), ParseError> {
         loop {
             match self.next() {
@@ -299,21 +427,19 @@ impl<'a> FormatParser<'a> {
         use self::ParseError::*;
 
         self.yield_whitespace();
+        // a zero width is the minimal width of I0, B0, O0 and Z0
         let w = self.yield_digits()
             .and_then(|v|v.ok_or(ExpectedNumber(self.pos)))?;
         let mut d = None;
-        if w == 0 {
-            return Err(ExpectedNonZero(self.pos));
-        }
         if self.peek() == Some('.') {
             let _ = self.next();
             self.yield_whitespace();
-            d = try!(self.yield_digits());
-            if !d.is_some() {
+            d = self.yield_digits()?;
+            if d.is_none() {
                 return Err(ExpectedNumber(self.pos));
             }
         }
-        return Ok((w, d));
+        Ok((w, d))
     }
 
     // Here be dragons
@@ -348,14 +474,17 @@ impl<'a> FormatParser<'a> {
                 None => break,
             };
 
-            if p != ')' && p != '/' && result.len() != 0 && (!was_comma && need_comma) {
-                return Err(ExpectedComma(self.pos));
+            if p != ')' && p != '/' && !result.is_empty() && (!was_comma && need_comma) {
+                if !self.lenient {
+                    return Err(ExpectedComma(self.pos));
+                }
+                self.missing_commas.push(self.pos);
             }
 
             // Scale Control is the only source of negative value prefixes
             if p == '-' {
                 let _ = self.next();
-                let scale = match try!(self.yield_digits()) {
+                let scale = match self.yield_digits()? {
                     Some(x) => -(x as isize),
                     None => return Err(ExpectedNumber(self.pos)),
                 };
@@ -363,6 +492,8 @@ impl<'a> FormatParser<'a> {
                 match self.next() {
                     Some('P') | Some('p') => {
                         result.push(Scale(scale));
+                        need_comma = !self.real_follows();
+                        was_comma = self.yield_comma();
                         continue;
                     },
                     Some(_) | None => {
@@ -371,27 +502,37 @@ impl<'a> FormatParser<'a> {
                 }
             }
 
-            let repeat = try!(self.yield_digits());
+            // F2008 unlimited repeat: *(...)
+            let unlimited = p == '*';
+            if unlimited {
+                let _ = self.next();
+            }
+
+            let repeat = self.yield_digits()?;
             self.yield_whitespace();
 
+            if unlimited && (repeat.is_some() || self.peek() != Some('(')) {
+                return Err(ExpectedParen(self.pos));
+            }
+
             if self.peek() == Some('(') {
                 match self.parse() {
                     Ok(Group(v)) => {
-                        result.push(mk_repeating(repeat, Group(v)));
+                        if !unlimited {
+                            result.push(mk_repeating(repeat, Group(v)));
+                        } else if v.iter().any(has_data_editing) {
+                            result.push(UnlimitedRepeat(Box::new(Group(v))));
+                        } else {
+                            // it would repeat forever without consuming items
+                            return Err(UnlimitedWithoutData(self.pos));
+                        }
                     },
                     Ok(_) => unreachable!(),
                     Err(x) => return Err(x),
                 };
 
-                self.yield_whitespace();
-
-                if self.peek() == Some(',') {
-                    was_comma = true;
-                    let _ = self.next();
-                } else {
-                    was_comma = false;
-                }
-
+                need_comma = true;
+                was_comma = self.yield_comma();
                 continue;
             }
 
@@ -436,20 +577,24 @@ impl<'a> FormatParser<'a> {
                     result.push(RemainingChars);
                 },
                 'I' | 'i' => {
-                    let (w, d) = try!(self.yield_int_format());
+                    let (w, d) = self.yield_int_format()?;
                     result.push(mk_repeating(repeat, Int(IntFormat::I, w, d)));
                 },
                 'Z' | 'z' => {
-                    let (w, d) = try!(self.yield_int_format());
+                    let (w, d) = self.yield_int_format()?;
                     result.push(mk_repeating(repeat, Int(IntFormat::Z, w, d)));
                 },
+                'B' | 'b' if self.peek().map(|c: char| c.is_ascii_digit()) == Some(true) => {
+                    let (w, d) = self.yield_int_format()?;
+                    result.push(mk_repeating(repeat, Int(IntFormat::B, w, d)));
+                },
                 'O' | 'o' => {
-                    let (w, d) = try!(self.yield_int_format());
+                    let (w, d) = self.yield_int_format()?;
                     result.push(mk_repeating(repeat, Int(IntFormat::O, w, d)));
                 },
                 'L' | 'l' => {
                     self.yield_whitespace();
-                    let w = try!(self.yield_digits());
+                    let w = self.yield_digits()?;
                     if w == Some(0) {
                         return Err(ExpectedNonZero(self.pos));
                     }
@@ -457,7 +602,7 @@ impl<'a> FormatParser<'a> {
                 },
                 'A' | 'a' => {
                     self.yield_whitespace();
-                    let w = try!(self.yield_digits());
+                    let w = self.yield_digits()?;
                     if w == Some(0) {
                         return Err(ExpectedNonZero(self.pos));
                     }
@@ -471,7 +616,7 @@ impl<'a> FormatParser<'a> {
                     let e = c;
                     loop {
                         // read quoted
-                        try!(self.yield_string(&mut s, e));
+                        self.yield_string(&mut s, e)?;
                         // if quote repeats, it's escaped
                         if self.peek() == Some(e) {
                             s.push(e);
@@ -482,46 +627,110 @@ impl<'a> FormatParser<'a> {
                     }
                     result.push(Literal(s));
                 },
+                'H' | 'h' => {
+                    // the count includes blanks, commas and parentheses
+                    let n = match repeat {
+                        Some(0) => return Err(ExpectedNonZero(self.pos)),
+                        Some(n) => n,
+                        None => return Err(MissingHollerithCount(self.pos)),
+                    };
+                    let mut s = String::new();
+                    for _ in 0..n {
+                        match self.next() {
+                            Some(c) => s.push(c),
+                            None => return Err(UnexpectedEOF(self.pos)),
+                        }
+                    }
+                    result.push(Hollerith(s));
+                },
+                'D' | 'd' if repeat.is_none() && self.peek().map(|c| "CPcp".contains(c)) == Some(true) => {
+                    let decimal_type = match self.next().unwrap() {
+                        'C' | 'c' => DecimalType::DC,
+                        _ => DecimalType::DP,
+                    };
+                    result.push(DecimalControl(decimal_type));
+                },
                 'F' | 'f' | 'E' | 'e' | 'D' | 'd' | 'G' | 'g' => {
+                    let f = match (c.to_ascii_uppercase(), self.peek().map(|c| c.to_ascii_uppercase())) {
+                        ('E', Some('S')) => RealFormat::ES,
+                        ('E', Some('N')) => RealFormat::EN,
+                        ('E', Some('X')) => RealFormat::EX,
+                        _ => RealFormat::from(c),
+                    };
+                    if f == RealFormat::ES || f == RealFormat::EN || f == RealFormat::EX {
+                        let _ = self.next();
+                    }
                     self.yield_whitespace();
                     let w = self.yield_digits()
                         .and_then(|v|v.ok_or(ExpectedNumber(self.pos)))?;
                     let mut d = None;
                     let mut e = None;
-                    if w == 0 {
+                    // only F0.d, G0 and EX0.d have a minimal width
+                    if w == 0 && f != RealFormat::F && f != RealFormat::G && f != RealFormat::EX {
                         return Err(ExpectedNonZero(self.pos));
                     }
                     if self.peek() == Some('.') {
                         let _ = self.next();
                         self.yield_whitespace();
-                        d = try!(self.yield_digits());
-                        if !d.is_some() {
+                        d = self.yield_digits()?;
+                        if d.is_none() {
                             return Err(ExpectedNumber(self.pos));
                         }
                     }
                     if d.is_some() && self.peek().map(|c| c == '.' || c == 'e' || c == 'E').unwrap_or(false) {
                         let _ = self.next();
                         self.yield_whitespace();
-                        e = try!(self.yield_digits());
+                        e = self.yield_digits()?;
                         if e == Some(0) {
                             return Err(ExpectedNonZero(self.pos));
                         }
-                        if !e.is_some() {
+                        if e.is_none() {
                             return Err(ExpectedNumber(self.pos));
                         }
                     }
-                    result.push(mk_repeating(repeat, Real(RealFormat::from(c), w, d, e)));
+                    result.push(mk_repeating(repeat, Real(f, w, d, e)));
                 },
-                'P' => {
+                'P' | 'p' => {
                     match repeat {
                         Some(r) => result.push(Scale(r as isize)),
                         None => return Err(MissingScale(self.pos)),
                     }
+                    need_comma = !self.real_follows();
+                },
+                'R' | 'r' if repeat.is_none() && self.peek().map(|c| "UDZNCPudzncp".contains(c)) == Some(true) => {
+                    let round_type = match self.next().unwrap().to_ascii_uppercase() {
+                        'U' => RoundType::RU,
+                        'D' => RoundType::RD,
+                        'Z' => RoundType::RZ,
+                        'N' => RoundType::RN,
+                        'C' => RoundType::RC,
+                        _ => RoundType::RP,
+                    };
+                    result.push(RoundControl(round_type));
+                },
+                'S' | 's' => {
+                    if repeat.is_some() {
+                        return Err(RepeatingSignControl(self.pos));
+                    }
+
+                    let sign_type = match self.peek() {
+                        Some('P') | Some('p') => {
+                            let _ = self.next();
+                            SignType::SP
+                        },
+                        Some('S') | Some('s') => {
+                            let _ = self.next();
+                            SignType::SS
+                        },
+                        _ => SignType::S,
+                    };
+
+                    result.push(SignControl(sign_type));
                 },
                 'R' => {
                     match repeat {
                         Some(r) => {
-                            if 2 <= r && r <= 36 {
+                            if (2..=36).contains(&r) {
                                 result.push(Radix(r));
                             } else {
                                 return Err(RadixOutOfRange(self.pos, r));
@@ -530,7 +739,7 @@ impl<'a> FormatParser<'a> {
                         None => return Err(MissingRadix(self.pos)),
                     }
                 },
-                'B' => {
+                'B' | 'b' => {
                     if repeat.is_some() {
                         return Err(RepeatingBlankControl(self.pos));
                     }
@@ -567,7 +776,7 @@ impl<'a> FormatParser<'a> {
                     };
 
                     self.yield_whitespace();
-                    let c = match try!(self.yield_digits()) {
+                    let c = match self.yield_digits()? {
                         Some(x) => x,
                         None => return Err(ExpectedNumber(self.pos)),
                     };
@@ -587,17 +796,10 @@ impl<'a> FormatParser<'a> {
                 },
             }
 
-            self.yield_whitespace();
-
-            if self.peek() == Some(',') {
-                was_comma = true;
-                let _ = self.next();
-            } else {
-                was_comma = false;
-            }
+            was_comma = self.yield_comma();
         }
 
-        return Err(UnexpectedEOF(self.pos));
+        Err(UnexpectedEOF(self.pos))
     }
 }
 
@@ -606,7 +808,8 @@ impl<'a> FormatParser<'a> {
 #[cfg(test)]
 mod tests {
     use ::format::FormatNode::*;
-    use ::format::parse_format;
+    use ::format::{BlankType, DecimalType, IntFormat, RealFormat, RoundType, SignType};
+    use ::format::{parse_format, FormatParser};
 
     #[test]
     fn empty() {
@@ -662,6 +865,114 @@ mod tests {
         assert_eq!(parse_format(src).unwrap(), parsed);
     }
 
+    #[test]
+    fn hollerith() {
+        let src = "(12HTOTAL ENERGY, F10.3, 3H(,), 2h ')";
+        let parsed = Group(vec![
+            Hollerith("TOTAL ENERGY".to_string()),
+            Real(RealFormat::F, 10, Some(3), None),
+            Hollerith("(,)".to_string()),
+            Hollerith(" '".to_string())]);
+        assert_eq!(parse_format(src).unwrap(), parsed);
+        assert_eq!(parsed.to_string(), "(12HTOTAL ENERGY, F10.3, 3H(,), 2H ')");
+    }
+
+    #[test]
+    fn err_hollerith() {
+        assert!(parse_format("(HABC)").is_err());
+        assert!(parse_format("(0HABC)").is_err());
+        assert!(parse_format("(5HABC)").is_err());
+    }
+
+    #[test]
+    fn modern_descriptors() {
+        let src = "(B8.4, ES12.4, EN12.3E3, EX20.6, I0, F0.3, G0, *(I5, :, ','))";
+        let parsed = Group(vec![
+            Int(IntFormat::B, 8, Some(4)),
+            Real(RealFormat::ES, 12, Some(4), None),
+            Real(RealFormat::EN, 12, Some(3), Some(3)),
+            Real(RealFormat::EX, 20, Some(6), None),
+            Int(IntFormat::I, 0, None),
+            Real(RealFormat::F, 0, Some(3), None),
+            Real(RealFormat::G, 0, None, None),
+            UnlimitedRepeat(Box::new(Group(vec![
+                Int(IntFormat::I, 5, None), Terminate, Literal(",".to_string())])))]);
+        assert_eq!(parse_format(src).unwrap(), parsed);
+        assert_eq!(parsed.to_string(), src.replace(":, ", ":"));
+        // the second letter need not match the case of the first
+        assert_eq!(parse_format("(Es12.4, eN10.2, eX9.1)").unwrap(), Group(vec![
+            Real(RealFormat::ES, 12, Some(4), None),
+            Real(RealFormat::EN, 10, Some(2), None),
+            Real(RealFormat::EX, 9, Some(1), None)]));
+    }
+
+    #[test]
+    fn blank_control_is_not_binary() {
+        let parsed = Group(vec![BlankControl(BlankType::B), Int(IntFormat::B, 4, None),
+                                BlankControl(BlankType::BN)]);
+        assert_eq!(parse_format("(B, B4, BN)").unwrap(), parsed);
+    }
+
+    #[test]
+    fn sign_and_round_control() {
+        let src = "(SP, I5, SS, S, RU, F8.2, rd, RZ, RN, RC, RP, 16R)";
+        let parsed = Group(vec![
+            SignControl(SignType::SP), Int(IntFormat::I, 5, None),
+            SignControl(SignType::SS), SignControl(SignType::S),
+            RoundControl(RoundType::RU), Real(RealFormat::F, 8, Some(2), None),
+            RoundControl(RoundType::RD), RoundControl(RoundType::RZ),
+            RoundControl(RoundType::RN), RoundControl(RoundType::RC),
+            RoundControl(RoundType::RP), Radix(16)]);
+        assert_eq!(parse_format(src).unwrap(), parsed);
+        assert_eq!(parsed.to_string(), src.replace("rd", "RD"));
+        assert!(parse_format("(2SP)").is_err());
+    }
+
+    #[test]
+    fn decimal_control() {
+        let parsed = Group(vec![DecimalControl(DecimalType::DC), Real(RealFormat::F, 8, Some(3), None),
+                                DecimalControl(DecimalType::DP), Real(RealFormat::D, 10, Some(2), None)]);
+        assert_eq!(parse_format("(DC, F8.3, dp, D10.2)").unwrap(), parsed);
+        assert_eq!(parsed.to_string(), "(DC, F8.3, DP, D10.2)");
+        assert!(parse_format("(2DC)").is_err());
+    }
+
+    #[test]
+    fn err_modern_descriptors() {
+        assert!(parse_format("(E0.3)").is_err());
+        assert!(parse_format("(*('x'))").is_err());
+        assert!(parse_format("(*I5)").is_err());
+        assert!(parse_format("(3*(I5))").is_err());
+    }
+
+    #[test]
+    fn scale_factor() {
+        let parsed = Group(vec![Scale(1), Real(RealFormat::E, 12, Some(4), None)]);
+        assert_eq!(parse_format("(1PE12.4)").unwrap(), parsed);
+        assert_eq!(parse_format("(1p, E12.4)").unwrap(), parsed);
+        let parsed = Group(vec![Int(IntFormat::I, 2, None), Scale(-2), Real(RealFormat::F, 5, Some(1), None)]);
+        assert_eq!(parse_format("(I2, -2P, F5.1)").unwrap(), parsed);
+        assert_eq!(parse_format("(I2, -2P F5.1)").unwrap(), parsed);
+        assert!(parse_format("(1P I5)").is_err());
+        assert_eq!(parse_format("(1P5E14.6)").unwrap(),
+                   Group(vec![Scale(1), Repeat(5, Box::new(Real(RealFormat::E, 14, Some(6), None)))]));
+        assert_eq!(parse_format("(2P3F8.2)").unwrap(),
+                   Group(vec![Scale(2), Repeat(3, Box::new(Real(RealFormat::F, 8, Some(2), None)))]));
+        assert_eq!(parse_format("(2P 3 F8.2)").unwrap(), parse_format("(2P3F8.2)").unwrap());
+        assert!(parse_format("(1P5I5)").is_err());
+    }
+
+    #[test]
+    fn missing_commas() {
+        let src = "(I5F10.3, 2(A1)'x')";
+        assert!(parse_format(src).is_err());
+        let mut it = src.chars().peekable();
+        let mut parser = FormatParser::new(&mut it);
+        parser.set_lenient(true);
+        assert_eq!(parser.parse().unwrap().to_string(), "(I5, F10.3, 2(A1), 'x')");
+        assert_eq!(parser.missing_commas(), &[3, 15]);
+    }
+
     // NOTE: this test is sensitive to whitespace.
     // do we want to change this behavior?
     #[test]
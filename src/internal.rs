@@ -0,0 +1,287 @@
+//! Internal files: records kept in strings
+//!
+//! An `InternalReader` reads a slice of strings as a file with one record
+//! per element, an `InternalWriter` stores the records written into a slice
+//! of strings, each padded with blanks to a fixed length. Both work with
+//! the formatted and the list-directed readers and writers.
+
+use std::error::{Error};
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::slice;
+use positioned::read_buffered;
+
+/// The records of an internal file being read, a `BufRead` giving
+/// every element followed by a newline.
+pub struct InternalReader<'a, S: 'a + AsRef<str>> {
+    records: &'a [S],
+    record: usize,
+    pos: usize,
+}
+
+impl<'a, S: AsRef<str>> InternalReader<'a, S> {
+    pub fn new(records: &'a [S]) -> InternalReader<'a, S> {
+        InternalReader {
+            records,
+            record: 0,
+            pos: 0,
+        }
+    }
+
+    /// An internal file of the single record `record`.
+    pub fn single(record: &'a S) -> InternalReader<'a, S> {
+        InternalReader::new(slice::from_ref(record))
+    }
+}
+
+impl<'a, S: AsRef<str>> Read for InternalReader<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read_buffered(self, buf)
+    }
+}
+
+impl<'a, S: AsRef<str>> BufRead for InternalReader<'a, S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let record = match self.records.get(self.record) {
+            Some(r) => r.as_ref().as_bytes(),
+            None => return Ok(&[]),
+        };
+        if self.pos < record.len() {
+            Ok(&record[self.pos..])
+        } else {
+            Ok(b"\n")
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let len = match self.records.get(self.record) {
+            Some(r) => r.as_ref().len(),
+            None => return,
+        };
+        self.pos += amt;
+        if self.pos > len {
+            self.record += 1;
+            self.pos = 0;
+        }
+    }
+}
+
+/// Why an `InternalWriter` failed, the payload of the `io::Error` it
+/// returns. The formatted and list-directed writers report it as
+/// `WriteErr::RecordOverflow` and `WriteErr::EndOfFile`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InternalError {
+    /// A record is longer than the records of the file.
+    RecordOverflow,
+    /// A record is written past the last element.
+    EndOfFile,
+}
+
+impl fmt::Display for InternalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InternalError::RecordOverflow => f.write_str("record longer than the internal file record"),
+            InternalError::EndOfFile => f.write_str("end of internal file"),
+        }
+    }
+}
+
+impl Error for InternalError {}
+
+/// The records of an internal file being written, every newline
+/// written ends a record. Each record is blank-padded to `len`
+/// characters, a longer record or one past the last element is an
+/// error. Elements past the last record written are left unchanged.
+pub struct InternalWriter<'a> {
+    records: &'a mut [String],
+    len: usize,
+    record: usize,
+    // characters of the current record written so far
+    column: usize,
+}
+
+impl<'a> InternalWriter<'a> {
+    pub fn new(records: &'a mut [String], len: usize) -> InternalWriter<'a> {
+        InternalWriter {
+            records,
+            len,
+            record: 0,
+            column: 0,
+        }
+    }
+
+    /// An internal file of the single record `record`.
+    pub fn single(record: &'a mut String, len: usize) -> InternalWriter<'a> {
+        InternalWriter::new(slice::from_mut(record), len)
+    }
+
+    /// Number of records written, including one that was started
+    /// but not ended by a newline.
+    pub fn records(&self) -> usize {
+        self.record + if self.column > 0 { 1 } else { 0 }
+    }
+
+    /// Append `text` to the current record, keeping it padded.
+    /// An empty `text` only starts the record if `start` is set.
+    fn put(&mut self, text: &str, start: bool) -> io::Result<()> {
+        if text.is_empty() && !start {
+            return Ok(());
+        }
+        let len = self.len;
+        let column = self.column;
+        let chars = text.chars().count();
+        if column + chars > len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, InternalError::RecordOverflow));
+        }
+        let record = match self.records.get_mut(self.record) {
+            Some(r) => r,
+            None => return Err(io::Error::new(io::ErrorKind::WriteZero, InternalError::EndOfFile)),
+        };
+        if column == 0 {
+            record.clear();
+        } else {
+            // drop the padding, which is all blanks
+            let end = record.len() - (len - column);
+            record.truncate(end);
+        }
+        record.push_str(text);
+        record.extend(::std::iter::repeat_n(' ', len - column - chars));
+        self.column += chars;
+        Ok(())
+    }
+
+    fn end_record(&mut self) -> io::Result<()> {
+        if self.column == 0 {
+            // an empty record is all blanks
+            self.put("", true)?;
+        }
+        self.record += 1;
+        self.column = 0;
+        Ok(())
+    }
+}
+
+impl<'a> Write for InternalWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = ::std::str::from_utf8(buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut lines = text.split('\n');
+        if let Some(first) = lines.next() {
+            self.put(first, false)?;
+        }
+        for line in lines {
+            self.end_record()?;
+            self.put(line, false)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::format::parse_format;
+    use ::internal::{InternalReader, InternalWriter};
+    use ::read::{FortranDefaultReader, FortranIterReader};
+    use ::write::{FortranDefaultWriter, FortranIterWriter, WriteErr};
+
+    #[test]
+    fn write_records() {
+        let fmt = parse_format("(I5.5)").unwrap();
+        let mut line = String::new();
+        {
+            let mut out = InternalWriter::single(&mut line, 8);
+            let mut writer = FortranIterWriter::new(&fmt);
+            writer.write_value(&mut out, &42).unwrap();
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        assert_eq!(line, "00042   ");
+
+        let fmt = parse_format("(2I3)").unwrap();
+        let mut lines = vec!["unchanged".to_string(); 4];
+        {
+            let mut out = InternalWriter::new(&mut lines, 6);
+            {
+                let mut writer = FortranIterWriter::new(&fmt);
+                writer.write_value(&mut out, &vec![1, 2, 3]).unwrap();
+                writer.write_constants(&mut out, false).unwrap();
+            }
+            assert_eq!(out.records(), 2);
+        }
+        assert_eq!(lines, ["  1  2", "  3   ", "unchanged", "unchanged"]);
+    }
+
+    #[test]
+    fn write_overflow() {
+        let fmt = parse_format("(I3, '/', I3)").unwrap();
+        let mut line = String::new();
+        let mut out = InternalWriter::single(&mut line, 5);
+        let mut writer = FortranIterWriter::new(&fmt);
+        writer.write_value(&mut out, &vec![1, 2]).unwrap();
+        match writer.write_constants(&mut out, false) {
+            Err(WriteErr::RecordOverflow) => {},
+            x => panic!("expected RecordOverflow, got {:?}", x),
+        }
+
+        // past the last record
+        let fmt = parse_format("(I3)").unwrap();
+        let mut lines = vec![String::new()];
+        let mut out = InternalWriter::new(&mut lines, 4);
+        let mut writer = FortranIterWriter::new(&fmt);
+        writer.write_value(&mut out, &1).unwrap();
+        writer.write_constants(&mut out, true).unwrap();
+        writer.write_value(&mut out, &2).unwrap();
+        match writer.write_constants(&mut out, false) {
+            Err(WriteErr::EndOfFile) => {},
+            x => panic!("expected EndOfFile, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn list_directed() {
+        let mut lines = vec![String::new(), String::new()];
+        {
+            let mut out = InternalWriter::new(&mut lines, 30);
+            let mut writer = FortranDefaultWriter::new();
+            writer.write_value(&mut out, &1).unwrap();
+            writer.write_value(&mut out, &2.5f32).unwrap();
+            writer.write_value(&mut out, &"\nx".to_string()).unwrap();
+        }
+        assert_eq!(lines[0], format!("{:>12}{:>15}   ", 1, "2.500000"));
+        assert_eq!(lines[1], format!("{:<30}", "x"));
+
+        let records = ["1, 2", "3"];
+        let mut inp = InternalReader::new(&records[..]);
+        let mut vals = [0i32; 3];
+        {
+            let mut reader = FortranDefaultReader::new(&mut inp);
+            reader.read_value(&mut &mut vals[..]).unwrap();
+        }
+        assert_eq!(vals, [1, 2, 3]);
+    }
+
+    #[test]
+    fn read_records() {
+        let fmt = parse_format("(3I3)").unwrap();
+        let records = vec!["  1  2".to_string(), "  3".to_string()];
+        let mut inp = InternalReader::new(&records);
+        let mut vals = vec![-1i32; 6];
+        {
+            let mut reader = FortranIterReader::new(&fmt, &mut inp);
+            reader.read_value(&mut vals).unwrap();
+            reader.consume_constants(false).unwrap();
+        }
+        // records are padded with blanks
+        assert_eq!(vals, [1, 2, 0, 3, 0, 0]);
+
+        let field = "  3.25";
+        let fmt = parse_format("(F6.2)").unwrap();
+        let mut inp = InternalReader::single(&field);
+        let mut x = 0.0f64;
+        FortranIterReader::new(&fmt, &mut inp).read_value(&mut x).unwrap();
+        assert_eq!(x, 3.25);
+    }
+}
@@ -19,10 +19,7 @@ impl<'a> Iterator for FormatEvalIter<'a> {
         use self::FormatIterCmd::*;
         use format::FormatNode::*;
         loop {
-            let last = match self.stack.pop() {
-                Some(l) => l,
-                None => return None,
-            };
+            let last = self.stack.pop()?;
             match last {
                 Index(pos, slice) => {
                     if pos < slice.len() {
@@ -38,11 +35,14 @@ impl<'a> Iterator for FormatEvalIter<'a> {
                 },
                 Walk(node) => {
                     match node {
-                        &Group(ref v) => {
-                            self.stack.push(Index(0, &v));
+                        Group(v) => {
+                            self.stack.push(Index(0, v));
                         },
-                        &Repeat(r, ref node) => {
-                            self.stack.push(Iteration(r, &node));
+                        Repeat(r, node) => {
+                            self.stack.push(Iteration(*r, node));
+                        },
+                        UnlimitedRepeat(node) => {
+                            self.stack.push(Iteration(usize::MAX, node));
                         },
                         x => return Some(x),
                     }
@@ -58,7 +58,7 @@ impl<'a> IntoIterator for &'a FormatNode {
 
     fn into_iter(self) -> FormatEvalIter<'a> {
         FormatEvalIter {
-            stack: vec![FormatIterCmd::Walk(&self)],
+            stack: vec![FormatIterCmd::Walk(self)],
         }
     }
 }
@@ -0,0 +1,284 @@
+//! A small JSON reader and writer
+//!
+//! Enough for record layout schemas and for converting records to
+//! JSON lines, without pulling a serialization framework in.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    /// Members in the order of the source.
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonError {
+    UnexpectedEOF(usize),
+    UnexpectedChar(usize, char),
+    BadNumber(usize),
+    BadEscape(usize),
+}
+
+impl Json {
+    pub fn parse(src: &str) -> Result<Json, JsonError> {
+        let mut parser = JsonParser { chars: src.chars().collect(), pos: 0 };
+        let value = parser.value()?;
+        parser.skip_blanks();
+        match parser.peek() {
+            Some(c) => Err(JsonError::UnexpectedChar(parser.pos, c)),
+            None => Ok(value),
+        }
+    }
+
+    /// The member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => members.iter().find(|m| m.0 == key).map(|m| &m.1),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::Str(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// The value if it is a whole number that fits.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Json::Number(x) if x.fract() == 0.0 && x.abs() < 9.007_199_254_740_992e15 => Some(x as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl JsonParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Result<char, JsonError> {
+        let c = self.peek().ok_or(JsonError::UnexpectedEOF(self.pos))?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn skip_blanks(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), JsonError> {
+        self.skip_blanks();
+        let at = self.pos;
+        match self.next()? {
+            x if x == c => Ok(()),
+            x => Err(JsonError::UnexpectedChar(at, x)),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        for w in word.chars() {
+            let at = self.pos;
+            let c = self.next()?;
+            if c != w {
+                return Err(JsonError::UnexpectedChar(at, c));
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_blanks();
+        match self.peek() {
+            None => Err(JsonError::UnexpectedEOF(self.pos)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('"') => self.string().map(Json::Str),
+            Some('[') => {
+                self.pos += 1;
+                let mut items = vec![];
+                self.skip_blanks();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_blanks();
+                    let at = self.pos;
+                    match self.next()? {
+                        ',' => {},
+                        ']' => return Ok(Json::Array(items)),
+                        c => return Err(JsonError::UnexpectedChar(at, c)),
+                    }
+                }
+            },
+            Some('{') => {
+                self.pos += 1;
+                let mut members = vec![];
+                self.skip_blanks();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_blanks();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    members.push((key, self.value()?));
+                    self.skip_blanks();
+                    let at = self.pos;
+                    match self.next()? {
+                        ',' => {},
+                        '}' => return Ok(Json::Object(members)),
+                        c => return Err(JsonError::UnexpectedChar(at, c)),
+                    }
+                }
+            },
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(JsonError::UnexpectedChar(self.pos, c)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().map(Json::Number).map_err(|_| JsonError::BadNumber(start))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let at = self.pos;
+            match self.next()? {
+                '"' => return Ok(s),
+                '\\' => {
+                    let c = match self.next()? {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\x08',
+                        'f' => '\x0c',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let hex: String = self.chars.get(self.pos..self.pos + 4)
+                                .ok_or(JsonError::BadEscape(at))?.iter().collect();
+                            self.pos += 4;
+                            u32::from_str_radix(&hex, 16).ok().and_then(::std::char::from_u32)
+                                .ok_or(JsonError::BadEscape(at))?
+                        },
+                        _ => return Err(JsonError::BadEscape(at)),
+                    };
+                    s.push(c);
+                },
+                c => s.push(c),
+            }
+        }
+    }
+}
+
+/// Write `s` as a JSON string.
+pub fn write_str(f: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Written on one line, numbers that are not finite as `null`.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(x) if x.is_finite() => write!(f, "{}", x),
+            Json::Number(_) => f.write_str("null"),
+            Json::Str(ref s) => write_str(f, s),
+            Json::Array(ref v) => {
+                f.write_str("[")?;
+                for (i, x) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", x)?;
+                }
+                f.write_str("]")
+            },
+            Json::Object(ref members) => {
+                f.write_str("{")?;
+                for (i, (k, x)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_str(f, k)?;
+                    write!(f, ":{}", x)?;
+                }
+                f.write_str("}")
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::json::{Json, JsonError};
+
+    #[test]
+    fn parse_and_write() {
+        let src = r#" {"a": [1, -2.5e1, true, null], "b\n": "x\"é", "c": {}} "#;
+        let json = Json::parse(src).unwrap();
+        assert_eq!(json.get("a").unwrap().as_array().unwrap()[1].as_f64(), Some(-25.0));
+        assert_eq!(json.get("b\n").unwrap().as_str(), Some("x\"\u{e9}"));
+        assert_eq!(json.get("a").unwrap().as_array().unwrap()[0].as_i64(), Some(1));
+        assert_eq!(json.to_string(), r#"{"a":[1,-25,true,null],"b\n":"x\"é","c":{}}"#);
+
+        assert_eq!(Json::parse("[1,]"), Err(JsonError::UnexpectedChar(3, ']')));
+        assert_eq!(Json::parse("{\"a\" 1}"), Err(JsonError::UnexpectedChar(5, '1')));
+        assert_eq!(Json::parse("[1] x"), Err(JsonError::UnexpectedChar(4, 'x')));
+        assert_eq!(Json::parse("\"abc"), Err(JsonError::UnexpectedEOF(4)));
+    }
+}
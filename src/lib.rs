@@ -1,6 +1,20 @@
 pub mod format;
 pub mod types;
 pub mod iter;
+pub mod program;
 pub mod write;
 pub mod read;
+pub mod decimal;
+pub mod internal;
+pub mod carriage;
+pub mod positioned;
+pub mod units;
+pub mod sequential;
+pub mod direct;
+pub mod stream;
+pub mod value;
+pub mod decl;
+pub mod json;
+pub mod schema;
+pub mod source;
 pub mod macros;
@@ -2,10 +2,10 @@
 macro_rules! f77_write_star {
     ($out: expr, $($val: expr),*) => {{
         use ::std::io::Write;
-        use $crate::write::{FortranWrite};
+        let mut writer = $crate::write::FortranDefaultWriter::new();
         Ok(())
         $(
-            .and_then(|_| FortranWrite::fortran_write_default(&$val, $out))
+            .and_then(|_| writer.write_value($out, &$val))
         )*
             .and_then(|_| $out.write_all(b"\n").map_err(|e|e.into()))
     }}
@@ -23,6 +23,23 @@ macro_rules! f77_write {
         f77_write!(&mut stdout, $out, $($val),*)
     }};
 
+    // a unit number, `unit = n` takes any expression
+    ($unit: literal, $($rest: tt)*) => {{
+        f77_write!(unit = $unit, $($rest)*)
+    }};
+
+    (unit = $unit: expr, *, $($val: expr),*) => {{
+        $crate::units::with_unit($unit, |unit| f77_write!(unit, *, $($val),*))
+            .map_err($crate::write::WriteErr::from)
+            .and_then(|res| res)
+    }};
+
+    (unit = $unit: expr, $src: expr, $($val: expr),*) => {{
+        $crate::units::with_unit($unit, |unit| f77_write!(*unit, $src, $($val),*))
+            .map_err($crate::write::WriteErr::from)
+            .and_then(|res| res)
+    }};
+
     ($out: expr, *, $($val: expr),*) => {{
         f77_write_star!($out, $($val),*)
     }};
@@ -40,6 +57,36 @@ macro_rules! f77_write {
     }}
 }
 
+/// Edit values into a `String` like `f77_write!`, without the newline
+/// ending the last record.
+#[macro_export]
+macro_rules! fortran_format {
+    (*, $($val: expr),*) => {{
+        let mut out: Vec<u8> = Vec::new();
+        let res = f77_write!(&mut out, *, $($val),*);
+        fortran_format!(@string out, res)
+    }};
+
+    (@string $out: ident, $res: expr) => {{
+        let mut out = $out;
+        match $res {
+            Ok(()) => {
+                if out.last() == Some(&b'\n') {
+                    out.pop();
+                }
+                Ok(String::from_utf8_lossy(&out).into_owned())
+            },
+            Err(e) => Err(e),
+        }
+    }};
+
+    ($src: expr, $($val: expr),*) => {{
+        let mut out: Vec<u8> = Vec::new();
+        let res = f77_write!(out, $src, $($val),*);
+        fortran_format!(@string out, res)
+    }};
+}
+
 #[macro_export]
 macro_rules! f77_read_star {
     ($inp: expr, $($val: expr),*) => {{
@@ -62,7 +109,24 @@ macro_rules! f77_read {
     (*, $inp: expr, $($val: expr),*) => {{
         use ::std::io::BufReader;
         let mut stdin = BufReader::new(::std::io::stdin());
-        f77_read!(stdin, $src, $($val),*)
+        f77_read!(stdin, $inp, $($val),*)
+    }};
+
+    // a unit number, `unit = n` takes any expression
+    ($unit: literal, $($rest: tt)*) => {{
+        f77_read!(unit = $unit, $($rest)*)
+    }};
+
+    (unit = $unit: expr, *, $($val: expr),*) => {{
+        $crate::units::with_unit($unit, |unit| f77_read!(*unit, *, $($val),*))
+            .map_err($crate::read::ReadErr::from)
+            .and_then(|res| res)
+    }};
+
+    (unit = $unit: expr, $src: expr, $($val: expr),*) => {{
+        $crate::units::with_unit($unit, |unit| f77_read!(*unit, $src, $($val),*))
+            .map_err($crate::read::ReadErr::from)
+            .and_then(|res| res)
     }};
 
     ($inp: expr, *, $($val: expr),*) => {{
@@ -71,13 +135,43 @@ macro_rules! f77_read {
 
     ($inp: expr, $src: expr, $($val: expr),*) => {{
         let fmt = $crate::format::parse_format($src).expect("Could not parse format string");
-        let inp = &mut $inp;
-        let mut reader = $crate::write::FortranIterReader::new(&fmt, &mut $inp);
+        let mut reader = $crate::read::FortranIterReader::new(&fmt, &mut $inp);
         Ok(())
         $(
-            .and_then(|_| reader.read_constants(inp, true))
-            .and_then(|_| reader.read_value(inp, &mut $val))
+            .and_then(|_| reader.consume_constants(true))
+            .and_then(|_| reader.read_value(&mut $val).map(|_| ()))
         )*
-            .and_then(|_| reader.read_constants(inp, false))
+            .and_then(|_| reader.consume_constants(false))
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use ::types::FortranTag;
+    use ::units::{close, open, rewind, OpenOptions, Status};
+    use ::write::WriteErr;
+
+    #[test]
+    fn format_string() {
+        assert_eq!(fortran_format!("(I3, '|', A)", 7, "ab".to_string()).unwrap(), "  7|ab");
+        match fortran_format!("(I3)", "abc".to_string()) {
+            Err(WriteErr::InvalidEditing(_, FortranTag::Strin)) => {},
+            x => panic!("expected InvalidEditing, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn unit_expression() {
+        let scratch = OpenOptions { status: Status::Scratch, ..OpenOptions::default() };
+        let number = 40 + 7;
+        open(number, None, scratch).unwrap();
+        f77_write!(unit = number, "(I3, A)", 12, "ab".to_string()).unwrap();
+        f77_write!(unit = number, *, 3).unwrap();
+        rewind(number).unwrap();
+        let (mut a, mut b, mut c) = (0i32, String::new(), 0i32);
+        f77_read!(unit = number, "(I3, A2)", a, b).unwrap();
+        f77_read!(unit = number, *, c).unwrap();
+        assert_eq!((a, b.as_str(), c), (12, "ab", 3));
+        close(number, false).unwrap();
+    }
+}
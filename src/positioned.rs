@@ -0,0 +1,101 @@
+//! Buffered streams that keep track of their position
+//!
+//! Sequential and stream files read through a buffer but write straight
+//! to the stream, so a write after a read has to drop what was read
+//! ahead first. A `PositionedBuf` does this and counts the offset of
+//! the next byte, which is where reading stopped.
+
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+pub struct PositionedBuf<S: Read + Seek> {
+    inner: BufReader<S>,
+    // offset of the next byte read or written
+    pos: u64,
+}
+
+impl<S: Read + Seek> PositionedBuf<S> {
+    /// A buffer positioned where `inner` is.
+    pub fn new(mut inner: S) -> io::Result<PositionedBuf<S>> {
+        let pos = inner.stream_position()?;
+        Ok(PositionedBuf {
+            inner: BufReader::new(inner),
+            pos,
+        })
+    }
+
+    /// Offset of the next byte read or written.
+    pub fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    /// Go to `pos`, dropping what was read ahead.
+    pub fn seek_to(&mut self, pos: u64) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(pos))?;
+        self.pos = pos;
+        Ok(())
+    }
+
+    /// The bytes read ahead, which start at `pos()`.
+    pub fn buffer(&self) -> &[u8] {
+        self.inner.buffer()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.inner.get_ref()
+    }
+
+    /// The stream, whose position is not `pos()` if bytes were read ahead.
+    pub fn get_mut(&mut self) -> &mut S {
+        self.inner.get_mut()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner.into_inner()
+    }
+}
+
+/// `Read::read` of a `BufRead`, copying what `fill_buf` gives.
+pub fn read_buffered<R: BufRead + ?Sized>(read: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let len = {
+        let available = read.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        len
+    };
+    read.consume(len);
+    Ok(len)
+}
+
+impl<S: Read + Seek> Read for PositionedBuf<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read_buffered(self, buf)
+    }
+}
+
+impl<S: Read + Seek> BufRead for PositionedBuf<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.pos += amt as u64;
+    }
+}
+
+impl<S: Read + Write + Seek> Write for PositionedBuf<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // drop what was read ahead, so the write goes where reading stopped
+        if !self.inner.buffer().is_empty() {
+            let pos = self.pos;
+            self.seek_to(pos)?;
+        }
+        let len = self.inner.get_mut().write(buf)?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.get_mut().flush()
+    }
+}
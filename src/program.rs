@@ -0,0 +1,255 @@
+//! Format trees compiled into flat programs
+//!
+//! A `FormatProgram` lists the descriptors of a format in order, with
+//! repeats turned into loops over counters, one counter per nesting
+//! level. It is built once and shared, each reader or writer runs it
+//! with its own `ProgramCursor`.
+
+use format::*;
+
+/// One step of a `FormatProgram`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    /// A descriptor to process, never a group or a repeat.
+    Edit(FormatNode),
+    /// Start `count` iterations of the loop ending at `end`,
+    /// counted by the counter of nesting level `level`.
+    Loop { count: usize, level: usize, end: usize },
+    /// Go back to `start` while the loop of `level` has iterations left.
+    EndLoop { start: usize, level: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatProgram {
+    source: FormatNode,
+    instrs: Vec<Instr>,
+    levels: usize,
+    reversion: usize,
+}
+
+/// Position of a run of a `FormatProgram`.
+#[derive(Debug, Clone)]
+pub struct ProgramCursor {
+    pc: usize,
+    counters: Vec<usize>,
+}
+
+/// Reversion restarts at the last group at the top level,
+/// with its repeat count, or at the start if there is none.
+fn is_reversion_group(node: &FormatNode) -> bool {
+    use format::FormatNode::*;
+    match *node {
+        Group(_) | UnlimitedRepeat(_) => true,
+        Repeat(_, ref n) => matches!(**n, Group(_)),
+        _ => false,
+    }
+}
+
+impl FormatProgram {
+    pub fn compile(fmt: &FormatNode) -> FormatProgram {
+        let mut program = FormatProgram {
+            source: fmt.clone(),
+            instrs: vec![],
+            levels: 0,
+            reversion: 0,
+        };
+        match *fmt {
+            FormatNode::Group(ref v) => {
+                for node in v {
+                    if is_reversion_group(node) {
+                        program.reversion = program.instrs.len();
+                    }
+                    program.emit(node, 0);
+                }
+            },
+            ref node => program.emit(node, 0),
+        }
+        program
+    }
+
+    fn emit(&mut self, node: &FormatNode, level: usize) {
+        use format::FormatNode::*;
+        match *node {
+            Group(ref v) => {
+                for n in v {
+                    self.emit(n, level);
+                }
+            },
+            Repeat(count, ref n) => self.emit_loop(count, n, level),
+            UnlimitedRepeat(ref n) => self.emit_loop(usize::MAX, n, level),
+            ref n => self.instrs.push(Instr::Edit(n.clone())),
+        }
+    }
+
+    fn emit_loop(&mut self, count: usize, body: &FormatNode, level: usize) {
+        self.levels = self.levels.max(level + 1);
+        let start = self.instrs.len();
+        self.instrs.push(Instr::Loop { count, level, end: 0 });
+        self.emit(body, level + 1);
+        let end = self.instrs.len();
+        self.instrs.push(Instr::EndLoop { start: start + 1, level });
+        self.instrs[start] = Instr::Loop { count, level, end };
+    }
+
+    /// The format this program was compiled from.
+    pub fn source(&self) -> &FormatNode {
+        &self.source
+    }
+
+    pub fn instructions(&self) -> &[Instr] {
+        &self.instrs
+    }
+
+    /// Whether the instruction at `pc` is inside an unlimited repeat.
+    pub fn is_unlimited(&self, pc: usize) -> bool {
+        self.instrs[..pc].iter().any(|i| match *i {
+            Instr::Loop { count, end, .. } => count == usize::MAX && end > pc,
+            _ => false,
+        })
+    }
+
+    /// Index of the instruction format reversion continues at.
+    pub fn reversion(&self) -> usize {
+        self.reversion
+    }
+
+    /// A cursor at the start of the program. This is the only
+    /// allocation needed to run it.
+    pub fn cursor(&self) -> ProgramCursor {
+        ProgramCursor {
+            pc: 0,
+            counters: vec![0; self.levels],
+        }
+    }
+
+    /// Rebuild the source format, replacing the descriptor of every
+    /// `Instr::Edit` by `f` of its index and the descriptor.
+    pub fn map_edits<F>(&self, mut f: F) -> FormatNode
+        where F: FnMut(usize, &FormatNode) -> FormatNode
+    {
+        // walks the tree in the order `emit` does
+        fn walk<F>(node: &FormatNode, pc: &mut usize, f: &mut F) -> FormatNode
+            where F: FnMut(usize, &FormatNode) -> FormatNode
+        {
+            use format::FormatNode::*;
+            match *node {
+                Group(ref v) => Group(v.iter().map(|n| walk(n, pc, f)).collect()),
+                Repeat(count, ref n) => {
+                    *pc += 1;
+                    let body = walk(n, pc, f);
+                    *pc += 1;
+                    Repeat(count, Box::new(body))
+                },
+                UnlimitedRepeat(ref n) => {
+                    *pc += 1;
+                    let body = walk(n, pc, f);
+                    *pc += 1;
+                    UnlimitedRepeat(Box::new(body))
+                },
+                ref n => {
+                    let rv = f(*pc, n);
+                    *pc += 1;
+                    rv
+                },
+            }
+        }
+        walk(&self.source, &mut 0, &mut f)
+    }
+}
+
+impl ProgramCursor {
+    /// Run the loop instructions up to the next descriptor.
+    fn settle(&mut self, program: &FormatProgram) {
+        while let Some(instr) = program.instrs.get(self.pc) {
+            match *instr {
+                Instr::Edit(_) => return,
+                Instr::Loop { count, level, end } => {
+                    self.counters[level] = count;
+                    self.pc = if count == 0 { end + 1 } else { self.pc + 1 };
+                },
+                Instr::EndLoop { start, level } => {
+                    self.counters[level] -= 1;
+                    self.pc = if self.counters[level] > 0 { start } else { self.pc + 1 };
+                },
+            }
+        }
+    }
+
+    /// The next descriptor, `None` at the end of the program.
+    pub fn peek<'p>(&mut self, program: &'p FormatProgram) -> Option<&'p FormatNode> {
+        self.settle(program);
+        match program.instrs.get(self.pc) {
+            Some(Instr::Edit(n)) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn next<'p>(&mut self, program: &'p FormatProgram) -> Option<&'p FormatNode> {
+        let rv = self.peek(program);
+        if rv.is_some() {
+            self.pc += 1;
+        }
+        rv
+    }
+
+    /// Index of the instruction `next` returns, valid after `peek`.
+    pub fn position(&self) -> usize {
+        self.pc
+    }
+
+    /// Continue at the reversion point after the end of the program.
+    pub fn revert(&mut self, program: &FormatProgram) {
+        self.pc = program.reversion;
+    }
+
+    pub fn reset(&mut self) {
+        self.pc = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::format::{parse_format, FormatNode};
+    use ::program::FormatProgram;
+
+    fn run(src: &str, n: usize) -> String {
+        let program = FormatProgram::compile(&parse_format(src).unwrap());
+        let mut cursor = program.cursor();
+        let mut out = vec![];
+        for _ in 0..n {
+            match cursor.next(&program) {
+                Some(node) => out.push(node.to_string()),
+                None => {
+                    out.push("/".to_string());
+                    cursor.revert(&program);
+                },
+            }
+        }
+        out.join(" ")
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(run("(I1, 2(I2, 2I3), I4)", 9), "I1 I2 I3 I3 I2 I3 I3 I4 /");
+        assert_eq!(run("(A, *(I2, :))", 5), "A I2 : I2 :");
+    }
+
+    #[test]
+    fn reversion() {
+        assert_eq!(run("(I1, 2(I2), I3)", 8), "I1 I2 I2 I3 / I2 I2 I3");
+        assert_eq!(run("(I1, (I2), 3I3)", 8), "I1 I2 I3 I3 I3 / I2 I3");
+        assert_eq!(run("(I1, 2I2)", 6), "I1 I2 I2 / I1 I2");
+    }
+
+    #[test]
+    fn shared() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        let program = FormatProgram::compile(&parse_format("(3(F5.1, 'x'))").unwrap());
+        assert_send_sync(&program);
+        let rebuilt = program.map_edits(|_, n| match *n {
+            FormatNode::Literal(_) => FormatNode::Literal("y".to_string()),
+            ref n => n.clone(),
+        });
+        assert_eq!(rebuilt.to_string(), "(3(F5.1, 'y'))");
+    }
+}
@@ -1,43 +1,125 @@
+use std::cmp::{Ordering};
 use std::io::{BufRead};
-use std::iter::{Peekable};
+use std::borrow::{Cow};
+use std::str::{FromStr};
 use format::*;
 use types::*;
-use iter::*;
+use program::*;
+use decimal::{cmp_digits, exact_digits, parse_digits, EditBuf};
+use value::FortranValue;
 
 pub struct ReaderOpts {
     terminated: bool,
     suppress_newline: bool,
+    blank_zero: bool,
     scale: isize,
     radix: usize,
+    round: RoundType,
+    decimal: DecimalType,
+}
+
+/// Settings of a `FortranIterReader` or `FortranDefaultReader` chosen by the caller.
+#[derive(Debug, Clone)]
+pub struct ReaderConfig {
+    /// Fail with `ReadErr::InvalidEditing` when an item's type does not
+    /// match its data edit descriptor. When unset, the field is converted
+    /// the way ifort does: integers and reals are converted into each
+    /// other, O and Z fill the bits of a real, logicals are edited as
+    /// integers and vice versa. Characters are never coerced.
+    ///
+    /// Hollerith descriptors in an input format are an error in strict
+    /// mode; otherwise they take their characters from the record, see
+    /// `FortranIterReader::hollerith_format`.
+    pub strict: bool,
+    /// The decimal symbol expected in reals, DC and DP descriptors
+    /// switch it within a format. List-directed input under DC is
+    /// separated by semicolons instead of commas.
+    pub decimal: DecimalType,
+}
+
+impl Default for ReaderConfig {
+    fn default() -> ReaderConfig {
+        ReaderConfig {
+            strict: true,
+            decimal: DecimalType::DP,
+        }
+    }
 }
 
 pub struct FortranIterReader<'a, R: 'a+BufRead> {
-    iter: Peekable<FormatEvalIter<'a>>,
+    program: Cow<'a, FormatProgram>,
+    cursor: ProgramCursor,
     line: String,
     line_pos: usize,
+    has_record: bool,
     consumed_data: bool,
     read: &'a mut R,
-    node: &'a FormatNode,
     opts: ReaderOpts,
+    config: ReaderConfig,
+    // Hollerith fields read so far, keyed by their instruction
+    hollerith: Vec<(usize, String)>,
+    // ADVANCE= and SIZE= of the current statement, and whether
+    // a non-advancing read ran past the end of the record
+    advance: bool,
+    size: usize,
+    eor: bool,
+    // records read from the input
+    records: usize,
 }
 
 pub struct FortranDefaultReader<'a, R: 'a+BufRead> {
     line: String,
     line_pos: usize,
     read: &'a mut R,
+    config: ReaderConfig,
 }
 
 #[derive(Debug)]
 pub enum ReadErr {
     IoErr(::std::io::Error),
     ParseIntError(::std::num::ParseIntError),
+    ParseFloatError(::std::num::ParseFloatError),
+    ParseHexFloatError,
     ParseBoolError,
     UnexpectedLiteral,
+    UnexpectedHollerith,
     NoDataEditings,
+    /// The input ended before the record an item is read from.
+    EndOfFile,
+    /// An error in the given line of the input, counted from one.
+    AtLine(usize, Box<ReadErr>),
+    /// A non-advancing read needed more characters than the record had.
+    /// The item was read from the record padded with blanks, the next
+    /// statement starts at the following record.
+    EndOfRecord,
+    ZeroWidthInput,
     InvalidState,
     InvalidEditing(FormatNode, FortranTag),
 }
 
+/// Which of the END=, EOR= and ERR= branches of a READ statement
+/// an error takes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReadErrKind {
+    EndOfFile,
+    EndOfRecord,
+    /// Any other error: a field that cannot be read, a format that
+    /// does not fit the items, or an I/O error.
+    Data,
+}
+
+impl ReadErr {
+    pub fn kind(&self) -> ReadErrKind {
+        match *self {
+            ReadErr::EndOfFile => ReadErrKind::EndOfFile,
+            ReadErr::IoErr(ref e) if e.kind() == ::std::io::ErrorKind::UnexpectedEof => ReadErrKind::EndOfFile,
+            ReadErr::EndOfRecord => ReadErrKind::EndOfRecord,
+            ReadErr::AtLine(_, ref e) => e.kind(),
+            _ => ReadErrKind::Data,
+        }
+    }
+}
+
 impl From<::std::io::Error> for ReadErr {
     fn from(x: ::std::io::Error) -> ReadErr {
         ReadErr::IoErr(x)
@@ -50,16 +132,148 @@ impl From<::std::num::ParseIntError> for ReadErr {
     }
 }
 
+impl From<::std::num::ParseFloatError> for ReadErr {
+    fn from(x: ::std::num::ParseFloatError) -> ReadErr {
+        ReadErr::ParseFloatError(x)
+    }
+}
+
 pub trait FortranRead {
     fn fortran_read<R: BufRead>(&mut self, reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr>;
     fn fortran_read_default<R: BufRead>(&mut self, reader: &mut FortranDefaultReader<R>) -> Result<bool, ReadErr>;
 }
 
+fn parse_bool(field: &str) -> Result<bool, ReadErr> {
+    let mut clear = field.chars()
+        .filter(|&c| !c.is_whitespace())
+        .skip_while(|&c| c == '.');
+    match clear.next() {
+        Some('T') | Some('t') => Ok(true),
+        Some('F') | Some('f') | None => Ok(false),
+        _ => Err(ReadErr::ParseBoolError),
+    }
+}
+
+/// Parse a real field with blanks already removed. Without a decimal point
+/// the last `d` digits are the fraction; without an exponent the scale
+/// factor `scale` divides the value by a power of ten.
+fn parse_real<T: FromStr>(field: &str, d: usize, scale: isize) -> Result<T, ReadErr>
+    where ReadErr: From<T::Err>
+{
+    if field.is_empty() {
+        return Ok("0".parse()?);
+    }
+
+    let unsigned = field.trim_start_matches(['+', '-']);
+    if unsigned.starts_with("0X") || unsigned.starts_with("0x") {
+        let val = parse_hex_real(&unsigned[2..])?;
+        let sign = if field.starts_with('-') { "-" } else { "" };
+        return Ok(format!("{}{:e}", sign, val).parse()?);
+    }
+    if unsigned.starts_with(|c: char| c.is_alphabetic()) {
+        // Infinity and NaN
+        return Ok(field.parse()?);
+    }
+
+    let (mantissa, shift) = split_decimal(field, d, scale)?;
+    Ok(format!("{}e{}", mantissa, shift).parse()?)
+}
+
+/// Split a decimal real field into the mantissa and the power of ten
+/// it is multiplied with, after the implied decimal point and scale factor.
+fn split_decimal(field: &str, d: usize, scale: isize) -> Result<(&str, isize), ReadErr> {
+    // the exponent either follows a letter or is a bare signed number
+    let unsigned = field.trim_start_matches(['+', '-']);
+    let sign_len = field.len() - unsigned.len();
+    let (mantissa, exponent) = match unsigned.find(|c| "EeDdQq+-".contains(c)) {
+        Some(p) => {
+            let p = sign_len + p;
+            let exponent = field[p..].trim_start_matches(|c| "EeDdQq".contains(c));
+            (&field[..p], Some(exponent.parse::<isize>()?))
+        },
+        None => (field, None),
+    };
+
+    let mut shift = exponent.unwrap_or(-scale);
+    if !mantissa.contains('.') {
+        shift -= d as isize;
+    }
+    Ok((mantissa, shift))
+}
+
+/// Move `val`, the real nearest to a decimal field, to its neighbour
+/// when a directed rounding mode wants the other side of the field value.
+/// RC and RP read like RN, they only differ for exact ties.
+fn round_directed<T: RealBits>(val: T, field: &str, d: usize, scale: isize, round: RoundType) -> Result<T, ReadErr> {
+    let v: f64 = val.into();
+    let unsigned = field.trim_start_matches(['+', '-']);
+    if !v.is_finite() || !unsigned.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        || unsigned.starts_with("0X") || unsigned.starts_with("0x") {
+        return Ok(val);
+    }
+    let negative = field.starts_with('-');
+    let toward_zero = match round {
+        RoundType::RZ => true,
+        RoundType::RU => negative,
+        RoundType::RD => !negative,
+        _ => return Ok(val),
+    };
+    let (mantissa, shift) = split_decimal(field, d, scale)?;
+    let (digits, x) = parse_digits(mantissa, shift);
+    let mut exact = EditBuf::new();
+    let ex = exact_digits(v.abs(), &mut exact);
+    Ok(match cmp_digits((exact.as_str(), ex), (&digits, x)) {
+        Ordering::Greater if toward_zero => val.step(false),
+        Ordering::Less if !toward_zero => val.step(true),
+        _ => val,
+    })
+}
+
+/// Parse the hexadecimal significand and binary exponent of EX editing,
+/// e.g. `1.8P+1`, following the `0X` prefix.
+fn parse_hex_real(field: &str) -> Result<f64, ReadErr> {
+    let (significand, exp) = match field.find(['P', 'p']) {
+        Some(p) => (&field[..p], field[p + 1..].parse::<i32>()?),
+        None => (field, 0),
+    };
+    let mut mantissa = 0u64;
+    let mut shift = exp;
+    let mut frac = false;
+    for c in significand.chars() {
+        if c == '.' && !frac {
+            frac = true;
+            continue;
+        }
+        let digit = c.to_digit(16).ok_or(ReadErr::ParseHexFloatError)?;
+        if mantissa >> 60 == 0 {
+            mantissa = mantissa << 4 | digit as u64;
+            if frac {
+                shift -= 4;
+            }
+        } else if !frac {
+            // digits beyond the precision of the mantissa
+            shift += 4;
+        }
+    }
+    Ok(mantissa as f64 * 2f64.powi(shift))
+}
+
+// In lenient mode the arms below the standard editings convert the field
+// the way ifort does; in strict mode `next_data` rejects them beforehand.
+
 macro_rules! impl_bool_read {
     ($ty: ty) => {
         impl FortranRead for $ty {
-            fn fortran_read<R: BufRead>(&mut self, _reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
-                Ok(false)
+            fn fortran_read<R: BufRead>(&mut self, reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
+                let tag = <$ty as FortranAltType>::fortran_tag();
+                *self = match *reader.next_data(tag)? {
+                    FormatNode::Bool(ow) => parse_bool(&reader.read_field(Some(ow.unwrap_or(2)))?)?,
+                    FormatNode::Real(RealFormat::G, w, _, _) => parse_bool(&reader.read_field(Some(w))?)?,
+                    // ifort only tests the low bit of an integer used as a logical
+                    FormatNode::Int(t, w, _) => (reader.read_int::<i64>(t, w)? & 1) != 0,
+                    ref n => return Err(ReadErr::InvalidEditing(n.clone(), tag)),
+                };
+                Ok(true)
             }
 
             fn fortran_read_default<R: BufRead>(&mut self, reader: &mut FortranDefaultReader<R>) -> Result<bool, ReadErr> {
@@ -87,14 +301,27 @@ impl_bool_read!(bool);
 macro_rules! impl_int_read {
     ($ty: ty, $w: expr) => {
         impl FortranRead for $ty {
-            fn fortran_read<R: BufRead>(&mut self, _reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
-                Ok(false)
+            fn fortran_read<R: BufRead>(&mut self, reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
+                let tag = <$ty as FortranAltType>::fortran_tag();
+                *self = match *reader.next_data(tag)? {
+                    FormatNode::Int(t, w, _) => reader.read_int(t, w)?,
+                    FormatNode::Real(RealFormat::G, w, _, _) => reader.read_int(IntFormat::I, w)?,
+                    FormatNode::RemainingChars => reader.remaining_chars()? as $ty,
+                    FormatNode::Real(_, w, od, _) => reader.read_real::<f64>(w, od)? as $ty,
+                    // ifort stores .TRUE. as -1
+                    FormatNode::Bool(ow) => {
+                        let val = parse_bool(&reader.read_field(Some(ow.unwrap_or(2)))?)?;
+                        if val { !0 } else { 0 }
+                    },
+                    ref n => return Err(ReadErr::InvalidEditing(n.clone(), tag)),
+                };
+                Ok(true)
             }
 
             fn fortran_read_default<R: BufRead>(&mut self, reader: &mut FortranDefaultReader<R>) -> Result<bool, ReadErr> {
                 let next = reader.read_next_csv()?;
                 let clear: String = next.chars().filter(|&c| !c.is_whitespace()).collect();
-                if clear.len() != 0 {
+                if !clear.is_empty() {
                     *self = clear.parse()?;
                     Ok(true)
                 } else {
@@ -114,24 +341,52 @@ impl_int_read! { u32, 12 }
 impl_int_read! { u16, 7 }
 impl_int_read! { u8, 5 }
 
-macro_rules! impl_float_write {
-    ($ty: ty, $w: expr, $d: expr, $e: expr) => {
+macro_rules! impl_float_read {
+    ($ty: ty, $bits: ty) => {
         impl FortranRead for $ty {
-            fn fortran_read(&self, reader: &mut FortranIterReader) -> Result<(), ReadErr> {
+            fn fortran_read<R: BufRead>(&mut self, reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
+                let tag = <$ty as FortranAltType>::fortran_tag();
+                *self = match *reader.next_data(tag)? {
+                    FormatNode::Real(_, w, od, _) => reader.read_real(w, od)?,
+                    // ifort converts an integer field to a real,
+                    // O and Z fill its internal representation
+                    FormatNode::Int(IntFormat::I, w, _) => reader.read_int::<i64>(IntFormat::I, w)? as $ty,
+                    FormatNode::Int(t, w, _) => <$ty>::from_bits(reader.read_int::<$bits>(t, w)?),
+                    ref n => return Err(ReadErr::InvalidEditing(n.clone(), tag)),
+                };
+                Ok(true)
             }
 
-            fn fortran_read_default(&self, reader: &mut FortranIterReader) -> Result<(), ReadErr> {
+            fn fortran_read_default<R: BufRead>(&mut self, reader: &mut FortranDefaultReader<R>) -> Result<bool, ReadErr> {
+                let next = reader.read_next_csv()?;
+                let mut clear: String = next.chars().filter(|&c| !c.is_whitespace()).collect();
+                if reader.config.decimal == DecimalType::DC {
+                    clear = clear.replace(',', ".");
+                }
+                if !clear.is_empty() {
+                    *self = parse_real(&clear, 0, 0)?;
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
             }
         }
     }
 }
 
-//impl_float_write! { f64, 25, 16, 2 }
-//impl_float_write! { f32, 15, 6, 2 }
-//
+impl_float_read! { f64, u64 }
+impl_float_read! { f32, u32 }
+
 impl FortranRead for String {
-    fn fortran_read<R: BufRead>(&mut self, _reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
-        Ok(false)
+    fn fortran_read<R: BufRead>(&mut self, reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
+        let tag = <String as FortranAltType>::fortran_tag();
+        // without a width, A takes the rest of the record
+        *self = match *reader.next_data(tag)? {
+            FormatNode::Str(ow) => reader.read_field(ow)?,
+            FormatNode::Real(RealFormat::G, w, _, _) => reader.read_field(Some(w))?,
+            ref n => return Err(ReadErr::InvalidEditing(n.clone(), tag)),
+        };
+        Ok(true)
     }
 
     fn fortran_read_default<R: BufRead>(&mut self, reader: &mut FortranDefaultReader<R>) -> Result<bool, ReadErr> {
@@ -141,9 +396,16 @@ impl FortranRead for String {
     }
 }
 
-impl<'a, T: FortranRead> FortranRead for &'a mut [T] {
-    fn fortran_read<R: BufRead>(&mut self, _reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
-        Ok(false)
+impl<T: FortranRead> FortranRead for &mut [T] {
+    fn fortran_read<R: BufRead>(&mut self, reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
+        let mut read = false;
+        for val in self.iter_mut() {
+            reader.consume_constants(true)?;
+            if val.fortran_read(reader)? {
+                read = true;
+            }
+        }
+        Ok(read)
     }
 
     fn fortran_read_default<R: BufRead>(&mut self, reader: &mut FortranDefaultReader<R>) -> Result<bool, ReadErr> {
@@ -158,8 +420,15 @@ impl<'a, T: FortranRead> FortranRead for &'a mut [T] {
 }
 
 impl<T: FortranRead> FortranRead for Vec<T> {
-    fn fortran_read<R: BufRead>(&mut self, _reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
-        Ok(false)
+    fn fortran_read<R: BufRead>(&mut self, reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
+        let mut read = false;
+        for val in self.iter_mut() {
+            reader.consume_constants(true)?;
+            if val.fortran_read(reader)? {
+                read = true;
+            }
+        }
+        Ok(read)
     }
 
     fn fortran_read_default<R: BufRead>(&mut self, reader: &mut FortranDefaultReader<R>) -> Result<bool, ReadErr> {
@@ -173,149 +442,573 @@ impl<T: FortranRead> FortranRead for Vec<T> {
     }
 }
 
+// a blank field or a null value is a missing item
+impl<T: FortranRead + FortranAltType + Default> FortranRead for Option<T> {
+    fn fortran_read<R: BufRead>(&mut self, reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
+        if reader.blank_field()? {
+            let tag = <T as FortranAltType>::fortran_tag();
+            let w = match *reader.next_data(tag)? {
+                FormatNode::Int(_, w, _) | FormatNode::Real(_, w, _, _) => Some(w),
+                FormatNode::Bool(ow) => Some(ow.unwrap_or(2)),
+                FormatNode::Str(ow) => ow,
+                ref n => return Err(ReadErr::InvalidEditing(n.clone(), tag)),
+            };
+            reader.read_field(w)?;
+            *self = None;
+        } else {
+            let mut val = T::default();
+            val.fortran_read(reader)?;
+            *self = Some(val);
+        }
+        Ok(true)
+    }
+
+    fn fortran_read_default<R: BufRead>(&mut self, reader: &mut FortranDefaultReader<R>) -> Result<bool, ReadErr> {
+        let mut val = T::default();
+        *self = if val.fortran_read_default(reader)? { Some(val) } else { None };
+        Ok(true)
+    }
+}
+
+// a tuple is read like a list of its elements
+macro_rules! impl_tuple_read {
+    ($($name: ident $idx: tt),*) => {
+        impl<$($name: FortranRead),*> FortranRead for ($($name,)*) {
+            fn fortran_read<R: BufRead>(&mut self, reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
+                let mut read = false;
+                $(
+                    reader.consume_constants(true)?;
+                    if self.$idx.fortran_read(reader)? {
+                        read = true;
+                    }
+                )*
+                Ok(read)
+            }
+
+            fn fortran_read_default<R: BufRead>(&mut self, reader: &mut FortranDefaultReader<R>) -> Result<bool, ReadErr> {
+                let mut read = false;
+                $(
+                    if self.$idx.fortran_read_default(reader)? {
+                        read = true;
+                    }
+                )*
+                Ok(read)
+            }
+        }
+    }
+}
+
+impl_tuple_read! { A 0 }
+impl_tuple_read! { A 0, B 1 }
+impl_tuple_read! { A 0, B 1, C 2 }
+impl_tuple_read! { A 0, B 1, C 2, D 3 }
+impl_tuple_read! { A 0, B 1, C 2, D 3, E 4 }
+impl_tuple_read! { A 0, B 1, C 2, D 3, E 4, F 5 }
+impl_tuple_read! { A 0, B 1, C 2, D 3, E 4, F 5, G 6 }
+impl_tuple_read! { A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7 }
+
 fn gives_data(n: &FormatNode) -> Result<bool, ReadErr> {
     use format::FormatNode::*;
-    let rv = match n {
-        &NewLine => false,
-        &SkipChar => false,
-        &SuppressNewLine => false,
-        &Terminate => false,
-        &BlankControl(_) => false,
-        &AbsColumn(_) => false,
-        &RelColumn(_) => false,
-        &Radix(_) => false,
-        &Scale(_) => false,
-        &Literal(_) => false,
-
-        &Str(_) => true,
-        &Bool(_) => true,
-        &Int(_, _, _) => true,
-        &Real(_, _, _, _) => true,
-        &Group(_) | &Repeat(_, _) => unreachable!(),
-        &RemainingChars => true,
+    let rv = match *n {
+        NewLine => false,
+        SkipChar => false,
+        SuppressNewLine => false,
+        Terminate => false,
+        BlankControl(_) => false,
+        AbsColumn(_) => false,
+        RelColumn(_) => false,
+        Radix(_) => false,
+        Scale(_) => false,
+        Literal(_) => false,
+        Hollerith(_) => false,
+        SignControl(_) => false,
+        RoundControl(_) => false,
+        DecimalControl(_) => false,
+
+        Str(_) => true,
+        Bool(_) => true,
+        Int(_, _, _) => true,
+        Real(_, _, _, _) => true,
+        Group(_) | Repeat(_, _) | UnlimitedRepeat(_) => unreachable!(),
+        RemainingChars => true,
     };
     Ok(rv)
 }
 
 impl<'a, R: BufRead> FortranIterReader<'a, R> {
-    pub fn new<'f>(fmt: &'f FormatNode, read: &'f mut R) -> FortranIterReader<'f, R> {
+    pub fn new(fmt: &'a FormatNode, read: &'a mut R) -> FortranIterReader<'a, R> {
+        FortranIterReader::with_config(fmt, read, ReaderConfig::default())
+    }
+
+    pub fn with_config(fmt: &'a FormatNode, read: &'a mut R, config: ReaderConfig) -> FortranIterReader<'a, R> {
+        FortranIterReader::from_cow(Cow::Owned(FormatProgram::compile(fmt)), read, config)
+    }
+
+    /// A reader running a program compiled beforehand, which
+    /// may be shared by any number of readers.
+    pub fn from_program(program: &'a FormatProgram, read: &'a mut R, config: ReaderConfig) -> FortranIterReader<'a, R> {
+        FortranIterReader::from_cow(Cow::Borrowed(program), read, config)
+    }
+
+    fn from_cow(program: Cow<'a, FormatProgram>, read: &'a mut R, config: ReaderConfig) -> FortranIterReader<'a, R> {
         FortranIterReader {
             opts: ReaderOpts {
                 terminated: false,
                 suppress_newline: false,
+                blank_zero: false,
                 scale: 0,
+                round: RoundType::RP,
+                decimal: config.decimal,
                 radix: 10,
             },
+            config,
+            hollerith: vec![],
+            advance: true,
+            size: 0,
+            eor: false,
+            records: 0,
             line: String::new(),
             line_pos: 0,
-            read: read,
+            has_record: false,
+            read,
             consumed_data: false,
-            node: fmt,
-            iter: fmt.into_iter().peekable(),
+            cursor: program.cursor(),
+            program,
         }
     }
 
-    fn check_rest(&mut self) -> Result<bool, ReadErr> {
-        if self.line_pos == self.line.len() {
-            return Ok(self.read_line()?);
+    /// Make sure there is a current record, reading the next line
+    /// from the input if the previous record was finished.
+    fn ensure_record(&mut self) -> Result<(), ReadErr> {
+        if self.has_record {
+            return Ok(());
         }
-        Ok(true)
-    }
-
-    fn read_line(&mut self) -> Result<bool, ReadErr> {
         self.line.clear();
-        let read = self.read.read_line(&mut self.line)?;
+        if self.read.read_line(&mut self.line)? == 0 {
+            return Err(ReadErr::EndOfFile);
+        }
+        self.records += 1;
+        let len = self.line.trim_end_matches(['\r', '\n']).len();
+        self.line.truncate(len);
         self.line_pos = 0;
-        Ok(read != 0)
+        self.has_record = true;
+        Ok(())
+    }
+
+    /// Finish the current record, skipping it if it was never started.
+    fn next_record(&mut self) -> Result<(), ReadErr> {
+        self.ensure_record()?;
+        self.has_record = false;
+        Ok(())
+    }
+
+    /// Take the next data edit descriptor for an item of type `tag`.
+    fn next_data(&mut self, tag: FortranTag) -> Result<&FormatNode, ReadErr> {
+        let n = match self.cursor.next(&self.program) {
+            Some(n) if gives_data(n)? => n,
+            _ => return Err(ReadErr::InvalidState),
+        };
+        self.consumed_data = true;
+        if self.config.strict && !n.accepts(tag) {
+            return Err(ReadErr::InvalidEditing(n.clone(), tag));
+        }
+        Ok(n)
+    }
+
+    /// Start the next statement on the same input with the format `fmt`,
+    /// keeping the position in the record a non-advancing statement
+    /// left. The statement is advancing unless `set_advance` says otherwise.
+    pub fn restart(&mut self, fmt: &FormatNode) {
+        self.restart_cow(Cow::Owned(FormatProgram::compile(fmt)));
+    }
+
+    /// Like `restart`, with a program compiled beforehand.
+    pub fn restart_program(&mut self, program: &'a FormatProgram) {
+        self.restart_cow(Cow::Borrowed(program));
+    }
+
+    fn restart_cow(&mut self, program: Cow<'a, FormatProgram>) {
+        self.cursor = program.cursor();
+        self.program = program;
+        self.reset_statement();
+    }
+
+    /// Run the format from its start for the next statement.
+    fn reset_statement(&mut self) {
+        self.opts = ReaderOpts {
+            terminated: false,
+            suppress_newline: false,
+            blank_zero: false,
+            scale: 0,
+            round: RoundType::RP,
+            decimal: self.config.decimal,
+            radix: 10,
+        };
+        self.cursor.reset();
+        self.hollerith.clear();
+        self.consumed_data = false;
+        self.advance = true;
+        self.size = 0;
+        self.eor = false;
+    }
+
+    /// Choose whether the statement moves to the next record when it
+    /// ends, as ADVANCE= does. A non-advancing statement leaves the
+    /// position in the record for the next one, and reading past the
+    /// end of the record gives `ReadErr::EndOfRecord`.
+    pub fn set_advance(&mut self, advance: bool) {
+        self.advance = advance;
+    }
+
+    /// Number of characters taken from records by data edit descriptors
+    /// in this statement, as SIZE= gives, blank padding not included.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the field of the next data edit descriptor is all blanks.
+    fn blank_field(&mut self) -> Result<bool, ReadErr> {
+        let ow = match self.cursor.peek(&self.program) {
+            Some(&FormatNode::Int(_, w, _)) | Some(&FormatNode::Real(_, w, _, _)) => Some(w),
+            Some(&FormatNode::Bool(ow)) => Some(ow.unwrap_or(2)),
+            Some(&FormatNode::Str(ow)) => ow,
+            _ => return Ok(false),
+        };
+        if ow == Some(0) || self.eor {
+            return Ok(false);
+        }
+        self.ensure_record()?;
+        let rest = &self.line[self.byte_at(self.line_pos)..];
+        let field = match ow {
+            Some(w) => rest.chars().take(w).collect::<String>(),
+            None => rest.to_string(),
+        };
+        Ok(field.trim().is_empty())
+    }
+
+    /// Take `w` characters of the current record, or the rest of it.
+    /// The record is treated as padded with blanks on the right.
+    fn read_field(&mut self, ow: Option<usize>) -> Result<String, ReadErr> {
+        // I0, F0.d and G0 are for output only
+        if ow == Some(0) {
+            return Err(ReadErr::ZeroWidthInput);
+        }
+        if self.eor {
+            return Err(ReadErr::EndOfRecord);
+        }
+        self.ensure_record()?;
+        let len = self.record_len();
+        let start = self.byte_at(self.line_pos);
+        let end = match ow {
+            Some(w) => self.byte_at(self.line_pos + w),
+            None => self.line.len(),
+        };
+        if let Some(w) = ow {
+            if !self.advance && self.line_pos + w > len {
+                self.eor = true;
+            }
+        }
+        self.line_pos = match ow {
+            Some(w) => self.line_pos + w,
+            None => len,
+        };
+        let field = self.line[start..end].to_owned();
+        self.size += field.chars().count();
+        Ok(field)
+    }
+
+    /// Take a numeric field of width `w`, applying the blank control:
+    /// leading blanks are ignored, the others are dropped by BN
+    /// or read as zeros by BZ.
+    fn read_numeric_field(&mut self, w: usize) -> Result<String, ReadErr> {
+        let field = self.read_field(Some(w))?;
+        let blank = if self.opts.blank_zero { Some('0') } else { None };
+        Ok(field.trim_start().chars()
+           .filter_map(|c| if c.is_whitespace() { blank } else { Some(c) })
+           .collect())
+    }
+
+    fn read_int<T>(&mut self, t: IntFormat, w: usize) -> Result<T, ReadErr>
+        where T: FromStr<Err=::std::num::ParseIntError> + Default + IntBits
+    {
+        let field = self.read_numeric_field(w)?;
+        if field.is_empty() {
+            return Ok(T::default());
+        }
+        match t {
+            IntFormat::I => Ok(field.parse()?),
+            IntFormat::O => Ok(T::from_bits(u64::from_str_radix(&field, 8)?)),
+            IntFormat::Z => Ok(T::from_bits(u64::from_str_radix(&field, 16)?)),
+            IntFormat::B => Ok(T::from_bits(u64::from_str_radix(&field, 2)?)),
+        }
+    }
+
+    fn read_real<T>(&mut self, w: usize, od: Option<usize>) -> Result<T, ReadErr>
+        where T: FromStr + RealBits, ReadErr: From<T::Err>
+    {
+        let mut field = self.read_numeric_field(w)?;
+        if self.opts.decimal == DecimalType::DC {
+            field = field.replace(',', ".");
+        }
+        let (d, scale) = (od.unwrap_or(0), self.opts.scale);
+        let val = parse_real(&field, d, scale)?;
+        round_directed(val, &field, d, scale, self.opts.round)
+    }
+
+    /// Number of characters left in the current record, as given by Q.
+    fn remaining_chars(&mut self) -> Result<usize, ReadErr> {
+        self.ensure_record()?;
+        Ok(self.record_len().saturating_sub(self.line_pos))
+    }
+
+    fn rest_is_blank(&mut self) -> Result<bool, ReadErr> {
+        self.ensure_record()?;
+        Ok(self.line[self.byte_at(self.line_pos)..].trim_start_matches(' ').is_empty())
+    }
+
+    /// Number of characters in the current record.
+    fn record_len(&self) -> usize {
+        self.line.chars().count()
+    }
+
+    /// Byte offset of the character `pos` of the current record, which
+    /// `line_pos` counts in, or the end of a shorter record.
+    fn byte_at(&self, pos: usize) -> usize {
+        if self.line.is_ascii() {
+            return pos.min(self.line.len());
+        }
+        self.line.char_indices().nth(pos).map_or(self.line.len(), |(i, _)| i)
     }
 
     pub fn consume_constants(&mut self, want_data: bool) -> Result<(), ReadErr> {
         use format::FormatNode::*;
         loop {
-            let has_next = self.iter.peek().is_some();
+            let has_next = self.cursor.peek(&self.program).is_some();
+
+            if self.eor {
+                return self.end_of_record();
+            }
 
             if !has_next {
                 // a the end of the iterator
                 if !want_data {
-                    // with no data, print the newline, done
-                    if !self.opts.suppress_newline {
-                        self.read_line()?;
-                    }
-                    return Ok(())
+                    // with no data, finish the record, done
+                    return self.end_statement();
                 } else {
                     // if we didn't read any data and we're
                     // at the end of the list, this is an error
                     if !self.consumed_data {
                         return Err(ReadErr::NoDataEditings);
                     } else {
-                        // otherwise, we've reached the end of the pattern, reset the iterator
-                        self.iter = self.node.into_iter().peekable();
+                        // otherwise, we've reached the end of the pattern,
+                        // continue from the next record
+                        self.next_record()?;
+                        self.cursor.revert(&self.program);
                     }
                 }
             }
 
-            if let Some(next) = self.iter.peek() {
-                if try!(gives_data(*next)) {
+            if let Some(next) = self.cursor.peek(&self.program) {
+                if gives_data(next)? {
+                    if !want_data {
+                        // the items ran out before the format did
+                        return self.end_statement();
+                    }
                     break;
                 }
             }
 
-            let next = self.iter.next().unwrap();
+            let pc = self.cursor.position();
+            let next = self.cursor.next(&self.program).unwrap();
 
-            match next {
-                &Radix(r) => { self.opts.radix = r; },
-                &Scale(p) => { self.opts.scale = p; },
-                // TODO: seek until next newline?
-                &NewLine => {
-                    if !self.check_rest()? {
-                        return Ok(());
-                    }
-                    self.line_pos = self.line.len();
+            match *next {
+                Radix(r) => { self.opts.radix = r; },
+                Scale(p) => { self.opts.scale = p; },
+                // the sign is always accepted on input
+                SignControl(_) => {},
+                RoundControl(r) => { self.opts.round = r; },
+                DecimalControl(d) => { self.opts.decimal = d; },
+                NewLine => {
+                    self.next_record()?;
                 },
-                // TODO: seek instead of writing space?
-                &SkipChar => {
+                SkipChar => {
+                    self.ensure_record()?;
                     self.line_pos += 1;
-                    if !self.check_rest()? {
-                        return Ok(());
-                    }
                 },
-                &SuppressNewLine => {},
-                &Terminate => {
+                SuppressNewLine => {
+                    self.opts.suppress_newline = true;
+                },
+                Terminate => {
                     if !want_data {
                         self.opts.terminated = true;
-                        return Ok(());
+                        return self.next_record();
                     }
                 },
-                &Literal(_) => {
+                Literal(_) => {
                     return Err(ReadErr::UnexpectedLiteral);
                 },
-                // TODO: seek?
-                &BlankControl(_) => {},
-                &AbsColumn(_) => {},
-                &RelColumn(_) => {},
-                x@_ => {
-                    unreachable!(format!("{:?}", x))
+                Hollerith(ref s) => {
+                    if self.config.strict {
+                        return Err(ReadErr::UnexpectedHollerith);
+                    }
+                    // F66 stores the record contents into the descriptor
+                    let w = s.chars().count();
+                    let mut field = self.read_field(Some(w))?;
+                    while field.chars().count() < w {
+                        field.push(' ');
+                    }
+                    self.hollerith.retain(|&(k, _)| k != pc);
+                    self.hollerith.push((pc, field));
+                },
+                BlankControl(ref b) => {
+                    self.opts.blank_zero = *b == BlankType::BZ;
+                },
+                AbsColumn(c) => {
+                    self.ensure_record()?;
+                    self.line_pos = c.saturating_sub(1);
+                },
+                RelColumn(c) => {
+                    self.ensure_record()?;
+                    self.line_pos = (self.line_pos as isize + c).max(0) as usize;
+                },
+                ref x => {
+                    unreachable!("{:?}", x)
                 }
             }
         }
         Ok(())
     }
 
+    /// Finish the statement, moving to the next record unless
+    /// the statement is non-advancing or ended by `$`.
+    fn end_statement(&mut self) -> Result<(), ReadErr> {
+        if self.advance && !self.opts.suppress_newline {
+            self.next_record()?;
+        }
+        Ok(())
+    }
+
+    /// Leave the record a non-advancing read ran past.
+    fn end_of_record(&mut self) -> Result<(), ReadErr> {
+        self.has_record = false;
+        Err(ReadErr::EndOfRecord)
+    }
+
     pub fn read_value<T: FortranRead>(&mut self, val: &mut T) -> Result<bool, ReadErr> {
-        val.fortran_read(self)
+        let rv = val.fortran_read(self);
+        if self.eor {
+            self.end_of_record()?;
+        }
+        rv
+    }
+
+    /// Read the items of one pass through the format, each of the type
+    /// its edit descriptor reads as `FortranValue::for_edit` gives it.
+    /// Inside an unlimited repeat, reading stops where the rest of the
+    /// record is blank.
+    pub fn read_values(&mut self) -> Result<Vec<FortranValue>, ReadErr> {
+        let mut values = vec![];
+        loop {
+            // look for the next data descriptor, without reverting
+            let mut cursor = self.cursor.clone();
+            let next = loop {
+                match cursor.next(&self.program) {
+                    Some(n) if gives_data(n)? => break FortranValue::for_edit(n),
+                    Some(_) => {},
+                    None => break None,
+                }
+            };
+            let mut value = match next {
+                Some(value) => value,
+                None => break,
+            };
+            let pc = cursor.position() - 1;
+            if !values.is_empty() && self.program.is_unlimited(pc) && self.rest_is_blank()? {
+                break;
+            }
+            self.consume_constants(true)?;
+            self.read_value(&mut value)?;
+            values.push(value);
+        }
+        self.consume_constants(false)?;
+        Ok(values)
+    }
+
+    /// The format this reader was created with, where every Hollerith
+    /// descriptor that was read holds the characters taken from the record,
+    /// as an F66 program would see the FORMAT statement afterwards.
+    pub fn hollerith_format(&self) -> FormatNode {
+        self.program.map_edits(|pc, node| {
+            match self.hollerith.iter().find(|&&(k, _)| k == pc) {
+                Some((_, s)) => FormatNode::Hollerith(s.clone()),
+                None => node.clone(),
+            }
+        })
+    }
+}
+
+/// Integers that O and Z editing fill bit by bit.
+trait IntBits {
+    fn from_bits(bits: u64) -> Self;
+}
+
+macro_rules! impl_int_bits {
+    ($ty: ty) => {
+        impl IntBits for $ty {
+            fn from_bits(bits: u64) -> $ty {
+                bits as $ty
+            }
+        }
+    }
+}
+
+impl_int_bits!(i64);
+impl_int_bits!(i32);
+impl_int_bits!(i16);
+impl_int_bits!(i8);
+impl_int_bits!(u64);
+impl_int_bits!(u32);
+impl_int_bits!(u16);
+impl_int_bits!(u8);
+
+/// Reals that can step to the adjacent representable magnitude.
+trait RealBits: Copy + Into<f64> {
+    fn step(self, away_from_zero: bool) -> Self;
+}
+
+macro_rules! impl_real_bits {
+    ($ty: ty) => {
+        impl RealBits for $ty {
+            fn step(self, away_from_zero: bool) -> $ty {
+                let bits = self.to_bits();
+                <$ty>::from_bits(if away_from_zero { bits + 1 } else { bits - 1 })
+            }
+        }
     }
 }
 
+impl_real_bits!(f64);
+impl_real_bits!(f32);
+
 impl<'a, R: BufRead> FortranDefaultReader<'a, R> {
-    pub fn new<'f>(read: &'f mut R) -> FortranDefaultReader<'f, R> {
+    pub fn new(read: &'a mut R) -> FortranDefaultReader<'a, R> {
+        FortranDefaultReader::with_config(read, ReaderConfig::default())
+    }
+
+    pub fn with_config(read: &'a mut R, config: ReaderConfig) -> FortranDefaultReader<'a, R> {
         FortranDefaultReader {
-            read: read,
+            read,
             line: String::new(),
             line_pos: 0,
+            config,
         }
     }
 
     pub fn read_next_csv(&mut self) -> Result<String, ReadErr> {
+        let sep = match self.config.decimal {
+            DecimalType::DC => ';',
+            DecimalType::DP => ',',
+        };
         fn is_newline(s: &str, p: usize) -> bool {
             s[p..].chars().next().map(|c| c == '\r' || c == '\n').unwrap_or(false)
         }
@@ -325,17 +1018,17 @@ impl<'a, R: BufRead> FortranDefaultReader<'a, R> {
                 let read = self.read.read_line(&mut self.line)?;
                 self.line_pos = 0;
                 if read == 0 {
-                    return Ok(String::new());
+                    return Err(ReadErr::EndOfFile);
                 }
                 continue;
             }
             let entry_end = self.line[self.line_pos..].char_indices()
                         .skip_while(|&(_, c)| c.is_whitespace())
-                        .find(|&(_, c)| c.is_whitespace() || c == ',');
+                        .find(|&(_, c)| c.is_whitespace() || c == sep);
             if let Some((end, c)) = entry_end {
                 let next_pos = self.line_pos + end;
                 let rv = self.line[self.line_pos..next_pos].to_owned();
-                self.line_pos = next_pos + if c == ',' { 1 } else { 0 };
+                self.line_pos = next_pos + if c == sep { 1 } else { 0 };
                 return Ok(rv);
             } else {
                 let prev_pos = self.line_pos;
@@ -351,10 +1044,10 @@ impl<'a, R: BufRead> FortranDefaultReader<'a, R> {
             let read = self.read.read_line(&mut self.line)?;
             self.line_pos = 0;
             if read == 0 {
-                return Ok(String::new());
+                return Err(ReadErr::EndOfFile);
             }
         }
-        let last = self.line.rfind(|c| c == '\r' || c == '\n').unwrap_or(self.line.len());
+        let last = self.line.rfind(['\r', '\n']).unwrap_or(self.line.len());
         let rest = self.line[self.line_pos..last].to_owned();
         self.line_pos = self.line.len();
         Ok(rest)
@@ -364,3 +1057,450 @@ impl<'a, R: BufRead> FortranDefaultReader<'a, R> {
         val.fortran_read_default(self)
     }
 }
+
+/// Records of an input decoded one by one with the same format, e.g.
+/// `FortranRecords::<(i32, f64, String), _>::new(&mut input, &fmt)`.
+/// Each item is a statement reading a `T`, usually a tuple, and the
+/// iteration ends at the end of the input. Errors give the line they
+/// occurred in, the next item starts at the following record.
+pub struct FortranRecords<'a, T, R: 'a + BufRead> {
+    reader: FortranIterReader<'a, R>,
+    done: bool,
+    item: ::std::marker::PhantomData<T>,
+}
+
+impl<'a, T: FortranRead + Default, R: BufRead> FortranRecords<'a, T, R> {
+    pub fn new(read: &'a mut R, fmt: &FormatNode) -> FortranRecords<'a, T, R> {
+        FortranRecords::with_config(read, fmt, ReaderConfig::default())
+    }
+
+    pub fn with_config(read: &'a mut R, fmt: &FormatNode, config: ReaderConfig) -> FortranRecords<'a, T, R> {
+        let program = Cow::Owned(FormatProgram::compile(fmt));
+        FortranRecords {
+            reader: FortranIterReader::from_cow(program, read, config),
+            done: false,
+            item: ::std::marker::PhantomData,
+        }
+    }
+
+    fn read_item(&mut self) -> Result<T, ReadErr> {
+        let mut val = T::default();
+        self.reader.consume_constants(true)?;
+        self.reader.read_value(&mut val)?;
+        self.reader.consume_constants(false)?;
+        Ok(val)
+    }
+}
+
+impl<'a, T: FortranRead + Default, R: BufRead> Iterator for FortranRecords<'a, T, R> {
+    type Item = Result<T, ReadErr>;
+
+    fn next(&mut self) -> Option<Result<T, ReadErr>> {
+        if self.done {
+            return None;
+        }
+        let start = self.reader.records;
+        let rv = self.read_item();
+        next_statement(&mut self.reader, &mut self.done, start, rv)
+    }
+}
+
+/// Finish a statement of the record iterators, started at record
+/// `start`. The input ending before the statement ends the iteration.
+fn next_statement<R: BufRead, T>(reader: &mut FortranIterReader<R>, done: &mut bool, start: usize,
+                                 rv: Result<T, ReadErr>) -> Option<Result<T, ReadErr>> {
+    reader.reset_statement();
+    match rv {
+        Ok(val) => Some(Ok(val)),
+        Err(ReadErr::EndOfFile) if reader.records == start => {
+            *done = true;
+            None
+        },
+        Err(e) => {
+            // a missing line is the one after the last
+            let line = reader.records + if reader.has_record { 0 } else { 1 };
+            *done = e.kind() == ReadErrKind::EndOfFile;
+            reader.has_record = false;
+            Some(Err(ReadErr::AtLine(line, Box::new(e))))
+        },
+    }
+}
+
+/// Records of an input read like `FortranRecords` does, for formats
+/// only known at run time: each item holds the values of a statement
+/// as `FortranIterReader::read_values` reads them.
+pub struct FortranValueRecords<'a, R: 'a + BufRead> {
+    reader: FortranIterReader<'a, R>,
+    done: bool,
+}
+
+impl<'a, R: BufRead> FortranValueRecords<'a, R> {
+    pub fn new(read: &'a mut R, fmt: &FormatNode) -> FortranValueRecords<'a, R> {
+        FortranValueRecords::with_config(read, fmt, ReaderConfig::default())
+    }
+
+    pub fn with_config(read: &'a mut R, fmt: &FormatNode, config: ReaderConfig) -> FortranValueRecords<'a, R> {
+        let program = Cow::Owned(FormatProgram::compile(fmt));
+        FortranValueRecords {
+            reader: FortranIterReader::from_cow(program, read, config),
+            done: false,
+        }
+    }
+}
+
+impl<'a, R: BufRead> Iterator for FortranValueRecords<'a, R> {
+    type Item = Result<Vec<FortranValue>, ReadErr>;
+
+    fn next(&mut self) -> Option<Result<Vec<FortranValue>, ReadErr>> {
+        if self.done {
+            return None;
+        }
+        let start = self.reader.records;
+        let rv = self.reader.read_values();
+        next_statement(&mut self.reader, &mut self.done, start, rv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::format::parse_format;
+    use ::types::FortranTag;
+    use ::format::DecimalType;
+    use ::read::{FortranDefaultReader, FortranIterReader, FortranRecords, FortranValueRecords, ReadErr, ReadErrKind, ReaderConfig};
+
+    #[test]
+    fn formatted_values() {
+        let fmt = parse_format("(I5, F8.3, L3, A4, F6.2)").unwrap();
+        let mut inp = "  -42   1.500  Tabcd  1234\n".as_bytes();
+        let mut a = 0i32;
+        let mut b = 0.0f64;
+        let mut c = false;
+        let mut d = String::new();
+        let mut e = 0.0f32;
+        {
+            let mut reader = FortranIterReader::new(&fmt, &mut inp);
+            reader.read_value(&mut a).unwrap();
+            reader.read_value(&mut b).unwrap();
+            reader.read_value(&mut c).unwrap();
+            reader.read_value(&mut d).unwrap();
+            reader.read_value(&mut e).unwrap();
+            reader.consume_constants(false).unwrap();
+        }
+        assert_eq!((a, b, c, d.as_str(), e), (-42, 1.5, true, "abcd", 12.34));
+    }
+
+    #[test]
+    fn non_ascii_record() {
+        // fields are counted in characters, not bytes
+        let fmt = parse_format("(A1, I2, 1X, A2, Q)").unwrap();
+        let mut inp = "\u{e9}12 \u{e0}\u{fc}xyz\n".as_bytes();
+        let (mut a, mut n, mut b, mut q) = (String::new(), 0i32, String::new(), 0i32);
+        {
+            let mut reader = FortranIterReader::new(&fmt, &mut inp);
+            reader.read_value(&mut a).unwrap();
+            reader.read_value(&mut n).unwrap();
+            reader.consume_constants(true).unwrap();
+            reader.read_value(&mut b).unwrap();
+            reader.read_value(&mut q).unwrap();
+            reader.consume_constants(false).unwrap();
+        }
+        assert_eq!((a.as_str(), n, b.as_str(), q), ("\u{e9}", 12, "\u{e0}\u{fc}", 3));
+    }
+
+    #[test]
+    fn exponents_and_blanks() {
+        let fmt = parse_format("(F8.2, E10.3, BZ, I4)").unwrap();
+        let mut inp = "  1.5D2 -2.5E-01  1 \n".as_bytes();
+        let mut a = 0.0f64;
+        let mut b = 0.0f64;
+        let mut c = 0i32;
+        {
+            let mut reader = FortranIterReader::new(&fmt, &mut inp);
+            reader.read_value(&mut a).unwrap();
+            reader.read_value(&mut b).unwrap();
+            reader.consume_constants(true).unwrap();
+            reader.read_value(&mut c).unwrap();
+        }
+        assert_eq!((a, b, c), (150.0, -0.25, 10));
+    }
+
+    #[test]
+    fn modern_descriptors() {
+        let fmt = parse_format("(B6, EX12.3, ES10.2, *(I2))").unwrap();
+        let mut inp = "  1011 -0X1.800P+1  1.50E+02 1 2 3\n".as_bytes();
+        let mut a = 0u8;
+        let mut b = 0.0f64;
+        let mut c = 0.0f32;
+        let mut d = vec![0i32; 3];
+        {
+            let mut reader = FortranIterReader::new(&fmt, &mut inp);
+            reader.read_value(&mut a).unwrap();
+            reader.read_value(&mut b).unwrap();
+            reader.read_value(&mut c).unwrap();
+            reader.read_value(&mut d).unwrap();
+            reader.consume_constants(false).unwrap();
+        }
+        assert_eq!((a, b, c, d), (11, -3.0, 150.0, vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn rounding_modes() {
+        // 0.1 is not representable, the modes pick either neighbour
+        let fmt = parse_format("(SP, RU, F4.1, RD, F4.1, RZ, F5.1, RN, F4.1)").unwrap();
+        let mut inp = " 0.1 0.1 -0.1 0.1\n".as_bytes();
+        let mut vals = vec![0.0f64; 4];
+        {
+            let mut reader = FortranIterReader::new(&fmt, &mut inp);
+            reader.read_value(&mut vals).unwrap();
+            reader.consume_constants(false).unwrap();
+        }
+        // the nearest double to 0.1 is above it
+        assert_eq!(vals[0], 0.1);
+        assert_eq!(vals[1], 0.1f64.next_down());
+        assert_eq!(vals[2], -vals[1]);
+        assert_eq!(vals[3], 0.1);
+    }
+
+    #[test]
+    fn decimal_comma() {
+        let fmt = parse_format("(DC, F5.2, DP, F5.2)").unwrap();
+        let mut inp = " 1,25 1.25\n".as_bytes();
+        let mut vals = vec![0.0f64; 2];
+        {
+            let mut reader = FortranIterReader::new(&fmt, &mut inp);
+            reader.read_value(&mut vals).unwrap();
+            reader.consume_constants(false).unwrap();
+        }
+        assert_eq!(vals, vec![1.25, 1.25]);
+
+        let config = ReaderConfig { decimal: DecimalType::DC, ..Default::default() };
+        let mut inp = "1,5;2;3,25\n".as_bytes();
+        let (mut a, mut b, mut c) = (0.0f64, 0i32, 0.0f32);
+        {
+            let mut reader = FortranDefaultReader::with_config(&mut inp, config);
+            reader.read_value(&mut a).unwrap();
+            reader.read_value(&mut b).unwrap();
+            reader.read_value(&mut c).unwrap();
+        }
+        assert_eq!((a, b, c), (1.5, 2, 3.25));
+    }
+
+    #[test]
+    fn reversion_point() {
+        // reversion restarts at the last group, not at I2
+        let fmt = parse_format("(I2, (I3))").unwrap();
+        let mut inp = " 1  2\n  3\n  4\n".as_bytes();
+        let mut vals = vec![0i32; 4];
+        {
+            let mut reader = FortranIterReader::new(&fmt, &mut inp);
+            reader.read_value(&mut vals).unwrap();
+            reader.consume_constants(false).unwrap();
+        }
+        assert_eq!(vals, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn non_advancing() {
+        let mut inp = "abc  12\n3\n  4 5\n".as_bytes();
+        let mut s = String::new();
+        let (mut a, mut b) = (0i32, 0i32);
+        let mut vals = vec![0i32; 2];
+        let fmt = parse_format("(A3)").unwrap();
+        let mut reader = FortranIterReader::new(&fmt, &mut inp);
+        reader.set_advance(false);
+        reader.read_value(&mut s).unwrap();
+        reader.consume_constants(false).unwrap();
+        assert_eq!((s.as_str(), reader.size()), ("abc", 3));
+
+        reader.restart(&parse_format("(I4)").unwrap());
+        reader.set_advance(false);
+        reader.read_value(&mut a).unwrap();
+        reader.consume_constants(false).unwrap();
+        assert_eq!((a, reader.size()), (12, 4));
+
+        reader.restart(&parse_format("(I3)").unwrap());
+        reader.set_advance(false);
+        match reader.read_value(&mut a) {
+            Err(ReadErr::EndOfRecord) => {},
+            x => panic!("expected EndOfRecord, got {:?}", x),
+        }
+        assert_eq!(reader.size(), 0);
+
+        // end of record moved to the next one
+        reader.restart(&parse_format("(I1)").unwrap());
+        reader.read_value(&mut b).unwrap();
+        reader.consume_constants(false).unwrap();
+        assert_eq!(b, 3);
+
+        reader.restart(&parse_format("(2I3)").unwrap());
+        reader.set_advance(false);
+        match reader.read_value(&mut vals) {
+            Err(ReadErr::EndOfRecord) => {},
+            x => panic!("expected EndOfRecord, got {:?}", x),
+        }
+        assert_eq!((vals, reader.size()), (vec![4, 5], 5));
+    }
+
+    #[test]
+    fn end_of_file() {
+        let fmt = parse_format("(I3)").unwrap();
+        let mut inp = "  1\n  2\n  x\n".as_bytes();
+        let mut vals = vec![];
+        let kind = loop {
+            let mut v = 0i32;
+            let res = {
+                let mut reader = FortranIterReader::new(&fmt, &mut inp);
+                reader.read_value(&mut v).and_then(|_| reader.consume_constants(false))
+            };
+            match res {
+                Ok(()) => vals.push(v),
+                Err(e) => break e.kind(),
+            }
+        };
+        assert_eq!((vals, kind), (vec![1, 2], ReadErrKind::Data));
+
+        let mut v = 0i32;
+        let mut reader = FortranIterReader::new(&fmt, &mut inp);
+        assert_eq!(reader.read_value(&mut v).unwrap_err().kind(), ReadErrKind::EndOfFile);
+
+        // a null value is not the end of the file
+        let mut inp = "1,,3\n".as_bytes();
+        let mut vals = [0i32; 4];
+        let mut reader = FortranDefaultReader::new(&mut inp);
+        assert!(reader.read_value(&mut vals[0]).unwrap());
+        assert!(!reader.read_value(&mut vals[1]).unwrap());
+        assert!(reader.read_value(&mut vals[2]).unwrap());
+        match reader.read_value(&mut vals[3]) {
+            Err(ReadErr::EndOfFile) => {},
+            x => panic!("expected EndOfFile, got {:?}", x),
+        }
+        assert_eq!(vals, [1, 0, 3, 0]);
+    }
+
+    #[test]
+    fn records() {
+        let fmt = parse_format("(I3, F5.1, 1X, A)").unwrap();
+        let mut inp = "  1  2.5 one\n  2 x    two\n  3  4.0 three\n".as_bytes();
+        let recs: Vec<_> = FortranRecords::<(i32, f64, String), _>::new(&mut inp, &fmt).collect();
+        assert_eq!(recs.len(), 3);
+        assert_eq!(recs[0].as_ref().unwrap(), &(1, 2.5, "one".to_string()));
+        match recs[1] {
+            Err(ReadErr::AtLine(2, ref e)) => assert_eq!(e.kind(), ReadErrKind::Data),
+            ref x => panic!("expected an error in line 2, got {:?}", x),
+        }
+        assert_eq!(recs[2].as_ref().unwrap(), &(3, 4.0, "three".to_string()));
+
+        // a record cut short by the end of the input
+        let fmt = parse_format("(I2 / I2)").unwrap();
+        let mut inp = " 1\n 2\n 3\n".as_bytes();
+        let mut recs = FortranRecords::<(i8, i8), _>::new(&mut inp, &fmt);
+        assert_eq!(recs.next().unwrap().unwrap(), (1, 2));
+        match recs.next() {
+            Some(Err(ReadErr::AtLine(4, ref e))) => assert_eq!(e.kind(), ReadErrKind::EndOfFile),
+            x => panic!("expected the end of the input in line 4, got {:?}", x),
+        }
+        assert!(recs.next().is_none());
+    }
+
+    #[test]
+    fn value_records() {
+        use ::value::FortranValue::*;
+        let fmt = parse_format("(I3, F5.1, 1X, A)").unwrap();
+        let mut inp = "  1  2.5 one\n  2 x    two\n".as_bytes();
+        let mut recs = FortranValueRecords::new(&mut inp, &fmt);
+        assert_eq!(recs.next().unwrap().unwrap(), vec![Int8(1), Real8(2.5), Strin("one".to_string())]);
+        match recs.next() {
+            Some(Err(ReadErr::AtLine(2, ref e))) => assert_eq!(e.kind(), ReadErrKind::Data),
+            x => panic!("expected an error in line 2, got {:?}", x),
+        }
+        assert!(recs.next().is_none());
+
+        let fmt = parse_format("(A2, *(I3))").unwrap();
+        let mut inp = "ab  1  2\ncd\n".as_bytes();
+        let recs: Vec<_> = FortranValueRecords::new(&mut inp, &fmt).map(|r| r.unwrap()).collect();
+        assert_eq!(recs, vec![vec![Strin("ab".to_string()), Int8(1), Int8(2)],
+                              vec![Strin("cd".to_string())]]);
+    }
+
+    #[test]
+    fn missing_values() {
+        let fmt = parse_format("(2I3, 2F6.2, L2, A3)").unwrap();
+        let mut inp = "  1     1.50        \n".as_bytes();
+        let mut a = vec![None; 2];
+        let mut b = (None, Some(0.0));
+        let mut c: (Option<bool>, Option<String>) = (Some(true), Some(String::new()));
+        {
+            let mut reader = FortranIterReader::new(&fmt, &mut inp);
+            reader.read_value(&mut a).unwrap();
+            reader.read_value(&mut b).unwrap();
+            reader.read_value(&mut c).unwrap();
+            reader.consume_constants(false).unwrap();
+        }
+        assert_eq!((a, b, c), (vec![Some(1i32), None], (Some(1.5f64), None), (None, None)));
+
+        let mut inp = "1,,3\n".as_bytes();
+        let mut vals = vec![Some(0i64); 3];
+        FortranDefaultReader::new(&mut inp).read_value(&mut vals).unwrap();
+        assert_eq!(vals, [Some(1), None, Some(3)]);
+    }
+
+    #[test]
+    fn zero_width_input() {
+        let fmt = parse_format("(I0)").unwrap();
+        let mut inp = "42\n".as_bytes();
+        let mut val = 0i32;
+        let mut reader = FortranIterReader::new(&fmt, &mut inp);
+        match reader.read_value(&mut val) {
+            Err(ReadErr::ZeroWidthInput) => {},
+            x => panic!("expected ZeroWidthInput, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn strict_mismatch() {
+        let fmt = parse_format("(I5)").unwrap();
+        let mut inp = "    1\n".as_bytes();
+        let mut val = 0.0f64;
+        let mut reader = FortranIterReader::new(&fmt, &mut inp);
+        match reader.read_value(&mut val) {
+            Err(ReadErr::InvalidEditing(_, FortranTag::Real8)) => {},
+            x => panic!("expected InvalidEditing, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn hollerith_input() {
+        let fmt = parse_format("(6HLABEL:, I3)").unwrap();
+        let mut inp = "name=  42\n".as_bytes();
+        let mut val = 0i32;
+        match FortranIterReader::new(&fmt, &mut inp).consume_constants(true) {
+            Err(ReadErr::UnexpectedHollerith) => {},
+            x => panic!("expected UnexpectedHollerith, got {:?}", x),
+        }
+
+        let mut inp = "name=  42\n".as_bytes();
+        let config = ReaderConfig { strict: false, ..Default::default() };
+        let mut reader = FortranIterReader::with_config(&fmt, &mut inp, config);
+        reader.consume_constants(true).unwrap();
+        reader.read_value(&mut val).unwrap();
+        assert_eq!(val, 42);
+        assert_eq!(reader.hollerith_format().to_string(), "(6Hname= , I3)");
+    }
+
+    #[test]
+    fn lenient_coercion() {
+        let fmt = parse_format("(I3, F5.1, I3)").unwrap();
+        let config = ReaderConfig { strict: false, ..Default::default() };
+        let mut inp = " 12  7.9 -1\n".as_bytes();
+        let mut a = 0.0f32;
+        let mut b = 0i32;
+        let mut c = false;
+        {
+            let mut reader = FortranIterReader::with_config(&fmt, &mut inp, config);
+            reader.read_value(&mut a).unwrap();
+            reader.read_value(&mut b).unwrap();
+            reader.read_value(&mut c).unwrap();
+        }
+        assert_eq!((a, b, c), (12.0, 7, true));
+    }
+}
@@ -0,0 +1,519 @@
+//! Record layouts of unformatted sequential files
+//!
+//! A `Schema` describes the records of a file in JSON: the fields of
+//! each record with their types, how many times a record is repeated,
+//! and records whose layout depends on a leading key field.
+//!
+//! ```json
+//! {
+//!   "endian": "little",
+//!   "records": [
+//!     {"name": "header", "fields": ["INTEGER NX, NSTEP", "CHARACTER*8 TITLE"]},
+//!     {"name": "grid", "fields": [{"name": "x", "type": "REAL*8", "dim": ["nx"]}]},
+//!     {"name": "step", "repeat": "*",
+//!      "key": {"name": "kind", "type": "INTEGER"},
+//!      "variants": [
+//!        {"when": 1, "name": "time", "fields": ["REAL*8 T"]},
+//!        {"when": 2, "name": "field", "fields": ["REAL*4 U(NX)"]}
+//!      ]}
+//!   ]
+//! }
+//! ```
+//!
+//! A field is either an object with a `name`, a `type` as Fortran
+//! writes it and optional `dim`, or a declaration as in Fortran source,
+//! which may declare several fields. Dimensions and `repeat` counts are
+//! numbers or the names of integer fields decoded before, in any case;
+//! `repeat` may also be `"*"`, repeating the record to the end of the file.
+
+use std::io::{self, Cursor, Read, Seek};
+use decl::{parse_entities, parse_type, DeclError, Entity, Extent};
+use json::{Json, JsonError};
+use sequential::SequentialFile;
+use stream::{Endian, FortranBinary};
+use types::{FortranTag, FortranType};
+use value::FortranValue;
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Json(JsonError),
+    /// A type or declaration that does not parse.
+    Decl(String, DeclError),
+    /// A schema that is valid JSON but not a valid layout.
+    Invalid(String),
+    IoErr(io::Error),
+    /// A dimension or count naming no integer field decoded before.
+    UnknownName(String),
+    /// A key value that no variant of the record matches.
+    NoVariant(String, i64),
+    /// A record shorter than its fields.
+    ShortRecord(String),
+    /// A record longer than its fields, with the number of bytes left over.
+    LongRecord(String, usize),
+    /// The file ended before a record that must be present.
+    EndOfFile(String),
+}
+
+impl From<io::Error> for SchemaError {
+    fn from(x: io::Error) -> SchemaError {
+        SchemaError::IoErr(x)
+    }
+}
+
+impl From<JsonError> for SchemaError {
+    fn from(x: JsonError) -> SchemaError {
+        SchemaError::Json(x)
+    }
+}
+
+/// A count given by a number or by the value of a field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Count {
+    Fixed(usize),
+    Field(String),
+    /// To the end of the file, for `repeat` only.
+    UntilEnd,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSpec {
+    pub name: String,
+    pub tag: FortranTag,
+    pub dim: Option<Vec<Count>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub when: i64,
+    pub name: String,
+    pub fields: Vec<FieldSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordBody {
+    Fields(Vec<FieldSpec>),
+    /// The layout is that of the variant matching the key field,
+    /// which starts the record.
+    Dispatch { key: FieldSpec, variants: Vec<Variant> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordSpec {
+    pub name: String,
+    pub repeat: Count,
+    pub body: RecordBody,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    pub endian: Endian,
+    pub records: Vec<RecordSpec>,
+}
+
+/// A decoded record: the name of its layout and its fields in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub fields: Vec<(String, FortranValue)>,
+}
+
+impl Record {
+    /// The field `name`, in any case.
+    pub fn get(&self, name: &str) -> Option<&FortranValue> {
+        self.fields.iter().find(|f| f.0.eq_ignore_ascii_case(name)).map(|f| &f.1)
+    }
+}
+
+fn invalid(what: &str) -> SchemaError {
+    SchemaError::Invalid(what.to_string())
+}
+
+fn parse_count(json: &Json, what: &str) -> Result<Count, SchemaError> {
+    if let Some(n) = json.as_i64() {
+        if n >= 0 {
+            return Ok(Count::Fixed(n as usize));
+        }
+    }
+    match json.as_str() {
+        Some("*") if what == "repeat" => Ok(Count::UntilEnd),
+        Some(name) if name != "*" => Ok(Count::Field(name.to_string())),
+        _ => Err(SchemaError::Invalid(format!("bad {}: {}", what, json))),
+    }
+}
+
+fn parse_fields(json: Option<&Json>) -> Result<Vec<FieldSpec>, SchemaError> {
+    let items = json.and_then(|j| j.as_array()).ok_or_else(|| invalid("fields must be an array"))?;
+    let mut fields = vec![];
+    for item in items {
+        if let Some(decl) = item.as_str() {
+            let entities = parse_entities(decl).map_err(|e| SchemaError::Decl(decl.to_string(), e))?;
+            for Entity { name, tag, dim } in entities {
+                let dim = dim.map(|d| d.into_iter().map(|e| match e {
+                    Extent::Fixed(n) => Count::Fixed(n),
+                    Extent::Named(name) => Count::Field(name),
+                }).collect());
+                fields.push(FieldSpec { name, tag, dim });
+            }
+            continue;
+        }
+        fields.push(parse_field(item)?);
+    }
+    Ok(fields)
+}
+
+fn parse_field(json: &Json) -> Result<FieldSpec, SchemaError> {
+    if let Some(decl) = json.as_str() {
+        let mut fields = parse_fields(Some(&Json::Array(vec![Json::Str(decl.to_string())])))?;
+        if fields.len() != 1 {
+            return Err(SchemaError::Invalid(format!("{} must declare one field", decl)));
+        }
+        return Ok(fields.remove(0));
+    }
+    let name = json.get("name").and_then(|n| n.as_str()).ok_or_else(|| invalid("a field needs a name"))?;
+    let ty = json.get("type").and_then(|t| t.as_str())
+        .ok_or_else(|| SchemaError::Invalid(format!("field {} needs a type", name)))?;
+    let tag = parse_type(ty).map_err(|e| SchemaError::Decl(ty.to_string(), e))?;
+    let dim = match json.get("dim") {
+        None => None,
+        Some(d) => {
+            let d = d.as_array().ok_or_else(|| SchemaError::Invalid(format!("dim of {} must be an array", name)))?;
+            Some(d.iter().map(|x| parse_count(x, "dim")).collect::<Result<Vec<_>, _>>()?)
+        },
+    };
+    Ok(FieldSpec { name: name.to_string(), tag, dim })
+}
+
+fn parse_record(json: &Json) -> Result<RecordSpec, SchemaError> {
+    let name = json.get("name").and_then(|n| n.as_str()).ok_or_else(|| invalid("a record needs a name"))?;
+    let repeat = match json.get("repeat") {
+        Some(r) => parse_count(r, "repeat")?,
+        None => Count::Fixed(1),
+    };
+    let body = match (json.get("key"), json.get("variants")) {
+        (Some(key), Some(variants)) => {
+            let key = parse_field(key)?;
+            if key.dim.is_some() || !key.tag.is_integer() {
+                return Err(SchemaError::Invalid(format!("the key of {} must be an integer", name)));
+            }
+            let variants = variants.as_array().ok_or_else(|| invalid("variants must be an array"))?;
+            let variants = variants.iter().map(|v| {
+                let when = v.get("when").and_then(|w| w.as_i64())
+                    .ok_or_else(|| SchemaError::Invalid(format!("a variant of {} needs an integer when", name)))?;
+                let vname = v.get("name").and_then(|n| n.as_str()).unwrap_or(name);
+                Ok(Variant { when, name: vname.to_string(), fields: parse_fields(v.get("fields"))? })
+            }).collect::<Result<Vec<_>, SchemaError>>()?;
+            RecordBody::Dispatch { key, variants }
+        },
+        (None, None) => RecordBody::Fields(parse_fields(json.get("fields"))?),
+        _ => return Err(SchemaError::Invalid(format!("record {} needs both key and variants", name))),
+    };
+    Ok(RecordSpec { name: name.to_string(), repeat, body })
+}
+
+/// Decodes the records of a file one at a time, following the schema.
+pub struct Decoder<'a> {
+    schema: &'a Schema,
+    env: Env,
+    // the record of the schema the next record is decoded with
+    spec: usize,
+    // the records left of it, `None` before its count is known
+    left: Option<usize>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Whether the schema has no record left, skipping the records
+    /// repeated no times. Counts are taken from the fields decoded.
+    pub fn is_done(&mut self) -> Result<bool, SchemaError> {
+        while let Some(spec) = self.schema.records.get(self.spec) {
+            let left = match self.left {
+                Some(left) => left,
+                None => match spec.repeat {
+                    Count::UntilEnd => usize::MAX,
+                    ref count => self.env.count(count)?,
+                },
+            };
+            if left > 0 {
+                self.left = Some(left);
+                return Ok(false);
+            }
+            self.spec += 1;
+            self.left = None;
+        }
+        Ok(true)
+    }
+
+    /// Decode `data`, the next record of the file.
+    pub fn decode(&mut self, data: &[u8]) -> Result<Record, SchemaError> {
+        if self.is_done()? {
+            return Err(invalid("more records than the schema has"));
+        }
+        let spec = &self.schema.records[self.spec];
+        let record = self.schema.decode_record(spec, data, &mut self.env)?;
+        if spec.repeat != Count::UntilEnd {
+            self.left = self.left.map(|n| n - 1);
+        }
+        Ok(record)
+    }
+
+    /// Check that the file can end here: only records repeated
+    /// to the end of the file or no times are left.
+    pub fn finish(&mut self) -> Result<(), SchemaError> {
+        while !self.is_done()? {
+            let spec = &self.schema.records[self.spec];
+            if spec.repeat != Count::UntilEnd {
+                return Err(SchemaError::EndOfFile(spec.name.clone()));
+            }
+            self.spec += 1;
+            self.left = None;
+        }
+        Ok(())
+    }
+}
+
+/// Integer fields decoded so far, the last one of a name counting.
+struct Env {
+    values: Vec<(String, i64)>,
+}
+
+impl Env {
+    fn count(&self, count: &Count) -> Result<usize, SchemaError> {
+        match *count {
+            Count::Fixed(n) => Ok(n),
+            Count::Field(ref name) => {
+                let value = self.values.iter().rev().find(|v| v.0.eq_ignore_ascii_case(name))
+                    .ok_or_else(|| SchemaError::UnknownName(name.clone()))?.1;
+                if value < 0 {
+                    return Err(SchemaError::Invalid(format!("{} is negative: {}", name, value)));
+                }
+                Ok(value as usize)
+            },
+            Count::UntilEnd => unreachable!(),
+        }
+    }
+}
+
+impl Schema {
+    pub fn parse(src: &str) -> Result<Schema, SchemaError> {
+        let json = Json::parse(src)?;
+        let endian = match json.get("endian").map(|e| e.as_str()) {
+            None => Endian::Little,
+            Some(Some("little")) => Endian::Little,
+            Some(Some("big")) => Endian::Big,
+            Some(Some("native")) => Endian::native(),
+            Some(_) => return Err(invalid("endian must be little, big or native")),
+        };
+        let records = json.get("records").and_then(|r| r.as_array())
+            .ok_or_else(|| invalid("records must be an array"))?;
+        let records = records.iter().map(parse_record).collect::<Result<Vec<_>, _>>()?;
+        Ok(Schema { endian, records })
+    }
+
+    /// A decoder for records given one by one.
+    pub fn decoder(&self) -> Decoder<'_> {
+        Decoder {
+            schema: self,
+            env: Env { values: vec![] },
+            spec: 0,
+            left: None,
+        }
+    }
+
+    /// Decode the records of `file` from its current position, up to the
+    /// end of the schema. The byte order of the schema applies to the
+    /// record markers too.
+    pub fn decode<S: Read + Seek>(&self, file: &mut SequentialFile<S>) -> Result<Vec<Record>, SchemaError> {
+        file.set_endian(self.endian);
+        let mut decoder = self.decoder();
+        let mut records = vec![];
+        while !decoder.is_done()? {
+            match file.read_record()? {
+                Some(data) => records.push(decoder.decode(&data)?),
+                None => {
+                    decoder.finish()?;
+                    break;
+                },
+            }
+        }
+        Ok(records)
+    }
+
+    fn decode_record(&self, spec: &RecordSpec, data: &[u8], env: &mut Env) -> Result<Record, SchemaError> {
+        let mut read = Cursor::new(data);
+        let mut record = Record { name: spec.name.clone(), fields: vec![] };
+        let fields = match spec.body {
+            RecordBody::Fields(ref fields) => fields,
+            RecordBody::Dispatch { ref key, ref variants } => {
+                self.decode_field(key, &mut read, &mut record, env)?;
+                let value = record.fields[0].1.as_i64().unwrap();
+                let variant = variants.iter().find(|v| v.when == value)
+                    .ok_or_else(|| SchemaError::NoVariant(spec.name.clone(), value))?;
+                record.name = variant.name.clone();
+                &variant.fields
+            },
+        };
+        for field in fields {
+            self.decode_field(field, &mut read, &mut record, env)?;
+        }
+        let extra = data.len() - read.position() as usize;
+        if extra > 0 {
+            return Err(SchemaError::LongRecord(record.name, extra));
+        }
+        Ok(record)
+    }
+
+    fn decode_field(&self, field: &FieldSpec, read: &mut Cursor<&[u8]>, record: &mut Record,
+                    env: &mut Env) -> Result<(), SchemaError> {
+        let dim = match field.dim {
+            Some(ref dim) => Some(dim.iter().map(|d| env.count(d)).collect::<Result<Vec<_>, _>>()?),
+            None => None,
+        };
+        if let Some(ref dim) = dim {
+            // a corrupt count must not allocate more than the record holds
+            let left = read.get_ref().len() as u64 - read.position();
+            let size = field.tag.size().unwrap_or(0).max(1);
+            let bytes = dim.iter().try_fold(size, |n, &d| n.checked_mul(d));
+            if bytes.is_none_or(|b| b as u64 > left) {
+                return Err(SchemaError::ShortRecord(record.name.clone()));
+            }
+        }
+        let mut value = FortranValue::for_type(&FortranType { tag: field.tag, dim });
+        value.read_binary(read, self.endian).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => SchemaError::ShortRecord(record.name.clone()),
+            _ => SchemaError::IoErr(e),
+        })?;
+        if let Some(n) = value.as_i64() {
+            env.values.push((field.name.clone(), n));
+        }
+        record.fields.push((field.name.clone(), value));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use ::schema::{Count, Schema, SchemaError};
+    use ::sequential::SequentialFile;
+    use ::stream::{Endian, FortranBinary};
+    use ::units::Form;
+    use ::value::FortranValue::*;
+
+    fn record(endian: Endian, values: &[&dyn FortranBinary]) -> Vec<u8> {
+        let mut data = vec![];
+        for v in values {
+            v.write_binary(&mut data, endian).unwrap();
+        }
+        data
+    }
+
+    const SCHEMA: &str = r#"{
+        "endian": "big",
+        "records": [
+            {"name": "header", "fields": ["INTEGER NX, NSTEP", "CHARACTER*4 TITLE"]},
+            {"name": "grid", "fields": [{"name": "x", "type": "REAL*8", "dim": ["nx"]}]},
+            {"name": "step", "repeat": "nstep",
+             "key": {"name": "kind", "type": "INTEGER*2"},
+             "variants": [
+                {"when": 1, "name": "time", "fields": ["REAL*8 T"]},
+                {"when": 2, "name": "field", "fields": ["REAL U(NX)", "LOGICAL OK"]}
+             ]},
+            {"name": "trailer", "repeat": "*", "fields": ["INTEGER*8 N"]}
+        ]
+    }"#;
+
+    #[test]
+    fn decode() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        assert_eq!(schema.records[2].repeat, Count::Field("nstep".to_string()));
+
+        let mut file = SequentialFile::new(Cursor::new(vec![]), Form::Unformatted).unwrap();
+        file.set_endian(Endian::Big);
+        file.write_record(&record(Endian::Big, &[&2i32, &2i32, &"abcd".to_string()])).unwrap();
+        file.write_record(&record(Endian::Big, &[&vec![0.5f64, 1.5]])).unwrap();
+        file.write_record(&record(Endian::Big, &[&1i16, &3.0f64])).unwrap();
+        file.write_record(&record(Endian::Big, &[&2i16, &vec![1.0f32, 2.0], &true])).unwrap();
+        file.write_record(&record(Endian::Big, &[&7i64])).unwrap();
+        file.write_record(&record(Endian::Big, &[&8i64])).unwrap();
+        file.rewind().unwrap();
+
+        let records = schema.decode(&mut file).unwrap();
+        let names: Vec<&str> = records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["header", "grid", "time", "field", "trailer", "trailer"]);
+        assert_eq!(records[0].get("title"), Some(&Strin("abcd".to_string())));
+        assert_eq!(records[1].get("X"), Some(&Array(vec![Real8(0.5), Real8(1.5)])));
+        assert_eq!(records[2].fields, [("kind".to_string(), Int2(1)), ("T".to_string(), Real8(3.0))]);
+        assert_eq!(records[3].get("U"), Some(&Array(vec![Real4(1.0), Real4(2.0)])));
+        assert_eq!(records[3].get("OK"), Some(&Bool(true)));
+        assert_eq!(records[5].get("N"), Some(&Int8(8)));
+    }
+
+    #[test]
+    fn errors() {
+        let mut file = SequentialFile::new(Cursor::new(vec![]), Form::Unformatted).unwrap();
+        file.set_endian(Endian::Big);
+        file.write_record(&record(Endian::Big, &[&1i32, &3i32])).unwrap();
+        file.write_record(&record(Endian::Big, &[&3i16])).unwrap();
+        file.rewind().unwrap();
+        let schema = Schema::parse(SCHEMA).unwrap();
+        match schema.decode(&mut file) {
+            Err(SchemaError::ShortRecord(ref r)) if r == "header" => {},
+            x => panic!("expected ShortRecord, got {:?}", x),
+        }
+
+        let mut file = SequentialFile::new(Cursor::new(vec![]), Form::Unformatted).unwrap();
+        file.set_endian(Endian::Big);
+        file.write_record(&record(Endian::Big, &[&1i32, &1i32, &"abcd".to_string(), &0i16])).unwrap();
+        file.rewind().unwrap();
+        match schema.decode(&mut file) {
+            Err(SchemaError::LongRecord(ref r, 2)) if r == "header" => {},
+            x => panic!("expected LongRecord, got {:?}", x),
+        }
+
+        assert!(matches!(Schema::parse(r#"{"records": [{"name": "a", "fields": ["REAL*3 X"]}]}"#),
+                         Err(SchemaError::Decl(_, _))));
+        assert!(matches!(Schema::parse(r#"{"records": [{"name": "a", "repeat": -1, "fields": []}]}"#),
+                         Err(SchemaError::Invalid(_))));
+        assert!(matches!(Schema::parse(r#"{"records": [{"name": "a", "key": "REAL X", "variants": []}]}"#),
+                         Err(SchemaError::Invalid(_))));
+        assert!(matches!(Schema::parse("{"), Err(SchemaError::Json(_))));
+    }
+
+    #[test]
+    fn dispatch_errors() {
+        let schema = Schema::parse(r#"{"records": [{"name": "a", "fields": ["INTEGER N"]},
+            {"name": "b", "repeat": "n", "key": {"name": "k", "type": "INTEGER*2"},
+             "variants": [{"when": 1, "fields": []}]}]}"#).unwrap();
+        let mut file = SequentialFile::new(Cursor::new(vec![]), Form::Unformatted).unwrap();
+        file.write_record(&record(Endian::Little, &[&2i32])).unwrap();
+        file.write_record(&record(Endian::Little, &[&1i16])).unwrap();
+        file.write_record(&record(Endian::Little, &[&5i16])).unwrap();
+        file.rewind().unwrap();
+        match schema.decode(&mut file) {
+            Err(SchemaError::NoVariant(ref r, 5)) if r == "b" => {},
+            x => panic!("expected NoVariant, got {:?}", x),
+        }
+
+        let mut file = SequentialFile::new(Cursor::new(vec![]), Form::Unformatted).unwrap();
+        for _ in 0..3 {
+            file.write_record(&[]).unwrap();
+        }
+        file.rewind().unwrap();
+        let schema = Schema::parse(r#"{"records": [{"name": "a", "repeat": 4, "fields": []}]}"#).unwrap();
+        assert!(matches!(schema.decode(&mut file), Err(SchemaError::EndOfFile(_))));
+    }
+
+    #[test]
+    fn corrupt_count() {
+        let schema = Schema::parse(r#"{"records": [{"name": "a", "fields": ["INTEGER N, M",
+            {"name": "x", "type": "REAL*8", "dim": ["n", "m"]}]}]}"#).unwrap();
+        for &(n, m) in [(1 << 30, 1), (i32::MAX, i32::MAX)].iter() {
+            let mut file = SequentialFile::new(Cursor::new(vec![]), Form::Unformatted).unwrap();
+            file.write_record(&record(Endian::Little, &[&n, &m, &1.0f64])).unwrap();
+            file.rewind().unwrap();
+            match schema.decode(&mut file) {
+                Err(SchemaError::ShortRecord(ref r)) if r == "a" => {},
+                x => panic!("expected ShortRecord, got {:?}", x),
+            }
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! Sequential files that can be repositioned
+//!
+//! A `SequentialFile` keeps track of the records of a seekable stream
+//! so that it can go back to the start of the file or to the previous
+//! record, as REWIND and BACKSPACE do, and end the file at the current
+//! record, as ENDFILE does. Like a Fortran sequential write, the first
+//! write after opening, reading or repositioning also ends the file
+//! where it starts, so no records of the old contents follow it.
+//!
+//! Formatted records are lines, read and written through `BufRead` and
+//! `Write`; the offsets of the records passed are kept in an index.
+//! Unformatted records are framed by their length in bytes, a 32-bit
+//! marker before and after the data as gfortran and ifort write them,
+//! which lets BACKSPACE step back without an index. The markers are
+//! little-endian unless `set_endian` says otherwise.
+
+use std::fs::File;
+use std::io::{self, BufRead, Cursor, Read, Seek, Write};
+use positioned::{read_buffered, PositionedBuf};
+use stream::Endian;
+use units::Form;
+
+/// Streams that can be cut at a given length, as ENDFILE needs.
+pub trait SetLen {
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+}
+
+impl SetLen for File {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+}
+
+impl SetLen for Cursor<Vec<u8>> {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.get_mut().truncate(len as usize);
+        Ok(())
+    }
+}
+
+pub struct SequentialFile<S: Read + Seek> {
+    inner: PositionedBuf<S>,
+    form: Form,
+    endian: Endian,
+    // offsets of the records passed, the last one is the current record
+    index: Vec<u64>,
+    // the next write ends the file where it starts
+    truncate: bool,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<S: Read + Seek> SequentialFile<S> {
+    /// A file of `form` records positioned where `inner` is.
+    pub fn new(inner: S, form: Form) -> io::Result<SequentialFile<S>> {
+        let inner = PositionedBuf::new(inner)?;
+        let pos = inner.pos();
+        Ok(SequentialFile {
+            inner,
+            form,
+            endian: Endian::Little,
+            index: vec![pos],
+            truncate: true,
+        })
+    }
+
+    pub fn form(&self) -> Form {
+        self.form
+    }
+
+    /// Byte order of the record markers.
+    pub fn set_endian(&mut self, endian: Endian) {
+        self.endian = endian;
+    }
+
+    pub fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.inner.get_ref()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner.into_inner()
+    }
+
+    /// Number of the current record counted from zero, for formatted
+    /// records only; the records before the initial position are not counted.
+    pub fn record(&self) -> usize {
+        self.index.len() - 1
+    }
+
+    fn seek_to(&mut self, pos: u64) -> io::Result<()> {
+        self.inner.seek_to(pos)?;
+        self.truncate = true;
+        Ok(())
+    }
+
+    /// Go to the start of the file, as REWIND does.
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.index = vec![0];
+        self.seek_to(0)
+    }
+
+    /// Go to the start of the current record, or of the previous one if
+    /// the file is at the start of a record, as BACKSPACE does. At the
+    /// start of the file this does nothing.
+    pub fn backspace(&mut self) -> io::Result<()> {
+        match self.form {
+            Form::Formatted => {
+                let start = *self.index.last().unwrap();
+                if self.inner.pos() > start {
+                    return self.seek_to(start);
+                }
+                if self.index.len() == 1 {
+                    if start > 0 {
+                        return Err(io::Error::new(io::ErrorKind::Unsupported,
+                                                  "cannot backspace before the records passed"));
+                    }
+                    return Ok(());
+                }
+                self.index.pop();
+                let start = *self.index.last().unwrap();
+                self.seek_to(start)
+            },
+            Form::Unformatted => {
+                if self.inner.pos() == 0 {
+                    return Ok(());
+                }
+                let end = self.inner.pos().checked_sub(4).ok_or_else(|| invalid("record marker missing"))?;
+                self.seek_to(end)?;
+                let len = self.read_marker()?.ok_or_else(|| invalid("record marker missing"))?;
+                let start = end.checked_sub(len + 4).ok_or_else(|| invalid("record marker mismatch"))?;
+                self.seek_to(start)
+            },
+        }
+    }
+
+    fn read_marker(&mut self) -> io::Result<Option<u64>> {
+        let mut marker = [0u8; 4];
+        let mut read = 0;
+        while read < 4 {
+            match self.read(&mut marker[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(invalid("record marker cut short")),
+                n => read += n,
+            }
+        }
+        let len = match self.endian {
+            Endian::Little => i32::from_le_bytes(marker),
+            Endian::Big => i32::from_be_bytes(marker),
+        };
+        if len < 0 {
+            return Err(invalid("records split into subrecords are not supported"));
+        }
+        Ok(Some(len as u64))
+    }
+
+    /// Read the next unformatted record, `None` at the end of the file.
+    pub fn read_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let len = match self.read_marker()? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        // the marker is not trusted to size the buffer
+        let mut data = vec![];
+        (&mut *self).take(len).read_to_end(&mut data)?;
+        if (data.len() as u64) < len {
+            return Err(invalid("record cut short"));
+        }
+        if self.read_marker()? != Some(len) {
+            return Err(invalid("record marker mismatch"));
+        }
+        Ok(Some(data))
+    }
+}
+
+impl<S: Read + Write + Seek + SetLen> SequentialFile<S> {
+    /// Write `data` as an unformatted record.
+    pub fn write_record(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.len() > i32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "record too long"));
+        }
+        let marker = match self.endian {
+            Endian::Little => (data.len() as i32).to_le_bytes(),
+            Endian::Big => (data.len() as i32).to_be_bytes(),
+        };
+        self.write_all(&marker)?;
+        self.write_all(data)?;
+        self.write_all(&marker)
+    }
+
+    /// End the file at the current position, as ENDFILE does.
+    pub fn endfile(&mut self) -> io::Result<()> {
+        let pos = self.inner.pos();
+        self.seek_to(pos)?;
+        self.inner.get_mut().set_len(pos)
+    }
+}
+
+impl<S: Read + Seek> Read for SequentialFile<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read_buffered(self, buf)
+    }
+}
+
+impl<S: Read + Seek> BufRead for SequentialFile<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.form == Form::Formatted {
+            let pos = self.inner.pos();
+            let ends = self.inner.buffer()[..amt].iter().enumerate()
+                .filter(|&(_, &c)| c == b'\n')
+                .map(|(i, _)| pos + i as u64 + 1);
+            self.index.extend(ends);
+        }
+        self.inner.consume(amt);
+        self.truncate |= amt > 0;
+    }
+}
+
+impl<S: Read + Write + Seek + SetLen> Write for SequentialFile<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let pos = self.inner.pos();
+        if self.truncate {
+            // drop what was read ahead and what follows in the file
+            self.seek_to(pos)?;
+            self.inner.get_mut().set_len(pos)?;
+            self.index.retain(|&start| start <= pos);
+            self.truncate = false;
+        }
+        let len = self.inner.write(buf)?;
+        if self.form == Form::Formatted {
+            let ends = buf[..len].iter().enumerate()
+                .filter(|&(_, &c)| c == b'\n')
+                .map(|(i, _)| pos + i as u64 + 1);
+            self.index.extend(ends);
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Cursor, Write};
+    use ::format::parse_format;
+    use ::read::FortranIterReader;
+    use ::sequential::SequentialFile;
+    use ::stream::Endian;
+    use ::units::Form;
+    use ::write::FortranIterWriter;
+
+    fn read_int(file: &mut SequentialFile<Cursor<Vec<u8>>>, src: &str) -> i32 {
+        let fmt = parse_format(src).unwrap();
+        let mut val = 0;
+        let mut reader = FortranIterReader::new(&fmt, file);
+        reader.consume_constants(true).unwrap();
+        reader.read_value(&mut val).unwrap();
+        reader.consume_constants(false).unwrap();
+        val
+    }
+
+    #[test]
+    fn formatted() {
+        let mut file = SequentialFile::new(Cursor::new(b"1 10\n2 20\n3 30\n".to_vec()), Form::Formatted).unwrap();
+        assert_eq!(read_int(&mut file, "(I1)"), 1);
+        assert_eq!(read_int(&mut file, "(I1)"), 2);
+        // read the same record again with another format
+        file.backspace().unwrap();
+        assert_eq!(read_int(&mut file, "(2X, I2)"), 20);
+        file.backspace().unwrap();
+        file.backspace().unwrap();
+        assert_eq!(file.record(), 0);
+        assert_eq!(read_int(&mut file, "(2X, I2)"), 10);
+        file.backspace().unwrap();
+        file.backspace().unwrap();
+        assert_eq!(read_int(&mut file, "(I1)"), 1);
+
+        file.endfile().unwrap();
+        assert_eq!(file.get_ref().get_ref(), b"1 10\n");
+        {
+            let fmt = parse_format("(I1, I3)").unwrap();
+            let mut writer = FortranIterWriter::new(&fmt);
+            writer.write_value(&mut file, &vec![4, 40]).unwrap();
+            writer.write_constants(&mut file, false).unwrap();
+        }
+        file.rewind().unwrap();
+        assert_eq!(read_int(&mut file, "(I1)"), 1);
+        assert_eq!(read_int(&mut file, "(1X, I3)"), 40);
+        assert_eq!(file.record(), 2);
+    }
+
+    #[test]
+    fn write_after_backspace() {
+        let mut file = SequentialFile::new(Cursor::new(b"line one\nline two\n".to_vec()), Form::Formatted).unwrap();
+        let mut line = String::new();
+        file.read_line(&mut line).unwrap();
+        assert_eq!(line, "line one\n");
+        file.backspace().unwrap();
+        file.write_all(b"X\n").unwrap();
+        assert_eq!(file.get_ref().get_ref(), b"X\n");
+        assert_eq!(file.record(), 1);
+        file.write_all(b"Y\n").unwrap();
+        file.rewind().unwrap();
+        file.read_line(&mut line).unwrap();
+        file.write_all(b"Z\n").unwrap();
+        assert_eq!(file.get_ref().get_ref(), b"X\nZ\n");
+    }
+
+    #[test]
+    fn unformatted() {
+        let mut file = SequentialFile::new(Cursor::new(vec![]), Form::Unformatted).unwrap();
+        for r in [&b"first"[..], b"", b"third"].iter() {
+            file.write_record(r).unwrap();
+        }
+        assert_eq!(file.get_ref().get_ref().len(), 3 * 8 + 10);
+        file.backspace().unwrap();
+        file.backspace().unwrap();
+        assert_eq!(file.read_record().unwrap().unwrap(), b"");
+        file.endfile().unwrap();
+        file.rewind().unwrap();
+        assert_eq!(file.read_record().unwrap().unwrap(), b"first");
+        assert_eq!(file.read_record().unwrap().unwrap(), b"");
+        assert_eq!(file.read_record().unwrap(), None);
+        file.rewind().unwrap();
+        file.backspace().unwrap();
+        assert_eq!(file.read_record().unwrap().unwrap(), b"first");
+
+        let mut file = SequentialFile::new(Cursor::new(vec![]), Form::Unformatted).unwrap();
+        file.set_endian(Endian::Big);
+        file.write_record(b"ab").unwrap();
+        assert_eq!(file.get_ref().get_ref(), b"\0\0\0\x02ab\0\0\0\x02");
+
+        // a marker claiming more than the file holds
+        let mut file = SequentialFile::new(Cursor::new(b"\xff\xff\xff\x7fab".to_vec()), Form::Unformatted).unwrap();
+        let err = file.read_record().unwrap_err();
+        assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidData);
+    }
+}
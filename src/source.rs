@@ -0,0 +1,422 @@
+//! FORMAT statements of Fortran source files
+//!
+//! A `FormatTable` holds the labeled FORMAT statements of a source
+//! file, in fixed or free form, so that the formats of a program can
+//! be looked up by their label. Formats are parsed leniently, missing
+//! commas being accepted as compilers do; a statement that still does
+//! not parse is left out of the table and its error kept beside it.
+//!
+//! ```
+//! use f77_io::source::{FormatTable, SourceForm};
+//! let src = "      WRITE(6, 100) N, X\n  100 FORMAT(1X, I5,\n     &       F10.3) ! result\n";
+//! let table = FormatTable::parse(src, SourceForm::Fixed);
+//! assert_eq!(table.get(100).unwrap().to_string(), "(1X, I5, F10.3)");
+//! assert!(table.errors().is_empty());
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use format::{FormatNode, FormatParser, ParseError};
+
+/// The source form of a file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SourceForm {
+    /// Labels in columns 1 to 5, continuation in column 6 and the
+    /// statement in columns 7 to 72, C or * comment lines.
+    Fixed,
+    /// Statements continued with `&`.
+    Free,
+}
+
+impl SourceForm {
+    /// The form a file is usually written in by its extension: fixed
+    /// for `.f`, `.for`, `.ftn` and `.f77`, free otherwise.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> SourceForm {
+        let ext = path.as_ref().extension().and_then(|e| e.to_str()).unwrap_or("");
+        match ext.to_ascii_lowercase().as_str() {
+            "f" | "for" | "ftn" | "f77" => SourceForm::Fixed,
+            _ => SourceForm::Free,
+        }
+    }
+}
+
+/// A place in a source file, the line and column counted from one.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    IoErr(io::Error),
+    /// The format of the statement with the label does not parse.
+    Parse(usize, Span, ParseError),
+    /// Characters after the closing parenthesis of a format.
+    Trailing(usize, Span),
+    /// A label given to a second FORMAT statement.
+    DuplicateLabel(usize, Span),
+}
+
+impl From<io::Error> for SourceError {
+    fn from(x: io::Error) -> SourceError {
+        SourceError::IoErr(x)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatStatement {
+    pub label: usize,
+    pub format: FormatNode,
+    /// Where the statement starts.
+    pub span: Span,
+}
+
+/// The labeled FORMAT statements of a source file, and the errors of
+/// the statements that could not be added.
+#[derive(Debug, Default)]
+pub struct FormatTable {
+    statements: Vec<FormatStatement>,
+    errors: Vec<SourceError>,
+}
+
+impl FormatTable {
+    pub fn parse(src: &str, form: SourceForm) -> FormatTable {
+        let lines = match form {
+            SourceForm::Fixed => fixed_statements(src),
+            SourceForm::Free => free_statements(src),
+        };
+        let mut table = FormatTable::default();
+        for line in lines {
+            for stmt in split_statements(line) {
+                match format_statement(&stmt) {
+                    Ok(Some(stmt)) => {
+                        if table.statements.iter().any(|s| s.label == stmt.label) {
+                            // the first statement with the label is kept
+                            table.errors.push(SourceError::DuplicateLabel(stmt.label, stmt.span));
+                        } else {
+                            table.statements.push(stmt);
+                        }
+                    },
+                    Ok(None) => {},
+                    Err(e) => table.errors.push(e),
+                }
+            }
+        }
+        table
+    }
+
+    /// The FORMAT statements of the file at `path`, in the form
+    /// its extension gives.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FormatTable, SourceError> {
+        let src = fs::read_to_string(path.as_ref())?;
+        Ok(FormatTable::parse(&src, SourceForm::from_path(path)))
+    }
+
+    /// The format of the statement labeled `label`.
+    pub fn get(&self, label: usize) -> Option<&FormatNode> {
+        self.statement(label).map(|s| &s.format)
+    }
+
+    pub fn statement(&self, label: usize) -> Option<&FormatStatement> {
+        self.statements.iter().find(|s| s.label == label)
+    }
+
+    /// The statements in the order of the source.
+    pub fn statements(&self) -> &[FormatStatement] {
+        &self.statements
+    }
+
+    /// The errors of the FORMAT statements left out, in the order of the source.
+    pub fn errors(&self) -> &[SourceError] {
+        &self.errors
+    }
+}
+
+/// A statement with its continuation lines joined, comments left out,
+/// each character with the place it came from. Letters outside of
+/// character contexts are in upper case, as case does not matter there.
+struct Statement {
+    label: Option<usize>,
+    text: Vec<(char, Span)>,
+    start: Span,
+}
+
+/// Where a statement is in a character context.
+#[derive(Default)]
+struct Scan {
+    quote: Option<char>,
+    hollerith: usize,
+}
+
+impl Statement {
+    fn new(label: Option<usize>, start: Span) -> Statement {
+        Statement { label, text: vec![], start }
+    }
+
+    /// The count of a Hollerith descriptor ending the text, if an H
+    /// coming next would start one.
+    fn hollerith_count(&self) -> Option<usize> {
+        let mut chars = self.text.iter().rev().map(|t| t.0).filter(|&c| c != ' ').peekable();
+        let mut digits = vec![];
+        while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+            digits.push(c);
+        }
+        match chars.next() {
+            Some('(') | Some(',') | Some('/') if !digits.is_empty() => {
+                digits.iter().rev().collect::<String>().parse().ok()
+            },
+            _ => None,
+        }
+    }
+
+    /// Add the characters of a line, up to an in-line comment.
+    fn push<I: Iterator<Item=(char, Span)>>(&mut self, scan: &mut Scan, chars: I) {
+        for (mut c, span) in chars {
+            if scan.hollerith > 0 {
+                scan.hollerith -= 1;
+            } else if let Some(q) = scan.quote {
+                if c == q {
+                    scan.quote = None;
+                }
+            } else {
+                match c {
+                    '!' => return,
+                    '\'' | '"' => scan.quote = Some(c),
+                    'H' | 'h' => scan.hollerith = self.hollerith_count().unwrap_or(0),
+                    _ => {},
+                }
+                c = c.to_ascii_uppercase();
+            }
+            self.text.push((c, span));
+        }
+    }
+}
+
+/// The label of a statement, `None` if there is none or it is not a number.
+fn parse_label(field: &str) -> Option<usize> {
+    let digits: String = field.chars().filter(|&c| c != ' ').collect();
+    digits.parse().ok()
+}
+
+fn fixed_statements(src: &str) -> Vec<Statement> {
+    let mut statements: Vec<Statement> = vec![];
+    let mut scan = Scan::default();
+    for (i, line) in src.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let first = match chars.iter().position(|&c| c != ' ' && c != '\t') {
+            Some(first) => first,
+            None => continue,
+        };
+        if "Cc*".contains(chars[0]) || (chars[first] == '!' && first != 5) {
+            continue;
+        }
+        // a tab in the first columns ends the label, a digit after it
+        // marks a continuation line
+        let (label, continued, start, end) = match chars.iter().position(|&c| c == '\t') {
+            Some(t) if t < 6 => {
+                let continued = chars.get(t + 1).is_some_and(|&c| ('1'..='9').contains(&c));
+                (&chars[..t], continued, t + 1 + continued as usize, chars.len())
+            },
+            _ => {
+                let continued = chars.get(5).is_some_and(|&c| c != ' ' && c != '0');
+                (&chars[..chars.len().min(5)], continued, 6, chars.len().min(72))
+            },
+        };
+        let text = (start..end.max(start)).map(|j| (chars[j], Span { line: i + 1, column: j + 1 }));
+        match statements.last_mut() {
+            Some(stmt) if continued => stmt.push(&mut scan, text),
+            _ => {
+                scan = Scan::default();
+                let label = parse_label(&label.iter().collect::<String>());
+                let mut stmt = Statement::new(label, Span { line: i + 1, column: first + 1 });
+                stmt.push(&mut scan, text);
+                statements.push(stmt);
+            },
+        }
+    }
+    statements
+}
+
+fn free_statements(src: &str) -> Vec<Statement> {
+    let mut statements: Vec<Statement> = vec![];
+    let mut scan = Scan::default();
+    let mut continued = false;
+    for (i, line) in src.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut start = match chars.iter().position(|&c| c != ' ' && c != '\t') {
+            Some(first) if chars[first] != '!' => first,
+            _ => continue,
+        };
+        let stmt = match statements.last_mut() {
+            Some(stmt) if continued => {
+                if chars[start] == '&' {
+                    start += 1;
+                }
+                stmt
+            },
+            _ => {
+                scan = Scan::default();
+                let span = Span { line: i + 1, column: start + 1 };
+                let digits = chars[start..].iter().take_while(|c| c.is_ascii_digit()).count();
+                let label = if digits > 0 && chars.get(start + digits).is_none_or(|&c| c == ' ' || c == '\t') {
+                    let label = parse_label(&chars[start..start + digits].iter().collect::<String>());
+                    start += digits;
+                    label
+                } else {
+                    None
+                };
+                statements.push(Statement::new(label, span));
+                statements.last_mut().unwrap()
+            },
+        };
+        let len = stmt.text.len();
+        let text = (start..chars.len()).map(|j| (chars[j], Span { line: i + 1, column: j + 1 }));
+        stmt.push(&mut scan, text);
+        while stmt.text.len() > len && stmt.text.last().is_some_and(|t| t.0 == ' ' || t.0 == '\t') {
+            stmt.text.pop();
+        }
+        continued = stmt.text.len() > len && stmt.text.last().is_some_and(|t| t.0 == '&');
+        if continued {
+            stmt.text.pop();
+        }
+    }
+    statements
+}
+
+/// Split a line at the semicolons that end statements.
+fn split_statements(stmt: Statement) -> Vec<Statement> {
+    let mut out = vec![Statement::new(stmt.label, stmt.start)];
+    let mut quote = None;
+    for (c, span) in stmt.text {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, ';') => {
+                out.push(Statement::new(None, span));
+                continue;
+            },
+            _ => {},
+        }
+        out.last_mut().unwrap().text.push((c, span));
+    }
+    out
+}
+
+/// The format of a labeled FORMAT statement, `None` for other statements.
+fn format_statement(stmt: &Statement) -> Result<Option<FormatStatement>, SourceError> {
+    let label = match stmt.label {
+        Some(label) => label,
+        None => return Ok(None),
+    };
+    // blanks are not significant in the keyword of fixed form sources
+    let mut rest = stmt.text.iter().skip_while(|t| t.0 == ' ');
+    let mut keyword = String::new();
+    while keyword.len() < 6 {
+        match rest.next() {
+            Some(&(' ', _)) => {},
+            Some(&(c, _)) => keyword.push(c),
+            None => return Ok(None),
+        }
+    }
+    let spec: Vec<(char, Span)> = rest.cloned().collect();
+    let paren = spec.iter().find(|t| t.0 != ' ').map(|t| t.0);
+    if !keyword.eq_ignore_ascii_case("format") || paren != Some('(') {
+        return Ok(None);
+    }
+
+    let text: String = spec.iter().map(|t| t.0).collect();
+    let span_at = |p: usize| spec.get(p).or(spec.last()).map(|t| t.1).unwrap_or(stmt.start);
+    let mut it = text.chars().peekable();
+    let mut parser = FormatParser::new(&mut it);
+    parser.set_lenient(true);
+    let format = match parser.parse() {
+        Ok(format) => format,
+        Err(e) => return Err(SourceError::Parse(label, span_at(e.position()), e)),
+    };
+    if let Some(extra) = spec[parser.position()..].iter().find(|t| t.0 != ' ') {
+        return Err(SourceError::Trailing(label, extra.1));
+    }
+    Ok(Some(FormatStatement { label, format, span: stmt.start }))
+}
+
+#[cfg(test)]
+mod tests {
+    use ::format::ParseError;
+    use ::source::{FormatTable, SourceError, SourceForm, Span};
+
+    #[test]
+    fn fixed_form() {
+        let src = [
+            "C     A COMMENT WITH FORMAT(\n",
+            "      PROGRAM P\n",
+            "  100 FORMAT(1X, 'A!B', I5, ! first\n",
+            "*     a comment between\n",
+            "     1  2HX!,\n",
+            &format!("     2  F10.3){:58}PAST COLUMN 72\n", ""),
+            "1 0 1 FOR MAT (A)\n",
+            "102\tFORMAT(A,\n",
+            "\t1L2)\n",
+            "      X = 1; Y = 2\n",
+            "      FORMAT(I5)\n",
+            "      END\n"].concat();
+        let table = FormatTable::parse(&src, SourceForm::Fixed);
+        assert!(table.errors().is_empty());
+        let labels: Vec<usize> = table.statements().iter().map(|s| s.label).collect();
+        assert_eq!(labels, vec![100, 101, 102]);
+        assert_eq!(table.get(100).unwrap().to_string(), "(1X, 'A!B', I5, 2HX!, F10.3)");
+        assert_eq!(table.statement(100).unwrap().span, Span { line: 3, column: 3 });
+        assert_eq!(table.get(101).unwrap().to_string(), "(A)");
+        assert_eq!(table.get(102).unwrap().to_string(), "(A, L2)");
+        assert!(table.get(103).is_none());
+    }
+
+    #[test]
+    fn free_form() {
+        let src = concat!(
+            "program p\n",
+            "  write(*, 10) n ! 20 format(a)\n",
+            "10 format(1x, i5, & ! count\n",
+            "  ! a comment between\n",
+            "        & 'it''s &\n",
+            "        &done') ; x = 1\n",
+            "30 format (F8.3)\n",
+            "end program\n");
+        let table = FormatTable::parse(src, SourceForm::Free);
+        assert!(table.errors().is_empty());
+        let labels: Vec<usize> = table.statements().iter().map(|s| s.label).collect();
+        assert_eq!(labels, vec![10, 30]);
+        assert_eq!(table.get(10).unwrap().to_string(), "(1X, I5, 'it''s done')");
+        assert_eq!(table.statement(30).unwrap().span, Span { line: 7, column: 1 });
+        assert_eq!(SourceForm::from_path("a/b.F"), SourceForm::Fixed);
+        assert_eq!(SourceForm::from_path("b.f90"), SourceForm::Free);
+    }
+
+    #[test]
+    fn errors() {
+        let src = [
+            "      X = 1\n",
+            "  100 FORMAT(I5,\n",
+            "     &  W3)\n",
+            "  200 FORMAT(I5F10.3)\n",
+            "  300 FORMAT(I5) X\n",
+            "  200 FORMAT(A)\n",
+            "  400 FORMAT(L2)\n"].concat();
+        let table = FormatTable::parse(&src, SourceForm::Fixed);
+        let labels: Vec<usize> = table.statements().iter().map(|s| s.label).collect();
+        // a missing comma is accepted, the first statement 200 is kept
+        assert_eq!(labels, vec![200, 400]);
+        assert_eq!(table.get(200).unwrap().to_string(), "(I5, F10.3)");
+        match table.errors() {
+            [SourceError::Parse(100, parse, ParseError::UnexpectedChar(_, 'W')),
+             SourceError::Trailing(300, trailing),
+             SourceError::DuplicateLabel(200, duplicate)] => {
+                assert_eq!(*parse, Span { line: 3, column: 9 });
+                assert_eq!(*trailing, Span { line: 5, column: 18 });
+                assert_eq!(*duplicate, Span { line: 6, column: 3 });
+            },
+            x => panic!("expected three errors, got {:?}", x),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Stream access: byte-addressed files without record markers
+//!
+//! A `StreamFile` reads and writes values in their binary form at the
+//! current position or at a position given as POS=, counted from 1 as
+//! in Fortran. It is also a `BufRead` and a `Write`, so that the
+//! formatted readers and writers use it as a formatted stream, where
+//! records end at newlines but any byte can be positioned to.
+
+use std::io::{self, BufRead, Read, Seek, Write};
+use positioned::PositionedBuf;
+use types::Complex;
+
+/// Byte order of the values in a file, CONVERT= of some compilers.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// The byte order of the machine.
+    pub fn native() -> Endian {
+        if cfg!(target_endian = "big") { Endian::Big } else { Endian::Little }
+    }
+}
+
+impl Default for Endian {
+    fn default() -> Endian {
+        Endian::native()
+    }
+}
+
+/// Values stored in their binary form, as unformatted I/O does.
+/// Reading fills the value in place: arrays and strings keep their
+/// length, as Fortran variables do.
+pub trait FortranBinary {
+    fn read_binary(&mut self, read: &mut dyn Read, endian: Endian) -> io::Result<()>;
+    fn write_binary(&self, write: &mut dyn Write, endian: Endian) -> io::Result<()>;
+}
+
+macro_rules! impl_binary_num {
+    ($ty: ty) => {
+        impl FortranBinary for $ty {
+            fn read_binary(&mut self, read: &mut dyn Read, endian: Endian) -> io::Result<()> {
+                let mut bytes = [0u8; ::std::mem::size_of::<$ty>()];
+                read.read_exact(&mut bytes)?;
+                *self = match endian {
+                    Endian::Little => <$ty>::from_le_bytes(bytes),
+                    Endian::Big => <$ty>::from_be_bytes(bytes),
+                };
+                Ok(())
+            }
+
+            fn write_binary(&self, write: &mut dyn Write, endian: Endian) -> io::Result<()> {
+                match endian {
+                    Endian::Little => write.write_all(&self.to_le_bytes()),
+                    Endian::Big => write.write_all(&self.to_be_bytes()),
+                }
+            }
+        }
+    }
+}
+
+impl_binary_num!(i8);
+impl_binary_num!(u8);
+impl_binary_num!(i16);
+impl_binary_num!(i32);
+impl_binary_num!(i64);
+impl_binary_num!(u16);
+impl_binary_num!(u32);
+impl_binary_num!(u64);
+impl_binary_num!(f32);
+impl_binary_num!(f64);
+
+/// A default LOGICAL, four bytes, any value but zero is true.
+impl FortranBinary for bool {
+    fn read_binary(&mut self, read: &mut dyn Read, endian: Endian) -> io::Result<()> {
+        let mut val = 0i32;
+        val.read_binary(read, endian)?;
+        *self = val != 0;
+        Ok(())
+    }
+
+    fn write_binary(&self, write: &mut dyn Write, endian: Endian) -> io::Result<()> {
+        (*self as i32).write_binary(write, endian)
+    }
+}
+
+impl<T: FortranBinary> FortranBinary for Complex<T> {
+    fn read_binary(&mut self, read: &mut dyn Read, endian: Endian) -> io::Result<()> {
+        self.re.read_binary(read, endian)?;
+        self.im.read_binary(read, endian)
+    }
+
+    fn write_binary(&self, write: &mut dyn Write, endian: Endian) -> io::Result<()> {
+        self.re.write_binary(write, endian)?;
+        self.im.write_binary(write, endian)
+    }
+}
+
+/// CHARACTER data in Latin-1, a byte for each character the string
+/// has. Characters past U+00FF are written as `?`.
+impl FortranBinary for String {
+    fn read_binary(&mut self, read: &mut dyn Read, _endian: Endian) -> io::Result<()> {
+        let mut bytes = vec![0; self.chars().count()];
+        read.read_exact(&mut bytes)?;
+        *self = bytes.iter().map(|&b| b as char).collect();
+        Ok(())
+    }
+
+    fn write_binary(&self, write: &mut dyn Write, _endian: Endian) -> io::Result<()> {
+        let bytes: Vec<u8> = self.chars().map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' }).collect();
+        write.write_all(&bytes)
+    }
+}
+
+impl<T: FortranBinary> FortranBinary for [T] {
+    fn read_binary(&mut self, read: &mut dyn Read, endian: Endian) -> io::Result<()> {
+        for x in self.iter_mut() {
+            x.read_binary(read, endian)?;
+        }
+        Ok(())
+    }
+
+    fn write_binary(&self, write: &mut dyn Write, endian: Endian) -> io::Result<()> {
+        for x in self.iter() {
+            x.write_binary(write, endian)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: FortranBinary> FortranBinary for Vec<T> {
+    fn read_binary(&mut self, read: &mut dyn Read, endian: Endian) -> io::Result<()> {
+        self[..].read_binary(read, endian)
+    }
+
+    fn write_binary(&self, write: &mut dyn Write, endian: Endian) -> io::Result<()> {
+        self[..].write_binary(write, endian)
+    }
+}
+
+/// A file opened with ACCESS='STREAM'.
+pub struct StreamFile<S: Read + Seek> {
+    inner: PositionedBuf<S>,
+    endian: Endian,
+}
+
+impl<S: Read + Seek> StreamFile<S> {
+    /// A stream positioned where `inner` is, with values in `endian` order.
+    pub fn new(inner: S, endian: Endian) -> io::Result<StreamFile<S>> {
+        Ok(StreamFile {
+            inner: PositionedBuf::new(inner)?,
+            endian,
+        })
+    }
+
+    pub fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.inner.get_ref()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner.into_inner()
+    }
+
+    /// The position of the next byte, from 1, as INQUIRE POS= gives it.
+    pub fn pos(&self) -> u64 {
+        self.inner.pos() + 1
+    }
+
+    /// Go to `pos`, counted from 1.
+    pub fn set_pos(&mut self, pos: u64) -> io::Result<()> {
+        if pos == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "POS= counts from 1"));
+        }
+        self.inner.seek_to(pos - 1)
+    }
+
+    /// Read `val` at the current position.
+    pub fn read_value<T: FortranBinary + ?Sized>(&mut self, val: &mut T) -> io::Result<()> {
+        let endian = self.endian;
+        val.read_binary(self, endian)
+    }
+
+    /// Read `val` at `pos`, as READ with POS= does.
+    pub fn read_at<T: FortranBinary + ?Sized>(&mut self, pos: u64, val: &mut T) -> io::Result<()> {
+        self.set_pos(pos)?;
+        self.read_value(val)
+    }
+}
+
+impl<S: Read + Write + Seek> StreamFile<S> {
+    /// Write `val` at the current position.
+    pub fn write_value<T: FortranBinary + ?Sized>(&mut self, val: &T) -> io::Result<()> {
+        let endian = self.endian;
+        val.write_binary(self, endian)
+    }
+
+    /// Write `val` at `pos`, as WRITE with POS= does.
+    pub fn write_at<T: FortranBinary + ?Sized>(&mut self, pos: u64, val: &T) -> io::Result<()> {
+        self.set_pos(pos)?;
+        self.write_value(val)
+    }
+}
+
+impl<S: Read + Seek> Read for StreamFile<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Read + Seek> BufRead for StreamFile<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+impl<S: Read + Write + Seek> Write for StreamFile<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use ::format::parse_format;
+    use ::read::FortranIterReader;
+    use ::stream::{Endian, StreamFile};
+    use ::types::Complex;
+    use ::write::FortranIterWriter;
+
+    #[test]
+    fn unformatted() {
+        let mut file = StreamFile::new(Cursor::new(vec![]), Endian::Big).unwrap();
+        file.write_value(&1i32).unwrap();
+        file.write_value(&vec![1.5f32, -2.0]).unwrap();
+        file.write_value(&true).unwrap();
+        file.write_value(&Complex::new(1.0f64, 2.0)).unwrap();
+        file.write_value(&"ab".to_string()).unwrap();
+        assert_eq!(file.pos(), 4 + 8 + 4 + 16 + 2 + 1);
+        assert_eq!(&file.get_ref().get_ref()[..4], &[0, 0, 0, 1]);
+
+        file.write_at(2, &0x0203u16).unwrap();
+        let mut n = 0i32;
+        file.read_at(1, &mut n).unwrap();
+        assert_eq!(n, 0x00020301);
+        let mut xs = [0.0f32; 2];
+        file.read_value(&mut xs[..]).unwrap();
+        assert_eq!(xs, [1.5, -2.0]);
+        let (mut b, mut c, mut s) = (false, Complex::new(0.0f64, 0.0), "  ".to_string());
+        file.read_value(&mut b).unwrap();
+        file.read_value(&mut c).unwrap();
+        file.read_value(&mut s).unwrap();
+        assert_eq!((b, c, s.as_str()), (true, Complex::new(1.0, 2.0), "ab"));
+        assert!(file.read_value(&mut n).is_err());
+
+        let mut file = StreamFile::new(Cursor::new(vec![1, 0]), Endian::Little).unwrap();
+        let mut x = 0u16;
+        file.read_value(&mut x).unwrap();
+        assert_eq!(x, 1);
+
+        let mut file = StreamFile::new(Cursor::new(b"caf\xe9".to_vec()), Endian::Little).unwrap();
+        let mut s = "    ".to_string();
+        file.read_value(&mut s).unwrap();
+        assert_eq!(s, "caf\u{e9}");
+        file.write_at(1, &s).unwrap();
+        file.write_value(&"\u{3b1}".to_string()).unwrap();
+        assert_eq!(file.get_ref().get_ref(), b"caf\xe9?");
+    }
+
+    #[test]
+    fn formatted() {
+        let mut file = StreamFile::new(Cursor::new(b"  1  2\n  3  4\n".to_vec()), Endian::native()).unwrap();
+        let fmt = parse_format("(I3)").unwrap();
+        let mut x = 0;
+        // a record read from the middle of a line
+        file.set_pos(4).unwrap();
+        {
+            let mut reader = FortranIterReader::new(&fmt, &mut file);
+            reader.consume_constants(true).unwrap();
+            reader.read_value(&mut x).unwrap();
+            reader.consume_constants(false).unwrap();
+        }
+        assert_eq!((x, file.pos()), (2, 8));
+
+        file.set_pos(11).unwrap();
+        {
+            let mut writer = FortranIterWriter::new(&fmt);
+            writer.write_value(&mut file, &5).unwrap();
+            writer.write_constants(&mut file, false).unwrap();
+        }
+        assert_eq!(file.get_ref().get_ref(), b"  1  2\n  3  5\n");
+    }
+}
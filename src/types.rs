@@ -19,7 +19,7 @@ impl_bool!(Fbool2);
 impl_bool!(Fbool4);
 impl_bool!(Fbool8);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FortranTag {
     Byte,
     Bool,
@@ -35,10 +35,55 @@ pub enum FortranTag {
     Real4,
     Real8,
     Strin,
+    /// CHARACTER of a given length, as declared.
+    Char(usize),
     Complex4,
     Complex8,
 }
 
+impl FortranTag {
+    pub fn is_logical(&self) -> bool {
+        use self::FortranTag::*;
+        matches!(*self, Bool | Bool2 | Bool4 | Bool8)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        use self::FortranTag::*;
+        matches!(*self, Byte | Int2 | Int4 | Int8 | Uint2 | Uint4 | Uint8)
+    }
+
+    pub fn is_real(&self) -> bool {
+        use self::FortranTag::*;
+        matches!(*self, Real4 | Real8)
+    }
+
+    pub fn is_complex(&self) -> bool {
+        use self::FortranTag::*;
+        matches!(*self, Complex4 | Complex8)
+    }
+
+    pub fn is_character(&self) -> bool {
+        matches!(*self, FortranTag::Strin | FortranTag::Char(_))
+    }
+
+    /// Size in bytes of unformatted data of this type, `None` for
+    /// strings of unknown length.
+    pub fn size(&self) -> Option<usize> {
+        use self::FortranTag::*;
+        let size = match *self {
+            Byte => 1,
+            Bool2 | Int2 | Uint2 => 2,
+            Bool | Bool4 | Int4 | Uint4 | Real4 => 4,
+            Bool8 | Int8 | Uint8 | Real8 | Complex4 => 8,
+            Complex8 => 16,
+            Char(len) => len,
+            Strin => return None,
+        };
+        Some(size)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct FortranType {
     pub tag: FortranTag,
     pub dim: Option<Vec<usize>>,
@@ -93,6 +138,7 @@ impl_primitive!(Bool2, Fbool2);
 impl_primitive!(Bool4, Fbool4);
 impl_primitive!(Bool8, Fbool8);
 impl_primitive!(Byte, i8);
+impl_primitive!(Byte, u8);
 impl_primitive!(Int2, i16);
 impl_primitive!(Int4, i32);
 impl_primitive!(Int8, i64);
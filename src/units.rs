@@ -0,0 +1,652 @@
+//! Numbered Fortran units
+//!
+//! A process-wide table connecting unit numbers to files, as OPEN and
+//! CLOSE do. Units 5, 6 and 0 are preconnected to the standard input,
+//! output and error, any other unit used before it is opened is
+//! connected to the file `fort.N` in the working directory.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use direct::DirectFile;
+use positioned::read_buffered;
+use sequential::SequentialFile;
+use stream::{Endian, StreamFile};
+
+/// STATUS= of OPEN.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Status {
+    /// The file must exist.
+    Old,
+    /// The file must not exist, it is created.
+    New,
+    /// The file is created, or truncated if it exists.
+    Replace,
+    /// A file without a name, deleted when the unit is closed.
+    Scratch,
+    /// The file is created if it does not exist.
+    Unknown,
+}
+
+/// ACCESS= of OPEN.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Access {
+    Sequential,
+    Direct,
+    Stream,
+}
+
+/// FORM= of OPEN.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Form {
+    Formatted,
+    Unformatted,
+}
+
+/// POSITION= of OPEN.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Position {
+    AsIs,
+    Rewind,
+    Append,
+}
+
+/// ACTION= of OPEN.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// The specifiers of OPEN, the defaults being those of Fortran.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenOptions {
+    pub status: Status,
+    pub access: Access,
+    pub form: Form,
+    /// RECL=, the record length, required for direct access.
+    pub recl: Option<usize>,
+    pub position: Position,
+    pub action: Action,
+}
+
+impl Default for OpenOptions {
+    fn default() -> OpenOptions {
+        OpenOptions {
+            status: Status::Unknown,
+            access: Access::Sequential,
+            form: Form::Formatted,
+            recl: None,
+            position: Position::AsIs,
+            action: Action::ReadWrite,
+        }
+    }
+}
+
+/// What INQUIRE tells about a connected unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitInfo {
+    pub number: i32,
+    /// NAME=, which preconnected units and scratch files do not have.
+    pub name: Option<PathBuf>,
+    pub options: OpenOptions,
+}
+
+enum Connection {
+    Stdin(BufReader<io::Stdin>),
+    Stdout(io::Stdout),
+    Stderr(io::Stderr),
+    File(SequentialFile<File>),
+    Direct(DirectFile<File>),
+    Stream(StreamFile<File>),
+}
+
+/// A unit connected to a file, read with `BufRead` and written
+/// with `Write`.
+pub struct Unit {
+    info: UnitInfo,
+    // the path of a scratch file, removed on close
+    scratch: Option<PathBuf>,
+    conn: Connection,
+}
+
+// A connected unit. Each unit has its own lock, the table is only
+// locked to look units up, so a unit can be used while another is.
+struct Entry {
+    info: UnitInfo,
+    unit: Arc<Mutex<Unit>>,
+}
+
+static UNITS: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+fn units() -> MutexGuard<'static, Vec<Entry>> {
+    // a panic while holding the table leaves the units usable
+    UNITS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn lock(unit: &Mutex<Unit>) -> MutexGuard<'_, Unit> {
+    unit.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Entry {
+    fn new(unit: Unit) -> Entry {
+        Entry { info: unit.info.clone(), unit: Arc::new(Mutex::new(unit)) }
+    }
+}
+
+fn scratch_path(number: i32) -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!("f77-io-{}-{}-{}.tmp", ::std::process::id(), number,
+                       COUNT.fetch_add(1, Ordering::Relaxed));
+    ::std::env::temp_dir().join(name)
+}
+
+impl Unit {
+    fn open(number: i32, path: Option<&Path>, options: OpenOptions) -> io::Result<Unit> {
+        if options.access == Access::Direct && options.recl.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "direct access requires RECL="));
+        }
+        let scratch = match options.status {
+            Status::Scratch => Some(scratch_path(number)),
+            _ => None,
+        };
+        let name = match (scratch.as_ref(), path) {
+            (Some(_), _) => None,
+            (None, Some(path)) => Some(path.to_path_buf()),
+            (None, None) => Some(PathBuf::from(format!("fort.{}", number))),
+        };
+        let file_path = scratch.clone().or_else(|| name.clone()).unwrap();
+
+        let mut open = fs::OpenOptions::new();
+        let write = options.action != Action::Read;
+        open.read(options.action != Action::Write).write(write);
+        match options.status {
+            Status::Old => {},
+            Status::New | Status::Scratch => { open.create_new(true); },
+            Status::Replace => { open.create(true).truncate(true); },
+            Status::Unknown => { open.create(write); },
+        }
+        let mut file = open.open(&file_path)?;
+        if options.position == Position::Append && options.access != Access::Direct {
+            file.seek(SeekFrom::End(0))?;
+        }
+        let conn = match (options.access, options.recl) {
+            (Access::Direct, Some(recl)) => Connection::Direct(DirectFile::new(file, options.form, recl)?),
+            (Access::Stream, _) => Connection::Stream(StreamFile::new(file, Endian::native())?),
+            _ => Connection::File(SequentialFile::new(file, options.form)?),
+        };
+        Ok(Unit {
+            info: UnitInfo { number, name, options },
+            scratch,
+            conn,
+        })
+    }
+
+    /// The unit as it is connected before any OPEN.
+    fn preconnected(number: i32) -> io::Result<Unit> {
+        let conn = match number {
+            5 => Connection::Stdin(BufReader::new(io::stdin())),
+            6 => Connection::Stdout(io::stdout()),
+            0 => Connection::Stderr(io::stderr()),
+            _ => return Unit::open(number, None, OpenOptions::default()),
+        };
+        let action = if number == 5 { Action::Read } else { Action::Write };
+        Ok(Unit {
+            info: UnitInfo {
+                number,
+                name: None,
+                options: OpenOptions { action, ..OpenOptions::default() },
+            },
+            scratch: None,
+            conn,
+        })
+    }
+
+    pub fn info(&self) -> &UnitInfo {
+        &self.info
+    }
+
+    /// Fail if the unit was opened for `other` only.
+    fn check_action(&self, other: Action) -> io::Result<()> {
+        if self.info.options.action == other {
+            let msg = format!("unit {} is not opened for {}", self.info.number,
+                              if other == Action::Read { "writing" } else { "reading" });
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, msg));
+        }
+        Ok(())
+    }
+
+    /// The file the unit is connected to for sequential access, `None`
+    /// for the standard streams. Unformatted records are read and
+    /// written through it.
+    pub fn file(&mut self) -> Option<&mut SequentialFile<File>> {
+        match self.conn {
+            Connection::File(ref mut f) => Some(f),
+            _ => None,
+        }
+    }
+
+    /// The file the unit is connected to for direct access, through
+    /// which REC= is given.
+    pub fn direct(&mut self) -> Option<&mut DirectFile<File>> {
+        match self.conn {
+            Connection::Direct(ref mut f) => Some(f),
+            _ => None,
+        }
+    }
+
+    /// The file the unit is connected to for stream access, through
+    /// which POS= is given and unformatted values are read and written.
+    pub fn stream(&mut self) -> Option<&mut StreamFile<File>> {
+        match self.conn {
+            Connection::Stream(ref mut f) => Some(f),
+            _ => None,
+        }
+    }
+
+    fn positioned(&mut self) -> io::Result<&mut SequentialFile<File>> {
+        let number = self.info.number;
+        self.file().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Unsupported, format!("unit {} cannot be positioned", number))
+        })
+    }
+
+    /// REWIND: go to the start of the file.
+    pub fn rewind(&mut self) -> io::Result<()> {
+        if let Some(stream) = self.stream() {
+            return stream.set_pos(1);
+        }
+        self.positioned()?.rewind()
+    }
+
+    /// BACKSPACE: go back to the start of the previous record.
+    pub fn backspace(&mut self) -> io::Result<()> {
+        self.positioned()?.backspace()
+    }
+
+    /// ENDFILE: end the file after the current record.
+    pub fn endfile(&mut self) -> io::Result<()> {
+        self.check_action(Action::Read)?;
+        self.positioned()?.endfile()
+    }
+}
+
+impl Read for Unit {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read_buffered(self, buf)
+    }
+}
+
+impl BufRead for Unit {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check_action(Action::Write)?;
+        match self.conn {
+            Connection::Stdin(ref mut r) => r.fill_buf(),
+            Connection::File(ref mut r) => r.fill_buf(),
+            Connection::Direct(ref mut r) => r.fill_buf(),
+            Connection::Stream(ref mut r) => r.fill_buf(),
+            Connection::Stdout(_) | Connection::Stderr(_) => {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "cannot read from an output unit"))
+            },
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self.conn {
+            Connection::Stdin(ref mut r) => r.consume(amt),
+            Connection::File(ref mut r) => r.consume(amt),
+            Connection::Direct(ref mut r) => r.consume(amt),
+            Connection::Stream(ref mut r) => r.consume(amt),
+            Connection::Stdout(_) | Connection::Stderr(_) => {},
+        }
+    }
+}
+
+impl Write for Unit {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_action(Action::Read)?;
+        match self.conn {
+            Connection::Stdout(ref mut w) => w.write(buf),
+            Connection::Stderr(ref mut w) => w.write(buf),
+            Connection::File(ref mut f) => f.write(buf),
+            Connection::Direct(ref mut f) => f.write(buf),
+            Connection::Stream(ref mut f) => f.write(buf),
+            Connection::Stdin(_) => {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "cannot write to an input unit"))
+            },
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.conn {
+            Connection::Stdout(ref mut w) => w.flush(),
+            Connection::Stderr(ref mut w) => w.flush(),
+            Connection::File(ref mut f) => f.flush(),
+            Connection::Direct(ref mut f) => f.flush(),
+            Connection::Stream(ref mut f) => f.flush(),
+            Connection::Stdin(_) => Ok(()),
+        }
+    }
+}
+
+/// Connect `unit` to the file at `path`, or to `fort.N` without a path,
+/// closing the file it was connected to.
+pub fn open(unit: i32, path: Option<&Path>, options: OpenOptions) -> io::Result<()> {
+    let new = Entry::new(Unit::open(unit, path, options)?);
+    let old = {
+        let mut units = units();
+        let old = units.iter().position(|u| u.info.number == unit).map(|p| units.remove(p));
+        units.push(new);
+        old
+    };
+    match old {
+        Some(old) => finish(old.unit, false),
+        None => Ok(()),
+    }
+}
+
+/// Disconnect `unit`, deleting its file if `delete` is set, as
+/// STATUS='DELETE' does. Scratch files are always deleted, closing
+/// a unit that is not connected does nothing.
+pub fn close(unit: i32, delete: bool) -> io::Result<()> {
+    let old = {
+        let mut units = units();
+        units.iter().position(|u| u.info.number == unit).map(|p| units.remove(p))
+    };
+    match old {
+        Some(old) => finish(old.unit, delete),
+        None => Ok(()),
+    }
+}
+
+fn finish(unit: Arc<Mutex<Unit>>, delete: bool) -> io::Result<()> {
+    let path = {
+        // waits for I/O on the unit in progress
+        let mut unit = lock(&unit);
+        unit.flush()?;
+        match unit.scratch.take() {
+            Some(path) => Some(path),
+            None if delete => unit.info.name.take(),
+            None => None,
+        }
+    };
+    // closes the file
+    drop(unit);
+    match path {
+        Some(path) => fs::remove_file(path),
+        None => Ok(()),
+    }
+}
+
+/// The properties of `unit`, `None` if it is not connected.
+/// Preconnected units are connected until they are closed.
+pub fn inquire(unit: i32) -> Option<UnitInfo> {
+    let units = units();
+    match units.iter().find(|u| u.info.number == unit) {
+        Some(u) => Some(u.info.clone()),
+        None if unit == 0 || unit == 5 || unit == 6 => Unit::preconnected(unit).ok().map(|u| u.info),
+        None => None,
+    }
+}
+
+/// Run `f` on `unit`, connecting it first if it is not. Only `unit`
+/// is locked meanwhile: `f` may use, open and close other units and
+/// inquire about any unit, but using `unit` itself again, including
+/// closing it, deadlocks.
+pub fn with_unit<T, F>(unit: i32, f: F) -> io::Result<T>
+    where F: FnOnce(&mut Unit) -> T
+{
+    let entry = {
+        let mut units = units();
+        let pos = match units.iter().position(|u| u.info.number == unit) {
+            Some(pos) => pos,
+            None => {
+                units.push(Entry::new(Unit::preconnected(unit)?));
+                units.len() - 1
+            },
+        };
+        units[pos].unit.clone()
+    };
+    let mut guard = lock(&entry);
+    Ok(f(&mut guard))
+}
+
+/// REWIND `unit`.
+pub fn rewind(unit: i32) -> io::Result<()> {
+    with_unit(unit, Unit::rewind)?
+}
+
+/// BACKSPACE `unit`.
+pub fn backspace(unit: i32) -> io::Result<()> {
+    with_unit(unit, Unit::backspace)?
+}
+
+/// ENDFILE `unit`.
+pub fn endfile(unit: i32) -> io::Result<()> {
+    with_unit(unit, Unit::endfile)?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{BufRead, Write};
+    use ::format::parse_format;
+    use ::read::FortranIterReader;
+    use ::units::*;
+    use ::write::FortranIterWriter;
+
+    fn temp(name: &str) -> ::std::path::PathBuf {
+        let path = ::std::env::temp_dir().join(format!("f77-io-test-{}-{}", ::std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn open_write_read() {
+        let path = temp("unit41");
+        let fmt = parse_format("(I3, F6.2)").unwrap();
+        let replace = OpenOptions { status: Status::Replace, ..OpenOptions::default() };
+        open(41, Some(&path), replace).unwrap();
+        with_unit(41, |unit| {
+            let mut writer = FortranIterWriter::new(&fmt);
+            writer.write_value(unit, &7).unwrap();
+            writer.write_value(unit, &1.5).unwrap();
+            writer.write_constants(unit, false).unwrap();
+        }).unwrap();
+        close(41, false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "  7  1.50\n");
+
+        let append = OpenOptions { status: Status::Old, position: Position::Append, ..OpenOptions::default() };
+        open(41, Some(&path), append).unwrap();
+        with_unit(41, |unit| unit.write_all(b"  8  2.50\n")).unwrap().unwrap();
+        close(41, false).unwrap();
+
+        let read = OpenOptions { status: Status::Old, action: Action::Read, ..OpenOptions::default() };
+        open(41, Some(&path), read.clone()).unwrap();
+        assert_eq!(inquire(41), Some(UnitInfo { number: 41, name: Some(path.clone()), options: read }));
+        let (mut a, mut b) = (vec![0i32; 2], vec![0.0f64; 2]);
+        with_unit(41, |unit| {
+            for i in 0..2 {
+                let mut reader = FortranIterReader::new(&fmt, unit);
+                reader.read_value(&mut a[i]).unwrap();
+                reader.read_value(&mut b[i]).unwrap();
+                reader.consume_constants(false).unwrap();
+            }
+            assert!(unit.write_all(b"x").is_err());
+        }).unwrap();
+        assert_eq!((a, b), (vec![7, 8], vec![1.5, 2.5]));
+        close(41, true).unwrap();
+        assert!(!path.exists());
+        assert_eq!(inquire(41), None);
+    }
+
+    #[test]
+    fn status() {
+        let path = temp("unit42");
+        let new = OpenOptions { status: Status::New, ..OpenOptions::default() };
+        open(42, Some(&path), new.clone()).unwrap();
+        close(42, false).unwrap();
+        assert!(open(42, Some(&path), new).is_err());
+        fs::remove_file(&path).unwrap();
+        let old = OpenOptions { status: Status::Old, ..OpenOptions::default() };
+        assert!(open(42, Some(&path), old).is_err());
+        let direct = OpenOptions { access: Access::Direct, ..OpenOptions::default() };
+        assert!(open(42, Some(&path), direct).is_err());
+
+        let scratch = OpenOptions { status: Status::Scratch, ..OpenOptions::default() };
+        open(43, None, scratch).unwrap();
+        let line = with_unit(43, |unit| {
+            unit.write_all(b"scratch\n").unwrap();
+            unit.flush().unwrap();
+            unit.fill_buf().map(|b| b.len())
+        }).unwrap().unwrap();
+        // written at the end, nothing left to read
+        assert_eq!(line, 0);
+        assert_eq!(inquire(43).unwrap().name, None);
+        close(43, false).unwrap();
+    }
+
+    #[test]
+    fn preconnected() {
+        assert_eq!(inquire(6).unwrap().options.action, Action::Write);
+        assert_eq!(inquire(5).unwrap().options.action, Action::Read);
+        assert!(with_unit(5, |unit| unit.write_all(b"x").is_err()).unwrap());
+        assert!(rewind(6).is_err());
+    }
+
+    #[test]
+    fn nested() {
+        let scratch = OpenOptions { status: Status::Scratch, ..OpenOptions::default() };
+        open(45, None, scratch.clone()).unwrap();
+        open(46, None, scratch).unwrap();
+        with_unit(45, |a| {
+            a.write_all(b"from 45\n").unwrap();
+            with_unit(46, |b| b.write_all(b"from 46\n")).unwrap().unwrap();
+            assert_eq!(inquire(45).unwrap().number, 45);
+        }).unwrap();
+        close(46, false).unwrap();
+        close(45, false).unwrap();
+    }
+
+    #[test]
+    fn positioning() {
+        let path = temp("unit44");
+        let fmt = parse_format("(I3)").unwrap();
+        let replace = OpenOptions { status: Status::Replace, ..OpenOptions::default() };
+        open(44, Some(&path), replace.clone()).unwrap();
+        with_unit(44, |unit| unit.write_all(b"  1\n  2\n  3\n")).unwrap().unwrap();
+        rewind(44).unwrap();
+        let read = |expected: i32| {
+            let mut val = 0;
+            with_unit(44, |unit| {
+                let mut reader = FortranIterReader::new(&fmt, unit);
+                reader.read_value(&mut val).unwrap();
+                reader.consume_constants(false).unwrap();
+            }).unwrap();
+            assert_eq!(val, expected);
+        };
+        read(1);
+        read(2);
+        backspace(44).unwrap();
+        read(2);
+        endfile(44).unwrap();
+        close(44, false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "  1\n  2\n");
+
+        let unformatted = OpenOptions { form: Form::Unformatted, ..replace };
+        open(44, Some(&path), unformatted).unwrap();
+        let record = with_unit(44, |unit| {
+            let file = unit.file().unwrap();
+            file.write_record(b"abc").unwrap();
+            file.write_record(b"defg").unwrap();
+            file.backspace().unwrap();
+            file.read_record().unwrap()
+        }).unwrap();
+        assert_eq!(record.unwrap(), b"defg");
+        close(44, true).unwrap();
+    }
+
+    #[test]
+    fn direct() {
+        let path = temp("unit48");
+        let fmt = parse_format("(I3, A)").unwrap();
+        let options = OpenOptions { status: Status::Replace, access: Access::Direct, recl: Some(6),
+                                    ..OpenOptions::default() };
+        open(48, Some(&path), options.clone()).unwrap();
+        assert_eq!(inquire(48).unwrap().options, options);
+        let write = |rec: u64, n: i32, s: &str| {
+            with_unit(48, |unit| {
+                unit.direct().unwrap().set_rec(rec).unwrap();
+                let mut writer = FortranIterWriter::new(&fmt);
+                writer.write_value(unit, &n).unwrap();
+                writer.write_value(unit, &s.to_string()).unwrap();
+                writer.write_constants(unit, false).unwrap();
+            }).unwrap();
+        };
+        write(3, 3, "abc");
+        write(1, 1, "a");
+        assert!(rewind(48).is_err());
+        let (mut n, mut s) = (0, String::new());
+        with_unit(48, |unit| {
+            unit.direct().unwrap().set_rec(3).unwrap();
+            let mut reader = FortranIterReader::new(&fmt, unit);
+            reader.read_value(&mut n).unwrap();
+            reader.read_value(&mut s).unwrap();
+            reader.consume_constants(false).unwrap();
+        }).unwrap();
+        assert_eq!((n, s.as_str()), (3, "abc"));
+        close(48, false).unwrap();
+        // no newlines, record 2 was never written
+        assert_eq!(fs::read(&path).unwrap(), b"  1a  \0\0\0\0\0\0  3abc");
+
+        let unformatted = OpenOptions { status: Status::Old, form: Form::Unformatted, ..options };
+        open(48, Some(&path), unformatted).unwrap();
+        let record = with_unit(48, |unit| {
+            let file = unit.direct().unwrap();
+            file.write_record(2, b"xy").unwrap();
+            file.read_record(1)
+        }).unwrap();
+        assert_eq!(record.unwrap().unwrap(), b"  1a  ");
+        close(48, false).unwrap();
+        assert_eq!(&fs::read(&path).unwrap()[6..12], b"xy\0\0\0\0");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn stream() {
+        let path = temp("unit49");
+        let options = OpenOptions { status: Status::Replace, access: Access::Stream, form: Form::Unformatted,
+                                    ..OpenOptions::default() };
+        open(49, Some(&path), options).unwrap();
+        with_unit(49, |unit| {
+            let file = unit.stream().unwrap();
+            file.write_value(&1i32).unwrap();
+            file.write_value(&"ab".to_string()).unwrap();
+            file.write_value(&[2i16, 3][..]).unwrap();
+        }).unwrap();
+        rewind(49).unwrap();
+        let mut n = 0i32;
+        with_unit(49, |unit| unit.stream().unwrap().read_value(&mut n)).unwrap().unwrap();
+        assert_eq!(n, 1);
+        assert!(backspace(49).is_err());
+        close(49, false).unwrap();
+        // the values only, without record markers
+        let mut expected = 1i32.to_ne_bytes().to_vec();
+        expected.extend_from_slice(b"ab");
+        expected.extend_from_slice(&2i16.to_ne_bytes());
+        expected.extend_from_slice(&3i16.to_ne_bytes());
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        let formatted = OpenOptions { status: Status::Old, access: Access::Stream, position: Position::Append,
+                                      ..OpenOptions::default() };
+        open(49, Some(&path), formatted).unwrap();
+        with_unit(49, |unit| unit.write_all(b"end\n")).unwrap().unwrap();
+        close(49, false).unwrap();
+        expected.extend_from_slice(b"end\n");
+        assert_eq!(fs::read(&path).unwrap(), expected);
+        fs::remove_file(&path).unwrap();
+    }
+}
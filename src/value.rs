@@ -0,0 +1,370 @@
+//! Values of any Fortran type known at run time
+//!
+//! A `FortranValue` holds an item of any `FortranTag`, or an array of
+//! items, so that records can be read and written without Rust types
+//! fixed at compile time. `FortranIterReader::read_values` reads a record
+//! into values whose types follow the edit descriptors of the format.
+
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use format::FormatNode;
+use read::{FortranDefaultReader, FortranIterReader, FortranRead, ReadErr};
+use stream::{Endian, FortranBinary};
+use types::{Complex, FortranTag, FortranType};
+use write::{FortranDefaultWriter, FortranIterWriter, FortranWrite, WriteErr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FortranValue {
+    Byte(i8),
+    Bool(bool),
+    Bool2(bool),
+    Bool4(bool),
+    Bool8(bool),
+    Int2(i16),
+    Int4(i32),
+    Int8(i64),
+    Uint2(u16),
+    Uint4(u32),
+    Uint8(u64),
+    Real4(f32),
+    Real8(f64),
+    Strin(String),
+    Complex4(Complex<f32>),
+    Complex8(Complex<f64>),
+    Array(Vec<FortranValue>),
+}
+
+impl FortranValue {
+    /// The type of the value, that of the first element for an array,
+    /// `None` for an empty array.
+    pub fn tag(&self) -> Option<FortranTag> {
+        use self::FortranValue::*;
+        let tag = match *self {
+            Byte(_) => FortranTag::Byte,
+            Bool(_) => FortranTag::Bool,
+            Bool2(_) => FortranTag::Bool2,
+            Bool4(_) => FortranTag::Bool4,
+            Bool8(_) => FortranTag::Bool8,
+            Int2(_) => FortranTag::Int2,
+            Int4(_) => FortranTag::Int4,
+            Int8(_) => FortranTag::Int8,
+            Uint2(_) => FortranTag::Uint2,
+            Uint4(_) => FortranTag::Uint4,
+            Uint8(_) => FortranTag::Uint8,
+            Real4(_) => FortranTag::Real4,
+            Real8(_) => FortranTag::Real8,
+            Strin(_) => FortranTag::Strin,
+            Complex4(_) => FortranTag::Complex4,
+            Complex8(_) => FortranTag::Complex8,
+            Array(ref v) => return v.first().and_then(|x| x.tag()),
+        };
+        Some(tag)
+    }
+
+    /// A zero value of type `tag`; a string of `len` blanks for `Char(len)`.
+    pub fn zero(tag: FortranTag) -> FortranValue {
+        use self::FortranValue::*;
+        match tag {
+            FortranTag::Byte => Byte(0),
+            FortranTag::Bool => Bool(false),
+            FortranTag::Bool2 => Bool2(false),
+            FortranTag::Bool4 => Bool4(false),
+            FortranTag::Bool8 => Bool8(false),
+            FortranTag::Int2 => Int2(0),
+            FortranTag::Int4 => Int4(0),
+            FortranTag::Int8 => Int8(0),
+            FortranTag::Uint2 => Uint2(0),
+            FortranTag::Uint4 => Uint4(0),
+            FortranTag::Uint8 => Uint8(0),
+            FortranTag::Real4 => Real4(0.0),
+            FortranTag::Real8 => Real8(0.0),
+            FortranTag::Strin => Strin(String::new()),
+            FortranTag::Char(len) => Strin(" ".repeat(len)),
+            FortranTag::Complex4 => Complex4(Complex::new(0.0, 0.0)),
+            FortranTag::Complex8 => Complex8(Complex::new(0.0, 0.0)),
+        }
+    }
+
+    /// A zero value of type `ty`, an array holding all the elements
+    /// in order if it has dimensions. The caller bounds the number of
+    /// elements, which are all allocated.
+    pub fn for_type(ty: &FortranType) -> FortranValue {
+        match ty.dim {
+            Some(ref dim) => {
+                let len = dim.iter().product();
+                FortranValue::Array(vec![FortranValue::zero(ty.tag); len])
+            },
+            None => FortranValue::zero(ty.tag),
+        }
+    }
+
+    /// The value as an integer, if it is one.
+    pub fn as_i64(&self) -> Option<i64> {
+        use self::FortranValue::*;
+        match *self {
+            Byte(x) => Some(x as i64),
+            Int2(x) => Some(x as i64),
+            Int4(x) => Some(x as i64),
+            Int8(x) => Some(x),
+            Uint2(x) => Some(x as i64),
+            Uint4(x) => Some(x as i64),
+            Uint8(x) if x <= i64::MAX as u64 => Some(x as i64),
+            _ => None,
+        }
+    }
+
+    /// A zero value of the type read by the data edit descriptor `node`:
+    /// an integer for I, B, O, Z and Q, a real for F, E, D and G, a
+    /// logical for L and a string for A. The widest kinds are used, so
+    /// that any field fits. `None` if `node` takes no data.
+    pub fn for_edit(node: &FormatNode) -> Option<FortranValue> {
+        match *node {
+            FormatNode::Int(_, _, _) | FormatNode::RemainingChars => Some(FortranValue::Int8(0)),
+            FormatNode::Real(_, _, _, _) => Some(FortranValue::Real8(0.0)),
+            FormatNode::Bool(_) => Some(FortranValue::Bool(false)),
+            FormatNode::Str(_) => Some(FortranValue::Strin(String::new())),
+            _ => None,
+        }
+    }
+}
+
+/// Shown as list-directed output shows it, without blanks: logicals
+/// as T or F, complex numbers in parentheses, arrays in brackets.
+impl fmt::Display for FortranValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::FortranValue::*;
+        match *self {
+            Byte(x) => write!(f, "{}", x),
+            Bool(x) | Bool2(x) | Bool4(x) | Bool8(x) => f.write_str(if x { "T" } else { "F" }),
+            Int2(x) => write!(f, "{}", x),
+            Int4(x) => write!(f, "{}", x),
+            Int8(x) => write!(f, "{}", x),
+            Uint2(x) => write!(f, "{}", x),
+            Uint4(x) => write!(f, "{}", x),
+            Uint8(x) => write!(f, "{}", x),
+            Real4(x) => write!(f, "{:?}", x),
+            Real8(x) => write!(f, "{:?}", x),
+            Strin(ref s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Complex4(c) => write!(f, "({:?},{:?})", c.re, c.im),
+            Complex8(c) => write!(f, "({:?},{:?})", c.re, c.im),
+            Array(ref v) => {
+                f.write_str("[")?;
+                for (i, x) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", x)?;
+                }
+                f.write_str("]")
+            },
+        }
+    }
+}
+
+// logicals of every kind are edited as `bool`, complex numbers as two reals
+macro_rules! dispatch {
+    ($val: expr, $x: ident => $scalar: expr, $c: ident => $complex: expr) => {
+        match *$val {
+            FortranValue::Byte(ref mut $x) => $scalar,
+            FortranValue::Bool(ref mut $x) | FortranValue::Bool2(ref mut $x) |
+            FortranValue::Bool4(ref mut $x) | FortranValue::Bool8(ref mut $x) => $scalar,
+            FortranValue::Int2(ref mut $x) => $scalar,
+            FortranValue::Int4(ref mut $x) => $scalar,
+            FortranValue::Int8(ref mut $x) => $scalar,
+            FortranValue::Uint2(ref mut $x) => $scalar,
+            FortranValue::Uint4(ref mut $x) => $scalar,
+            FortranValue::Uint8(ref mut $x) => $scalar,
+            FortranValue::Real4(ref mut $x) => $scalar,
+            FortranValue::Real8(ref mut $x) => $scalar,
+            FortranValue::Strin(ref mut $x) => $scalar,
+            FortranValue::Array(ref mut $x) => $scalar,
+            FortranValue::Complex4(ref mut $c) => $complex,
+            FortranValue::Complex8(ref mut $c) => $complex,
+        }
+    }
+}
+
+macro_rules! dispatch_ref {
+    ($val: expr, $x: ident => $scalar: expr, $c: ident => $complex: expr) => {
+        match *$val {
+            FortranValue::Byte(ref $x) => $scalar,
+            FortranValue::Bool(ref $x) | FortranValue::Bool2(ref $x) |
+            FortranValue::Bool4(ref $x) | FortranValue::Bool8(ref $x) => $scalar,
+            FortranValue::Int2(ref $x) => $scalar,
+            FortranValue::Int4(ref $x) => $scalar,
+            FortranValue::Int8(ref $x) => $scalar,
+            FortranValue::Uint2(ref $x) => $scalar,
+            FortranValue::Uint4(ref $x) => $scalar,
+            FortranValue::Uint8(ref $x) => $scalar,
+            FortranValue::Real4(ref $x) => $scalar,
+            FortranValue::Real8(ref $x) => $scalar,
+            FortranValue::Strin(ref $x) => $scalar,
+            FortranValue::Array(ref $x) => $scalar,
+            FortranValue::Complex4(ref $c) => $complex,
+            FortranValue::Complex8(ref $c) => $complex,
+        }
+    }
+}
+
+impl FortranRead for FortranValue {
+    fn fortran_read<R: BufRead>(&mut self, reader: &mut FortranIterReader<R>) -> Result<bool, ReadErr> {
+        dispatch!(self,
+                  x => x.fortran_read(reader),
+                  c => {
+                      let mut parts = (c.re, c.im);
+                      let read = parts.fortran_read(reader)?;
+                      *c = Complex::new(parts.0, parts.1);
+                      Ok(read)
+                  })
+    }
+
+    fn fortran_read_default<R: BufRead>(&mut self, reader: &mut FortranDefaultReader<R>) -> Result<bool, ReadErr> {
+        dispatch!(self,
+                  x => x.fortran_read_default(reader),
+                  c => {
+                      let mut parts = (c.re, c.im);
+                      let read = parts.fortran_read_default(reader)?;
+                      *c = Complex::new(parts.0, parts.1);
+                      Ok(read)
+                  })
+    }
+}
+
+impl FortranWrite for FortranValue {
+    fn fortran_write<W: Write>(&self, dst: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
+        dispatch_ref!(self,
+                      x => x.fortran_write(dst, writer),
+                      c => {
+                          c.re.fortran_write(dst, writer)?;
+                          writer.write_constants(dst, true)?;
+                          c.im.fortran_write(dst, writer)
+                      })
+    }
+
+    fn fortran_write_default<W: Write>(&self, dst: &mut W, writer: &mut FortranDefaultWriter) -> Result<(), WriteErr> {
+        dispatch_ref!(self,
+                      x => x.fortran_write_default(dst, writer),
+                      c => {
+                          c.re.fortran_write_default(dst, writer)?;
+                          c.im.fortran_write_default(dst, writer)
+                      })
+    }
+}
+
+// logicals are stored as integers of their kind
+impl FortranBinary for FortranValue {
+    fn read_binary(&mut self, read: &mut dyn Read, endian: Endian) -> io::Result<()> {
+        use self::FortranValue::*;
+        match *self {
+            Byte(ref mut x) => x.read_binary(read, endian),
+            Bool(ref mut x) | Bool4(ref mut x) => x.read_binary(read, endian),
+            Bool2(ref mut x) => {
+                let mut n = 0i16;
+                n.read_binary(read, endian)?;
+                *x = n != 0;
+                Ok(())
+            },
+            Bool8(ref mut x) => {
+                let mut n = 0i64;
+                n.read_binary(read, endian)?;
+                *x = n != 0;
+                Ok(())
+            },
+            Int2(ref mut x) => x.read_binary(read, endian),
+            Int4(ref mut x) => x.read_binary(read, endian),
+            Int8(ref mut x) => x.read_binary(read, endian),
+            Uint2(ref mut x) => x.read_binary(read, endian),
+            Uint4(ref mut x) => x.read_binary(read, endian),
+            Uint8(ref mut x) => x.read_binary(read, endian),
+            Real4(ref mut x) => x.read_binary(read, endian),
+            Real8(ref mut x) => x.read_binary(read, endian),
+            Strin(ref mut x) => x.read_binary(read, endian),
+            Complex4(ref mut x) => x.read_binary(read, endian),
+            Complex8(ref mut x) => x.read_binary(read, endian),
+            Array(ref mut x) => x.read_binary(read, endian),
+        }
+    }
+
+    fn write_binary(&self, write: &mut dyn Write, endian: Endian) -> io::Result<()> {
+        use self::FortranValue::*;
+        match *self {
+            Byte(ref x) => x.write_binary(write, endian),
+            Bool(ref x) | Bool4(ref x) => x.write_binary(write, endian),
+            Bool2(x) => (x as i16).write_binary(write, endian),
+            Bool8(x) => (x as i64).write_binary(write, endian),
+            Int2(ref x) => x.write_binary(write, endian),
+            Int4(ref x) => x.write_binary(write, endian),
+            Int8(ref x) => x.write_binary(write, endian),
+            Uint2(ref x) => x.write_binary(write, endian),
+            Uint4(ref x) => x.write_binary(write, endian),
+            Uint8(ref x) => x.write_binary(write, endian),
+            Real4(ref x) => x.write_binary(write, endian),
+            Real8(ref x) => x.write_binary(write, endian),
+            Strin(ref x) => x.write_binary(write, endian),
+            Complex4(ref x) => x.write_binary(write, endian),
+            Complex8(ref x) => x.write_binary(write, endian),
+            Array(ref x) => x.write_binary(write, endian),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::format::parse_format;
+    use ::read::FortranIterReader;
+    use ::types::{Complex, FortranTag};
+    use ::value::FortranValue;
+    use ::value::FortranValue::*;
+    use ::write::FortranIterWriter;
+
+    #[test]
+    fn read_inferred() {
+        let fmt = parse_format("(I3, 1X, F5.2, L2, A4, Z3)").unwrap();
+        let mut inp = " 42  1.50 Tabcd 1F\n".as_bytes();
+        let values = FortranIterReader::new(&fmt, &mut inp).read_values().unwrap();
+        assert_eq!(values, [Int8(42), Real8(1.5), Bool(true), Strin("abcd".to_string()), Int8(31)]);
+        assert_eq!(values[3].tag(), Some(FortranTag::Strin));
+
+        // an unlimited repeat ends with the record
+        let fmt = parse_format("(A2, *(I2))").unwrap();
+        let mut inp = "ab 1 2 3\n 4\n".as_bytes();
+        let mut reader = FortranIterReader::new(&fmt, &mut inp);
+        let values = reader.read_values().unwrap();
+        assert_eq!(values, [Strin("ab".to_string()), Int8(1), Int8(2), Int8(3)]);
+        let fmt = parse_format("(I2)").unwrap();
+        reader.restart(&fmt);
+        assert_eq!(reader.read_values().unwrap(), [Int8(4)]);
+
+        // trailing blanks are not read as zeros
+        let fmt = parse_format("(A2, *(I2))").unwrap();
+        let mut inp = "ab 1 2     \n".as_bytes();
+        let values = FortranIterReader::new(&fmt, &mut inp).read_values().unwrap();
+        assert_eq!(values, [Strin("ab".to_string()), Int8(1), Int8(2)]);
+    }
+
+    #[test]
+    fn write_back() {
+        let fmt = parse_format("(I3, F5.1, L2, A3, 2F4.1, 3I2)").unwrap();
+        let values = vec![
+            Int4(7), Real4(2.5), Bool8(false), Strin("xyz".to_string()),
+            Complex4(Complex::new(1.0, -1.0)),
+            Array(vec![Byte(1), Uint2(2), Int2(3)]),
+        ];
+        let mut out = vec![];
+        {
+            let mut writer = FortranIterWriter::new(&fmt);
+            writer.write_value(&mut out, &values).unwrap();
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        assert_eq!(String::from_utf8(out.clone()).unwrap(), "  7  2.5 Fxyz 1.0-1.0 1 2 3\n");
+
+        let mut read = values.clone();
+        read[0] = Int4(0);
+        read[4] = Complex4(Complex::new(0.0, 0.0));
+        FortranIterReader::new(&fmt, &mut &out[..]).read_value(&mut read).unwrap();
+        assert_eq!(read, values);
+        assert_eq!(FortranValue::Array(vec![]).tag(), None);
+        assert_eq!(values.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+                   ["7", "2.5", "F", "'xyz'", "(1.0,-1.0)", "[1, 2, 3]"]);
+    }
+}
@@ -1,21 +1,101 @@
 use std::io::{Write};
-use std::iter::{Peekable};
+use std::borrow::{Cow};
+use std::fmt::{Binary, Debug, Display, LowerHex, Octal};
 use format::*;
 use types::*;
-use iter::*;
+use program::*;
+use decimal::{fixed_digits, sig_digits, EditBuf};
 
 pub struct WriterOpts {
     terminated: bool,
     suppress_newline: bool,
     scale: isize,
     radix: usize,
+    sign_plus: bool,
+    round: RoundType,
+    decimal: DecimalType,
+    overflow: OverflowMode,
 }
 
+/// What to do with a value that does not fit the width of its field.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OverflowMode {
+    /// Fill the field with asterisks, as Fortran does.
+    Asterisks,
+    /// Fail with `WriteErr::FieldOverflow`.
+    Error,
+    /// Write the whole value, making the field wider.
+    Widen,
+}
+
+/// Settings of a `FortranIterWriter` chosen by the caller.
+#[derive(Debug, Clone)]
+pub struct WriterConfig {
+    /// Fail with `WriteErr::InvalidEditing` when an item's type does not
+    /// match its data edit descriptor. When unset, the value is coerced
+    /// the way ifort does: integers and reals are converted into each
+    /// other, O and Z show the bits of a real, logicals are edited as
+    /// integers and vice versa. Characters are never coerced.
+    pub strict: bool,
+    /// The decimal mode a record starts in, as given by `DECIMAL=` on
+    /// OPEN. DC and DP descriptors switch it within a format.
+    pub decimal: DecimalType,
+    /// What happens to an I, B, O, Z, F, E, D or G field too narrow for
+    /// its value: `Asterisks` fills it with `*` as Fortran does, `Error`
+    /// stops the statement with `WriteErr::FieldOverflow`, and `Widen`
+    /// writes the whole value, shifting the rest of the record. L and A
+    /// fields never overflow.
+    pub overflow: OverflowMode,
+    /// The value a missing `Option` item is written as by I, F, E, D
+    /// and G descriptors and in list-directed output, cast to the type
+    /// of the item. Other descriptors, and all of them when there is
+    /// no missing value, write blanks; list-directed output leaves
+    /// the value out.
+    pub missing: Option<f64>,
+}
+
+impl Default for WriterConfig {
+    fn default() -> WriterConfig {
+        WriterConfig {
+            strict: true,
+            decimal: DecimalType::DP,
+            overflow: OverflowMode::Asterisks,
+            missing: None,
+        }
+    }
+}
+
+/// Formatted writer. Items are edited into a buffer holding the current
+/// record, which is written to `dst` when the record ends and when
+/// `write_constants(dst, false)` finishes the statement.
 pub struct FortranIterWriter<'a> {
-    iter: Peekable<FormatEvalIter<'a>>,
-    node: &'a FormatNode,
+    program: Cow<'a, FormatProgram>,
+    cursor: ProgramCursor,
+    record: Vec<u8>,
     opts: WriterOpts,
+    config: WriterConfig,
     consumed_data: bool,
+    // data items written so far and the instruction of the last one
+    items: usize,
+    last_data: usize,
+    advance: bool,
+}
+
+/// List-directed writer, the counterpart of `FortranDefaultReader`.
+#[derive(Default)]
+pub struct FortranDefaultWriter {
+    config: WriterConfig,
+    items: usize,
+}
+
+/// A value edited with a format, to be written with `{}`, e.g.
+/// `format!("x ={}", FortranDisplay::new(&x, &fmt))`. The width and
+/// alignment of the placeholder apply to the edited record, the
+/// trailing newline is left out. Values the format cannot edit are
+/// shown as `*`, `try_to_string` gives the error instead.
+pub struct FortranDisplay<'a, T: 'a + FortranWrite> {
+    value: &'a T,
+    fmt: &'a FormatNode,
 }
 
 
@@ -26,38 +106,377 @@ pub enum WriteErr {
     UnexpectedQInWrite,
     InvalidState,
     InvalidEditing(FormatNode, FortranTag),
+    /// The value edited as the given text does not fit its descriptor.
+    /// The item is counted from zero over everything written with the
+    /// writer, each array element being an item.
+    FieldOverflow(usize, FormatNode, String),
+    /// A record is longer than the records of an internal file.
+    RecordOverflow,
+    /// A record is written past the last one of an internal file.
+    EndOfFile,
 }
 
 impl From<::std::io::Error> for WriteErr {
     fn from(x: ::std::io::Error) -> WriteErr {
-        WriteErr::IoErr(x)
+        use internal::InternalError;
+        match x.get_ref().and_then(|e| e.downcast_ref::<InternalError>()) {
+            Some(&InternalError::RecordOverflow) => WriteErr::RecordOverflow,
+            Some(&InternalError::EndOfFile) => WriteErr::EndOfFile,
+            None => WriteErr::IoErr(x),
+        }
     }
 }
 
 pub trait FortranWrite {
+    /// Formatted output of the value. Fields are edited into the record
+    /// the writer holds, `dst` only receives the records completed while
+    /// writing the value, e.g. when an array reverts the format. The last
+    /// record reaches `dst` when `write_constants(dst, false)` ends the
+    /// statement.
     fn fortran_write<W: Write>(&self, dst: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr>;
-    fn fortran_write_default<W: Write>(&self, dst: &mut W) -> Result<(), WriteErr>;
+    /// List-directed output of the value. The writer carries the DECIMAL
+    /// mode and separates items, so implementations for compound types
+    /// should pass it on to their parts. It is taken since 0.2.0.
+    fn fortran_write_default<W: Write>(&self, dst: &mut W, writer: &mut FortranDefaultWriter) -> Result<(), WriteErr>;
+}
+
+/// Right-justify `field` in `w` columns of the record. A zero width is
+/// as wide as the field. A field that does not fit is handled as `mode`
+/// says, the error holds its text.
+fn put_field(rec: &mut Vec<u8>, field: &[u8], w: usize, mode: OverflowMode) -> Result<(), String> {
+    if w == 0 || (field.len() > w && mode == OverflowMode::Widen) {
+        rec.extend_from_slice(field);
+    } else if field.len() <= w {
+        rec.resize(rec.len() + w - field.len(), b' ');
+        rec.extend_from_slice(field);
+    } else if mode == OverflowMode::Asterisks {
+        rec.resize(rec.len() + w, b'*');
+    } else {
+        return Err(String::from_utf8_lossy(field).into_owned());
+    }
+    Ok(())
+}
+
+fn write_bool(rec: &mut Vec<u8>, val: bool, ow: Option<usize>) -> Result<(), String> {
+    put_field(rec, if val { b"T" } else { b"F" }, ow.unwrap_or(2), OverflowMode::Asterisks)
+}
+
+fn write_int<T>(rec: &mut Vec<u8>, val: T, t: IntFormat, w: usize, om: Option<usize>, opts: &WriterOpts)
+    -> Result<(), String>
+    where T: Display + Octal + LowerHex + Binary
+{
+    let mut out = EditBuf::new();
+    match t {
+        IntFormat::I => out.fmt(format_args!("{}", val)),
+        IntFormat::O => out.fmt(format_args!("{:o}", val)),
+        IntFormat::Z => out.fmt(format_args!("{:x}", val)),
+        IntFormat::B => out.fmt(format_args!("{:b}", val)),
+    }
+    let negative = out.as_bytes()[0] == b'-';
+    if negative {
+        out.remove(0);
+    }
+    match om {
+        // Iw.0 writes zero as blanks
+        Some(0) if out.as_bytes() == b"0" => out.clear(),
+        Some(m) if out.len() < m => {
+            let missing = m - out.len();
+            out.insert(0, b'0', missing);
+        },
+        _ => {},
+    }
+    // only I editing has a sign
+    match t {
+        _ if negative => out.insert(0, b'-', 1),
+        IntFormat::I if opts.sign_plus && !out.is_empty() => out.insert(0, b'+', 1),
+        _ => {},
+    }
+    put_field(rec, out.as_bytes(), w, opts.overflow)
+}
+
+/// Exponent part of E, D, ES and EN editing, `false` if it does not fit.
+fn edit_exponent(out: &mut EditBuf, letter: char, exp: isize, oe: Option<usize>) -> bool {
+    let sign = if exp < 0 { '-' } else { '+' };
+    let abs = exp.unsigned_abs();
+    let len = abs.checked_ilog10().map_or(1, |l| l as usize + 1);
+    match oe {
+        Some(e) if len <= e => out.fmt(format_args!("{}{}{:0e$}", letter, sign, abs, e=e)),
+        Some(_) => return false,
+        None if abs <= 99 => out.fmt(format_args!("{}{}{:02}", letter, sign, abs)),
+        None if abs <= 999 => out.fmt(format_args!("{}{:03}", sign, abs)),
+        None => return false,
+    }
+    true
+}
+
+/// Ew.d and Dw.d editing of a non-negative value with the scale factor.
+fn edit_e(out: &mut EditBuf, val: f64, d: usize, oe: Option<usize>, letter: char, negative: bool,
+          opts: &WriterOpts) -> bool
+{
+    let (d, k) = (d as isize, opts.scale);
+    if k <= -d || k > d + 1 {
+        return false;
+    }
+    let n = if k <= 0 { d + k } else { d + 1 } as usize;
+    let mut digits = EditBuf::new();
+    let x = sig_digits(val, n, negative, opts.round, &mut digits);
+    if k <= 0 {
+        out.push_str("0.");
+        out.push_repeat(b'0', -k as usize);
+        out.push_bytes(digits.as_bytes());
+    } else {
+        let (int, frac) = digits.as_bytes().split_at(k as usize);
+        out.push_bytes(int);
+        out.push_str(".");
+        out.push_bytes(frac);
+    }
+    let exp = if val == 0.0 { 0 } else { x - k };
+    edit_exponent(out, letter, exp, oe)
+}
+
+/// ESw.d editing of a non-negative value.
+fn edit_es(out: &mut EditBuf, val: f64, d: usize, oe: Option<usize>, negative: bool, round: RoundType) -> bool {
+    let mut digits = EditBuf::new();
+    let x = sig_digits(val, d + 1, negative, round, &mut digits);
+    let exp = if val == 0.0 { 0 } else { x - 1 };
+    let (int, frac) = digits.as_bytes().split_at(1);
+    out.push_bytes(int);
+    out.push_str(".");
+    out.push_bytes(frac);
+    edit_exponent(out, 'E', exp, oe)
+}
+
+/// ENw.d editing of a non-negative value: the exponent is a multiple
+/// of three and one to three digits precede the decimal point.
+fn edit_en(out: &mut EditBuf, val: f64, d: usize, oe: Option<usize>, negative: bool, round: RoundType) -> bool {
+    let mut digits = EditBuf::new();
+    let mut x = sig_digits(val, d + 1, negative, round, &mut digits);
+    loop {
+        let exp = if val == 0.0 { 0 } else { (x - 1).div_euclid(3) * 3 };
+        let int = (x - exp) as usize;
+        let rounded = sig_digits(val, int + d, negative, round, &mut digits);
+        // rounding may carry into another digit, e.g. 999.96 to 1000.0
+        if rounded != x {
+            x = rounded;
+            continue;
+        }
+        let (int, frac) = digits.as_bytes().split_at(int);
+        out.push_bytes(int);
+        out.push_str(".");
+        out.push_bytes(frac);
+        return edit_exponent(out, 'E', exp, oe);
+    }
+}
+
+/// EXw.d editing of a non-negative value: a hexadecimal significand
+/// with `d` digits after the point and a binary exponent. Without `d`
+/// the significand is as short as the value allows.
+fn edit_ex(out: &mut EditBuf, val: f64, od: Option<usize>, oe: Option<usize>, negative: bool,
+           round: RoundType) -> bool
+{
+    if val == 0.0 {
+        out.push_str("0X0.");
+        out.push_repeat(b'0', od.unwrap_or(0));
+        out.fmt(format_args!("P+{:0e$}", 0, e=oe.unwrap_or(0)));
+        return true;
+    }
+    // normalize to 1.frac * 2^exp with a 52 bit fraction
+    let bits = val.to_bits();
+    let (mut frac, mut exp) = match ((bits >> 52) & 0x7ff) as isize {
+        0 => {
+            let frac = bits & ((1 << 52) - 1);
+            let shift = frac.leading_zeros() as isize - 11;
+            ((frac << shift) & ((1 << 52) - 1), -1022 - shift)
+        },
+        e => (bits & ((1 << 52) - 1), e - 1023),
+    };
+    let d = match od {
+        Some(d) => d,
+        None => (13 - frac.trailing_zeros() as usize / 4).min(13),
+    };
+    if d < 13 {
+        // round to 4d bits
+        let drop = 52 - 4 * d as u32;
+        let half = 1u64 << (drop - 1);
+        let rest = frac & ((1 << drop) - 1);
+        frac >>= drop;
+        let up = rest != 0 && match round {
+            RoundType::RZ => false,
+            RoundType::RU => !negative,
+            RoundType::RD => negative,
+            RoundType::RC => rest >= half,
+            RoundType::RN | RoundType::RP => rest > half || (rest == half && frac & 1 == 1),
+        };
+        if up {
+            frac += 1;
+        }
+        if frac >> (4 * d) != 0 {
+            frac = 0;
+            exp += 1;
+        }
+    }
+    let sign = if exp < 0 { '-' } else { '+' };
+    let e = oe.unwrap_or(0);
+    let abs = exp.unsigned_abs();
+    if oe.is_some() && abs.checked_ilog10().map_or(1, |l| l as usize + 1) > e {
+        return false;
+    }
+    out.push_str("0X1.");
+    if d > 0 {
+        // the fraction has 13 hex digits, any more are zeros
+        out.fmt(format_args!("{:0d$X}", frac, d=d.min(13)));
+        out.push_repeat(b'0', d.saturating_sub(13));
+    }
+    out.fmt(format_args!("P{}{:0e$}", sign, abs, e=e));
+    true
+}
+
+/// Edit a real value with the given data edit descriptor, the scale
+/// factor, sign, rounding and decimal modes are taken from `opts`.
+/// Returns `false` if the value cannot be represented, a field of `w`
+/// asterisks is written instead.
+fn edit_real<T>(out: &mut EditBuf, val: T, t: RealFormat, w: usize, od: Option<usize>, oe: Option<usize>,
+                opts: &WriterOpts) -> bool
+    where T: Display + Debug + Into<f64> + Copy
+{
+    let v: f64 = val.into();
+    let negative = v.is_sign_negative();
+    if v.is_nan() {
+        out.push_str("NaN");
+        return true;
+    }
+    if negative {
+        out.push_str("-");
+    } else if opts.sign_plus {
+        out.push_str("+");
+    }
+    if v.is_infinite() {
+        out.push_str("Infinity");
+        return true;
+    }
+    let start = out.len();
+    let (abs, round) = (v.abs(), opts.round);
+    let mut digits = EditBuf::new();
+    let fits = match (t, od) {
+        // G0 and F without d: the shortest representation
+        (RealFormat::G, None) | (RealFormat::F, None) => {
+            if w == 0 && t == RealFormat::G {
+                out.fmt(format_args!("{:?}", val));
+                out.as_mut_bytes().iter_mut().filter(|c| **c == b'e').for_each(|c| *c = b'E');
+            } else {
+                out.fmt(format_args!("{}", val));
+            }
+            if negative {
+                out.remove(start);
+            }
+            true
+        },
+        (RealFormat::F, Some(d)) => {
+            fixed_digits(abs * 10f64.powi(opts.scale as i32), d, negative, round, &mut digits);
+            out.push_bytes(digits.as_bytes());
+            true
+        },
+        (RealFormat::E, Some(d)) => edit_e(out, abs, d, oe, 'E', negative, opts),
+        (RealFormat::D, Some(d)) => edit_e(out, abs, d, oe, 'D', negative, opts),
+        (RealFormat::ES, Some(d)) => edit_es(out, abs, d, oe, negative, round),
+        (RealFormat::EN, Some(d)) => edit_en(out, abs, d, oe, negative, round),
+        (RealFormat::EX, od) => edit_ex(out, abs, od, oe, negative, round),
+        (RealFormat::G, Some(d)) => {
+            // F editing if the value has no more than d integer digits,
+            // followed by the blanks that would hold the exponent
+            let blanks = oe.map(|e| e + 2).unwrap_or(4);
+            let x = sig_digits(abs, d, negative, round, &mut digits);
+            if abs == 0.0 || (0 <= x && x <= d as isize) {
+                let d = if abs == 0.0 { d.saturating_sub(1) } else { d - x as usize };
+                fixed_digits(abs, d, negative, round, &mut digits);
+                out.push_bytes(digits.as_bytes());
+                // a zero width is as wide as the value needs
+                if w != 0 {
+                    out.push_repeat(b' ', blanks);
+                }
+                true
+            } else {
+                edit_e(out, abs, d, oe, 'E', negative, opts)
+            }
+        },
+        (_, None) => false,
+    };
+    if opts.decimal == DecimalType::DC {
+        out.as_mut_bytes().iter_mut().filter(|c| **c == b'.').for_each(|c| *c = b',');
+    }
+    fits
 }
 
+fn write_real<T>(rec: &mut Vec<u8>, val: T, t: RealFormat, w: usize, od: Option<usize>, oe: Option<usize>,
+                 opts: &WriterOpts) -> Result<(), String>
+    where T: Display + Debug + Into<f64> + Copy
+{
+    let mut out = EditBuf::new();
+    let mut fits = edit_real(&mut out, val, t, w, od, oe, opts);
+    if !fits && oe.is_some() && opts.overflow == OverflowMode::Widen {
+        // no real has a decimal exponent of more than three digits
+        out.clear();
+        fits = edit_real(&mut out, val, t, w, od, Some(3), opts);
+    }
+    if !fits {
+        if opts.overflow != OverflowMode::Asterisks {
+            return Err(format!("{}", val));
+        }
+        rec.resize(rec.len() + w, b'*');
+        return Ok(());
+    }
+    // the zero before the decimal symbol is optional, e.g. -.25 in F4.2
+    let bytes = out.as_bytes();
+    let start = if bytes.first().is_some_and(|&c| c == b'-' || c == b'+') { 1 } else { 0 };
+    if w != 0 && bytes.len() > w && bytes.get(start) == Some(&b'0')
+        && bytes.get(start + 1).is_some_and(|&c| c == b'.' || c == b',')
+        && bytes.get(start + 2).is_some_and(|c| c.is_ascii_digit()) {
+        out.remove(start);
+    }
+    put_field(rec, out.as_bytes(), w, opts.overflow)
+}
+
+fn write_str(rec: &mut Vec<u8>, val: &str, ow: Option<usize>) -> Result<(), String> {
+    let w = match ow {
+        Some(w) => w,
+        None => {
+            rec.extend_from_slice(val.as_bytes());
+            return Ok(());
+        },
+    };
+    // the leftmost w characters, or the value right-justified
+    match val.char_indices().nth(w) {
+        Some((end, _)) => rec.extend_from_slice(&val.as_bytes()[..end]),
+        None => {
+            let len = val.chars().count();
+            rec.resize(rec.len() + w - len, b' ');
+            rec.extend_from_slice(val.as_bytes());
+        },
+    }
+    Ok(())
+}
+
+// In lenient mode the arms below the standard editings coerce the value
+// the way ifort does; in strict mode `next_data` rejects them beforehand.
+
 macro_rules! impl_bool_write {
     ($ty: ty) => {
         impl FortranWrite for $ty {
-            fn fortran_write<W: Write>(&self, dst: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
-                let n = match writer.iter.next() {
-                    Some(n) if requires_data(n)? => n,
-                    _ => return Err(WriteErr::InvalidState),
-                };
+            fn fortran_write<W: Write>(&self, _: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
+                let tag = <$ty as FortranAltType>::fortran_tag();
                 let val = *self;
-
-                let ow =
-                    if let &FormatNode::Bool(ow) = n { ow }
-                    else { return Err(WriteErr::InvalidState) };
-                let c = if val { 'T' } else { 'F' };
-                write!(dst, "{:>w$}", c, w=ow.unwrap_or(2))?;
-                Ok(())
+                let res = match *writer.next_data(tag)? {
+                    FormatNode::Bool(ow) => write_bool(&mut writer.record, val, ow),
+                    FormatNode::Real(RealFormat::G, w, _, _) => write_bool(&mut writer.record, val, Some(w)),
+                    // ifort stores .TRUE. as -1
+                    FormatNode::Int(t, w, om) => write_int(&mut writer.record, if val { -1i32 } else { 0 }, t, w, om, &writer.opts),
+                    ref n => return Err(WriteErr::InvalidEditing(n.clone(), tag)),
+                };
+                res.map_err(|text| writer.overflow(text))
             }
 
-            fn fortran_write_default<W: Write>(&self, dst: &mut W) -> Result<(), WriteErr> {
+            fn fortran_write_default<W: Write>(&self, dst: &mut W, writer: &mut FortranDefaultWriter) -> Result<(), WriteErr> {
+                writer.next_item(dst)?;
                 let c = if *self { 'T' } else { 'F' };
                 write!(dst, "{:>7}", c)?;
                 Ok(())
@@ -71,40 +490,22 @@ impl_bool_write!(bool);
 macro_rules! impl_int_write {
     ($ty: ty, $w: expr) => {
         impl FortranWrite for $ty {
-            fn fortran_write<W: Write>(&self, dst: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
-                let n = match writer.iter.next() {
-                    Some(n) if try!(requires_data(n)) => n,
-                    _ => return Err(WriteErr::InvalidState),
-                };
-                writer.consumed_data = true;
+            fn fortran_write<W: Write>(&self, _: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
+                let tag = <$ty as FortranAltType>::fortran_tag();
                 let val = *self;
-
-                let (t, w, om) =
-                    if let &FormatNode::Int(t, w, om) = n { (t, w, om) }
-                    else { return Err(WriteErr::InvalidState) };
-
-                let mut s = if let Some(m) = om {
-                    match t {
-                        IntFormat::I => format!("{:>w$}", format!("{:0m$}", val, m=m), w=w),
-                        IntFormat::O => format!("{:>w$}", format!("{:0m$o}", val, m=m), w=w),
-                        IntFormat::Z => format!("{:>w$}", format!("{:0m$x}", val, m=m), w=w),
-                    }
-                } else {
-                    match t {
-                        IntFormat::I => format!("{:>w$}", val, w=w),
-                        IntFormat::O => format!("{:>w$o}", val, w=w),
-                        IntFormat::Z => format!("{:>w$x}", val, w=w),
-                    }
+                let res = match *writer.next_data(tag)? {
+                    FormatNode::Int(t, w, om) => write_int(&mut writer.record, val, t, w, om, &writer.opts),
+                    FormatNode::Real(RealFormat::G, w, _, _) => write_int(&mut writer.record, val, IntFormat::I, w, None, &writer.opts),
+                    FormatNode::Real(t, w, od, oe) => write_real(&mut writer.record, val as f64, t, w, od, oe, &writer.opts),
+                    // ifort only tests the low bit of an integer used as a logical
+                    FormatNode::Bool(ow) => write_bool(&mut writer.record, (val & 1) != 0, ow),
+                    ref n => return Err(WriteErr::InvalidEditing(n.clone(), tag)),
                 };
-
-                if s.len() > w {
-                    s = format!("{:*>w$}", w=w)
-                }
-                write!(dst, "{}", s)?;
-                Ok(())
+                res.map_err(|text| writer.overflow(text))
             }
 
-            fn fortran_write_default<W: Write>(&self, dst: &mut W) -> Result<(), WriteErr> {
+            fn fortran_write_default<W: Write>(&self, dst: &mut W, writer: &mut FortranDefaultWriter) -> Result<(), WriteErr> {
+                writer.next_item(dst)?;
                 const W: usize = $w;
                 write!(dst, "{:>w$}", *self, w=W)?;
                 Ok(())
@@ -125,35 +526,29 @@ impl_int_write! { u8, 5 }
 macro_rules! impl_float_write {
     ($ty: ty, $w: expr, $d: expr, $e: expr) => {
         impl FortranWrite for $ty {
-            fn fortran_write<W: Write>(&self, dst: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
-                let n = match writer.iter.next() {
-                    Some(n) if try!(requires_data(n)) => n,
-                    _ => return Err(WriteErr::InvalidState),
-                };
-                writer.consumed_data = true;
+            fn fortran_write<W: Write>(&self, _: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
+                let tag = <$ty as FortranAltType>::fortran_tag();
                 let val = *self;
-
-                let (_t, w, od) =
-                    if let &FormatNode::Real(t, w, od, _oe) = n { (t, w, od) }
-                    else { return Err(WriteErr::InvalidState) };
-
-                let s = if let Some(d) = od {
-                    format!("{:>w$.d$}", val, w=w, d=d)
-                } else {
-                    format!("{:>w$}", val, w=w)
+                let res = match *writer.next_data(tag)? {
+                    FormatNode::Real(t, w, od, oe) => write_real(&mut writer.record, val, t, w, od, oe, &writer.opts),
+                    // ifort truncates a real edited with I,
+                    // O and Z show its internal representation
+                    FormatNode::Int(IntFormat::I, w, om) => write_int(&mut writer.record, val as i64, IntFormat::I, w, om, &writer.opts),
+                    FormatNode::Int(t, w, om) => write_int(&mut writer.record, val.to_bits(), t, w, om, &writer.opts),
+                    ref n => return Err(WriteErr::InvalidEditing(n.clone(), tag)),
                 };
-                if s.len() > w {
-                    write!(dst, "{:*>width$}", "", width=w)?;
-                } else {
-                    write!(dst, "{:>width$}", s, width=w)?;
-                }
-                Ok(())
+                res.map_err(|text| writer.overflow(text))
             }
 
-            fn fortran_write_default<W: Write>(&self, dst: &mut W) -> Result<(), WriteErr> {
+            fn fortran_write_default<W: Write>(&self, dst: &mut W, writer: &mut FortranDefaultWriter) -> Result<(), WriteErr> {
+                writer.next_item(dst)?;
                 const W: usize = $w;
                 const D: usize = $d;
-                write!(dst, "{: >w$.d$}", *self, w=W, d=D)?;
+                let s = format!("{: >w$.d$}", *self, w=W, d=D);
+                match writer.config.decimal {
+                    DecimalType::DC => write!(dst, "{}", s.replace('.', ","))?,
+                    DecimalType::DP => write!(dst, "{}", s)?,
+                }
                 Ok(())
             }
         }
@@ -164,48 +559,63 @@ impl_float_write! { f64, 25, 16, 2 }
 impl_float_write! { f32, 15, 6, 2 }
 
 impl FortranWrite for String {
-    fn fortran_write<W: Write>(&self, dst: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
-        let n = match writer.iter.next() {
-            Some(n) if try!(requires_data(n)) => n,
-            _ => return Err(WriteErr::InvalidState),
+    fn fortran_write<W: Write>(&self, _: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
+        let tag = <String as FortranAltType>::fortran_tag();
+        let res = match *writer.next_data(tag)? {
+            FormatNode::Str(ow) => write_str(&mut writer.record, self, ow),
+            FormatNode::Real(RealFormat::G, w, _, _) => write_str(&mut writer.record, self, Some(w)),
+            ref n => return Err(WriteErr::InvalidEditing(n.clone(), tag)),
         };
-        writer.consumed_data = true;
-
-        let ow =
-            if let &FormatNode::Str(ow) = n { ow }
-            else { return Err(WriteErr::InvalidState) };
-        if let Some(w) = ow {
-            let len = self.len();
-            if len <= w {
-                write!(dst, "{:>w$}", self, w=w)?;
-            } else {
-                let s: String = self.chars().take(w).collect();
-                write!(dst, "{}", s)?;
-            }
-        } else {
-            write!(dst, "{}", self)?;
-        }
-        Ok(())
+        res.map_err(|text| writer.overflow(text))
     }
 
-    fn fortran_write_default<W: Write>(&self, dst: &mut W) -> Result<(), WriteErr> {
+    fn fortran_write_default<W: Write>(&self, dst: &mut W, writer: &mut FortranDefaultWriter) -> Result<(), WriteErr> {
+        writer.next_item(dst)?;
         dst.write_all(self.as_bytes())?;
         Ok(())
     }
 }
 
-impl<'a, T: FortranWrite> FortranWrite for &'a [T] {
+impl<T: FortranWrite + FortranAltType> FortranWrite for Option<T> {
+    fn fortran_write<W: Write>(&self, dst: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
+        match *self {
+            Some(ref val) => val.fortran_write(dst, writer),
+            None => writer.write_missing(<T as FortranAltType>::fortran_tag()),
+        }
+    }
+
+    fn fortran_write_default<W: Write>(&self, dst: &mut W, writer: &mut FortranDefaultWriter) -> Result<(), WriteErr> {
+        let tag = <T as FortranAltType>::fortran_tag();
+        let m = match (self, writer.config.missing) {
+            (Some(val), _) => return val.fortran_write_default(dst, writer),
+            (None, Some(m)) => m,
+            (None, None) => return writer.next_item(dst),
+        };
+        // as wide as the values of the item's type
+        match tag {
+            FortranTag::Byte => (m as i8).fortran_write_default(dst, writer),
+            FortranTag::Int2 | FortranTag::Uint2 => (m as i16).fortran_write_default(dst, writer),
+            FortranTag::Int4 | FortranTag::Uint4 => (m as i32).fortran_write_default(dst, writer),
+            FortranTag::Int8 | FortranTag::Uint8 => (m as i64).fortran_write_default(dst, writer),
+            FortranTag::Real4 => (m as f32).fortran_write_default(dst, writer),
+            FortranTag::Real8 => m.fortran_write_default(dst, writer),
+            _ => writer.next_item(dst),
+        }
+    }
+}
+
+impl<T: FortranWrite> FortranWrite for &[T] {
     fn fortran_write<W: Write>(&self, dst: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
         for v in self.iter() {
             writer.write_constants(dst, true)?;
-            (&v).fortran_write(dst, writer)?;
+            v.fortran_write(dst, writer)?;
         }
         Ok(())
     }
 
-    fn fortran_write_default<W: Write>(&self, dst: &mut W) -> Result<(), WriteErr> {
+    fn fortran_write_default<W: Write>(&self, dst: &mut W, writer: &mut FortranDefaultWriter) -> Result<(), WriteErr> {
         for v in self.iter() {
-            (&v).fortran_write_default(dst)?;
+            v.fortran_write_default(dst, writer)?;
         }
         Ok(())
     }
@@ -215,14 +625,14 @@ impl<T: FortranWrite> FortranWrite for Vec<T> {
     fn fortran_write<W: Write>(&self, dst: &mut W, writer: &mut FortranIterWriter) -> Result<(), WriteErr> {
         for v in self.iter() {
             writer.write_constants(dst, true)?;
-            (&v).fortran_write(dst, writer)?;
+            v.fortran_write(dst, writer)?;
         }
         Ok(())
     }
 
-    fn fortran_write_default<W: Write>(&self, dst: &mut W) -> Result<(), WriteErr> {
+    fn fortran_write_default<W: Write>(&self, dst: &mut W, writer: &mut FortranDefaultWriter) -> Result<(), WriteErr> {
         for v in self.iter() {
-            (&v).fortran_write_default(dst)?;
+            v.fortran_write_default(dst, writer)?;
         }
         Ok(())
     }
@@ -241,106 +651,551 @@ fn requires_data(n: &FormatNode) -> Result<bool, WriteErr> {
         &Radix(_) => false,
         &Scale(_) => false,
         &Literal(_) => false,
+        &Hollerith(_) => false,
+        &SignControl(_) => false,
+        &RoundControl(_) => false,
+        &DecimalControl(_) => false,
 
         &Str(_) => true,
         &Bool(_) => true,
         &Int(_, _, _) => true,
         &Real(_, _, _, _) => true,
-        &Group(_) | &Repeat(_, _) => unreachable!(),
+        &Group(_) | &Repeat(_, _) | &UnlimitedRepeat(_) => unreachable!(),
         &RemainingChars => return Err(WriteErr::UnexpectedQInWrite),
     };
     Ok(rv)
 }
 
 impl<'a> FortranIterWriter<'a> {
-    pub fn new<'f>(fmt: &'f FormatNode) -> FortranIterWriter<'f> {
+    pub fn new(fmt: &FormatNode) -> FortranIterWriter<'_> {
+        FortranIterWriter::with_config(fmt, WriterConfig::default())
+    }
+
+    pub fn with_config(fmt: &FormatNode, config: WriterConfig) -> FortranIterWriter<'_> {
+        FortranIterWriter::from_cow(Cow::Owned(FormatProgram::compile(fmt)), config)
+    }
+
+    /// A writer running a program compiled beforehand, which
+    /// may be shared by any number of writers.
+    pub fn from_program(program: &'a FormatProgram, config: WriterConfig) -> FortranIterWriter<'a> {
+        FortranIterWriter::from_cow(Cow::Borrowed(program), config)
+    }
+
+    fn from_cow(program: Cow<'a, FormatProgram>, config: WriterConfig) -> FortranIterWriter<'a> {
         FortranIterWriter {
             opts: WriterOpts {
                 terminated: false,
                 suppress_newline: false,
                 scale: 0,
                 radix: 10,
+                sign_plus: false,
+                round: RoundType::RP,
+                decimal: config.decimal,
+                overflow: config.overflow,
             },
+            config,
             consumed_data: false,
-            node: fmt,
-            iter: fmt.into_iter().peekable(),
+            items: 0,
+            last_data: 0,
+            advance: true,
+            cursor: program.cursor(),
+            program,
+            record: Vec::new(),
         }
     }
 
+    /// Take the next data edit descriptor for an item of type `tag`.
+    fn next_data(&mut self, tag: FortranTag) -> Result<&FormatNode, WriteErr> {
+        let n = match self.cursor.next(&self.program) {
+            Some(n) if requires_data(n)? => n,
+            _ => return Err(WriteErr::InvalidState),
+        };
+        self.consumed_data = true;
+        self.items += 1;
+        self.last_data = self.cursor.position() - 1;
+        if self.config.strict && !n.accepts(tag) {
+            return Err(WriteErr::InvalidEditing(n.clone(), tag));
+        }
+        Ok(n)
+    }
+
+    /// Write the field of a missing item of type `tag`, either
+    /// the missing value of the config or blanks.
+    fn write_missing(&mut self, tag: FortranTag) -> Result<(), WriteErr> {
+        let missing = self.config.missing;
+        let res = match (self.next_data(tag)?.clone(), missing) {
+            (FormatNode::Int(t, w, om), Some(m)) => write_int(&mut self.record, m as i64, t, w, om, &self.opts),
+            (FormatNode::Real(RealFormat::G, w, _, _), Some(m)) if tag.is_integer() => {
+                write_int(&mut self.record, m as i64, IntFormat::I, w, None, &self.opts)
+            },
+            (FormatNode::Real(t, w, od, oe), Some(m)) => write_real(&mut self.record, m, t, w, od, oe, &self.opts),
+            (FormatNode::Int(_, w, _), None) | (FormatNode::Real(_, w, _, _), None) => self.blank(w),
+            (FormatNode::Bool(ow), _) => self.blank(ow.unwrap_or(2)),
+            (FormatNode::Str(ow), _) => self.blank(ow.unwrap_or(0)),
+            (n, _) => return Err(WriteErr::InvalidEditing(n, tag)),
+        };
+        res.map_err(|text| self.overflow(text))
+    }
+
+    fn blank(&mut self, w: usize) -> Result<(), String> {
+        self.record.resize(self.record.len() + w, b' ');
+        Ok(())
+    }
+
+    /// The error for the last item, edited as `text`, not fitting its field.
+    fn overflow(&self, text: String) -> WriteErr {
+        match self.program.instructions()[self.last_data] {
+            Instr::Edit(ref n) => WriteErr::FieldOverflow(self.items - 1, n.clone(), text),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Write the current record to `dst`.
+    fn end_record<W: Write>(&mut self, dst: &mut W, newline: bool) -> Result<(), WriteErr> {
+        if newline {
+            self.record.push(b'\n');
+        }
+        dst.write_all(&self.record)?;
+        self.record.clear();
+        Ok(())
+    }
+
+    /// Write the record the statement ends in, which stays open
+    /// for the next statement if it is non-advancing or ended by `$`.
+    fn end_statement<W: Write>(&mut self, dst: &mut W) -> Result<(), WriteErr> {
+        let newline = self.advance && !self.opts.suppress_newline;
+        self.end_record(dst, newline)
+    }
+
+    /// Choose whether the statement ends its last record, as ADVANCE=
+    /// does. The record a non-advancing statement leaves is written
+    /// without a newline, the next statement continues it.
+    pub fn set_advance(&mut self, advance: bool) {
+        self.advance = advance;
+    }
 
     pub fn write_constants<W>(&mut self, dst: &mut W, has_data: bool) -> Result<(), WriteErr>
         where W: Write
     {
         use format::FormatNode::*;
         loop {
-            let has_next = self.iter.peek().is_some();
+            let has_next = self.cursor.peek(&self.program).is_some();
 
             if !has_next {
                 // a the end of the iterator
                 if !has_data {
                     // with no data, print the newline, done
-                    if !self.opts.suppress_newline {
-                        dst.write_all(b"\n")?;
-                    }
-                    return Ok(())
+                    return self.end_statement(dst);
                 } else {
                     // if there's data present, but the format string
                     // consumes no data, this is an error
                     if !self.consumed_data {
                         return Err(WriteErr::DataWithoutFormat);
                     } else {
-                        // otherwise, we've reached the end of the pattern, reset the iterator
-                        self.iter = self.node.into_iter().peekable();
+                        // otherwise, we've reached the end of the pattern,
+                        // revert and continue in a new record
+                        self.end_record(dst, true)?;
+                        self.cursor.revert(&self.program);
                     }
                 }
             }
 
-            if let Some(next) = self.iter.peek() {
-                if try!(requires_data(*next)) {
+            if let Some(next) = self.cursor.peek(&self.program) {
+                if requires_data(next)? {
+                    if !has_data {
+                        // the data ran out before the format did
+                        return self.end_statement(dst);
+                    }
                     break;
                 }
             }
 
-            let next = self.iter.next().unwrap();
-
-            match next {
-                &Radix(r) => { self.opts.radix = r; },
-                &Scale(p) => { self.opts.scale = p; },
-                &Literal(ref s) => {
-                    try!(dst.write_all(s.as_bytes()));
-                } ,
-                // TODO: seek until next newline?
-                &NewLine => {
-                    try!(dst.write_all(b"\n"));
+            let next = self.cursor.next(&self.program).unwrap();
+
+            match *next {
+                Radix(r) => { self.opts.radix = r; },
+                Scale(p) => { self.opts.scale = p; },
+                SignControl(ref t) => { self.opts.sign_plus = *t == SignType::SP; },
+                RoundControl(r) => { self.opts.round = r; },
+                DecimalControl(d) => { self.opts.decimal = d; },
+                Literal(ref s) | Hollerith(ref s) => {
+                    self.record.extend_from_slice(s.as_bytes());
+                },
+                NewLine => {
+                    self.end_record(dst, true)?;
                 },
                 // TODO: seek instead of writing space?
-                &SkipChar => {
-                    try!(dst.write_all(b" "));
+                SkipChar => {
+                    self.record.push(b' ');
                 },
-                &SuppressNewLine => {
+                SuppressNewLine => {
                     self.opts.suppress_newline = true;
                 },
-                &Terminate => {
+                Terminate => {
                     if !has_data {
+                        // the record still ends here
                         self.opts.terminated = true;
-                        return Ok(());
+                        return self.end_statement(dst);
                     }
                 },
                 // TODO: seek?
-                &BlankControl(_) => {},
-                &AbsColumn(_) => {},
-                &RelColumn(_) => {},
-                x@_ => {
-                    unreachable!(format!("{:?}", x))
+                BlankControl(_) => {},
+                AbsColumn(_) => {},
+                RelColumn(_) => {},
+                ref x => {
+                    unreachable!("{:?}", x)
                 }
             }
         }
         Ok(())
     }
 
+    /// Write one item. The record it is edited into is held by the writer
+    /// until it ends, call `write_constants(dst, false)` after the last
+    /// item to write it out.
     pub fn write_value<W: Write, T: FortranWrite>
         (&mut self, dst: &mut W, val: &T) -> Result<(), WriteErr>
     {
         val.fortran_write(dst, self)
     }
 }
+
+impl<'a, T: FortranWrite> FortranDisplay<'a, T> {
+    pub fn new(value: &'a T, fmt: &'a FormatNode) -> FortranDisplay<'a, T> {
+        FortranDisplay { value, fmt }
+    }
+
+    /// The edited records without the trailing newline, or the
+    /// error that keeps the value from being edited.
+    pub fn try_to_string(&self) -> Result<String, WriteErr> {
+        let mut out = Vec::new();
+        {
+            let mut writer = FortranIterWriter::new(self.fmt);
+            writer.write_constants(&mut out, true)?;
+            writer.write_value(&mut out, self.value)?;
+            writer.write_constants(&mut out, false)?;
+        }
+        if out.last() == Some(&b'\n') {
+            out.pop();
+        }
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+}
+
+impl<'a, T: FortranWrite> Display for FortranDisplay<'a, T> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self.try_to_string() {
+            Ok(s) => f.pad(&s),
+            Err(_) => f.pad("*"),
+        }
+    }
+}
+
+impl FortranDefaultWriter {
+    pub fn new() -> FortranDefaultWriter {
+        FortranDefaultWriter::with_config(WriterConfig::default())
+    }
+
+    pub fn with_config(config: WriterConfig) -> FortranDefaultWriter {
+        FortranDefaultWriter {
+            config,
+            items: 0,
+        }
+    }
+
+    /// Start the next item of the record, under DC the values
+    /// are separated by semicolons.
+    fn next_item<W: Write>(&mut self, dst: &mut W) -> Result<(), WriteErr> {
+        if self.items > 0 && self.config.decimal == DecimalType::DC {
+            dst.write_all(b";")?;
+        }
+        self.items += 1;
+        Ok(())
+    }
+
+    pub fn write_value<W: Write, T: FortranWrite>
+        (&mut self, dst: &mut W, val: &T) -> Result<(), WriteErr>
+    {
+        val.fortran_write_default(dst, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::format::parse_format;
+    use ::types::FortranTag;
+    use ::format::{DecimalType, FormatNode, IntFormat, RealFormat};
+    use ::program::FormatProgram;
+    use ::write::{FortranDefaultWriter, FortranDisplay, FortranIterWriter, OverflowMode, WriteErr, WriterConfig};
+
+    #[test]
+    fn g_accepts_all() {
+        let fmt = parse_format("(4G6)").unwrap();
+        let mut out = Vec::new();
+        {
+            let mut writer = FortranIterWriter::new(&fmt);
+            writer.write_constants(&mut out, true).unwrap();
+            writer.write_value(&mut out, &42i32).unwrap();
+            writer.write_value(&mut out, &true).unwrap();
+            writer.write_value(&mut out, &"abc".to_string()).unwrap();
+            writer.write_value(&mut out, &1.5f64).unwrap();
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "    42     T   abc   1.5\n");
+    }
+
+    fn write_fmt(src: &str, vals: &[f64]) -> String {
+        let fmt = parse_format(src).unwrap();
+        let mut out = Vec::new();
+        {
+            let mut writer = FortranIterWriter::new(&fmt);
+            for v in vals {
+                writer.write_constants(&mut out, true).unwrap();
+                writer.write_value(&mut out, v).unwrap();
+            }
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn exponential_editing() {
+        assert_eq!(write_fmt("(E12.4, D12.4, 1P, E12.4)", &[12345.678, -0.000123, 2.5]),
+                   "  0.1235E+05 -0.1230D-03  2.5000E+00\n");
+        assert_eq!(write_fmt("(ES12.4, EN12.3, EN12.3)", &[6.02e23, 999.96, 0.0123]),
+                   "  6.0200E+23   1.000E+03  12.300E-03\n");
+        assert_eq!(write_fmt("(E10.3E3, E10.3, E8.3)", &[1.0e-200, 1.0e200, 1.0e300]),
+                   "0.100E-199 0.100+201.100+301\n");
+        assert_eq!(write_fmt("(EX12.3, EX0.1, EX0)", &[3.0, -0.1, 0.75]),
+                   "  0X1.800P+1-0X1.AP-40X1.8P-1\n");
+        assert_eq!(write_fmt("(EX25.17, EX50.30)", &[3.0, 0.1]),
+                   " 0X1.80000000000000000P+1             0X1.999999999999A00000000000000000P-4\n");
+    }
+
+    #[test]
+    fn general_and_minimal_editing() {
+        assert_eq!(write_fmt("(G12.4, G12.4, G12.4)", &[0.5, 1234.4, 1.0e7]),
+                   "  0.5000       1234.      0.1000E+08\n");
+        assert_eq!(write_fmt("(F0.3, '|', G0.3, '|', G0)", &[-0.5, 2.5, 1.0e20]),
+                   "-0.500|2.50|1E20\n");
+    }
+
+    #[test]
+    fn sign_control() {
+        assert_eq!(write_fmt("(SP, F6.2, S, F6.2, SP, ES10.2, SS, G0)", &[1.5, 1.5, 100.0, 2.0]),
+                   " +1.50  1.50 +1.00E+022.0\n");
+        let fmt = parse_format("(SP, I4, Z4, I4.0, I4)").unwrap();
+        let mut out = Vec::new();
+        {
+            let mut writer = FortranIterWriter::new(&fmt);
+            for v in &[5i32, 255, 0, -3] {
+                writer.write_constants(&mut out, true).unwrap();
+                writer.write_value(&mut out, v).unwrap();
+            }
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "  +5  ff      -3\n");
+    }
+
+    #[test]
+    fn rounding_modes() {
+        // 2.125 lies exactly between 2.12 and 2.13
+        assert_eq!(write_fmt("(RU, F6.2, RD, F6.2, RZ, F6.2, RC, F6.2, RN, F6.2)", &[2.125, 2.125, -2.125, 2.125, 2.125]),
+                   "  2.13  2.12 -2.12  2.13  2.12\n");
+        assert_eq!(write_fmt("(RU, E10.3, RD, E10.3, RZ, ES10.2)", &[1.0001, -1.0001, -9.999]),
+                   " 0.101E+01-0.101E+01 -9.99E+00\n");
+    }
+
+    #[test]
+    fn decimal_comma() {
+        assert_eq!(write_fmt("(DC, F6.2, DP, F6.2, DC, E10.3, G0)", &[1.5, 1.5, 0.25, 2.5]),
+                   "  1,50  1.50 0,250E+002,5\n");
+        let config = WriterConfig { decimal: DecimalType::DC, ..Default::default() };
+        let mut out = Vec::new();
+        {
+            let mut writer = FortranDefaultWriter::with_config(config);
+            writer.write_value(&mut out, &1.5f32).unwrap();
+            writer.write_value(&mut out, &2i32).unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{:>15};{:>12}", "1,500000", 2));
+    }
+
+    #[test]
+    fn records_end() {
+        // reversion starts a new record, running out of data ends it
+        assert_eq!(write_fmt("(2(F4.1))", &[0.0, 0.5, 1.5]), " 0.0 0.5\n 1.5\n");
+        assert_eq!(write_fmt("(F4.1, '|', F4.1)", &[2.0]), " 2.0|\n");
+    }
+
+    #[test]
+    fn shared_program() {
+        let program = FormatProgram::compile(&parse_format("(2(F4.1))").unwrap());
+        let outputs: Vec<String> = ::std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..2).map(|i| {
+                let program = &program;
+                scope.spawn(move || {
+                    let mut out = Vec::new();
+                    let mut writer = FortranIterWriter::from_program(program, WriterConfig::default());
+                    for v in &[i as f64, 0.5] {
+                        writer.write_constants(&mut out, true).unwrap();
+                        writer.write_value(&mut out, v).unwrap();
+                    }
+                    writer.write_constants(&mut out, false).unwrap();
+                    String::from_utf8(out).unwrap()
+                })
+            }).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(outputs, vec![" 0.0 0.5\n", " 1.0 0.5\n"]);
+    }
+
+    #[test]
+    fn binary_minimal_and_unlimited() {
+        let fmt = parse_format("(I0, B8.4, B0, *(I3, :, ','))").unwrap();
+        let mut out = Vec::new();
+        {
+            let mut writer = FortranIterWriter::new(&fmt);
+            for v in &[42, 5, 10, 1, 2, 3] {
+                writer.write_constants(&mut out, true).unwrap();
+                writer.write_value(&mut out, v).unwrap();
+            }
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "42    01011010  1,  2,  3\n");
+    }
+
+    fn write_overflow(src: &str, vals: &[f64], overflow: OverflowMode) -> Result<String, WriteErr> {
+        let fmt = parse_format(src).unwrap();
+        let config = WriterConfig { strict: false, overflow, ..Default::default() };
+        let mut out = Vec::new();
+        {
+            let mut writer = FortranIterWriter::with_config(&fmt, config);
+            writer.write_value(&mut out, &vals)?;
+            writer.write_constants(&mut out, false)?;
+        }
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn field_overflow() {
+        let src = "(F5.3, E9.4, I3, F4.1, E10.3E1)";
+        let vals = [-0.123, 12345.678, 1234.0, 12345.6, 1e20];
+        assert_eq!(write_overflow(src, &vals, OverflowMode::Asterisks).unwrap(),
+                   "-.123.1235E+05*****************\n");
+        assert_eq!(write_overflow(src, &vals, OverflowMode::Widen).unwrap(),
+                   "-.123.1235E+05123412345.60.100E+021\n");
+        match write_overflow("(I3, I3)", &[1.0, 1234.0], OverflowMode::Error) {
+            Err(WriteErr::FieldOverflow(1, FormatNode::Int(IntFormat::I, 3, None), ref text)) if text == "1234" => {},
+            x => panic!("expected FieldOverflow, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn non_advancing() {
+        let prompt = parse_format("('Name? ')").unwrap();
+        let fmt = parse_format("(A, I3)").unwrap();
+        let mut out = Vec::new();
+        {
+            let mut writer = FortranIterWriter::new(&prompt);
+            writer.set_advance(false);
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        assert_eq!(out, b"Name? ");
+        {
+            let mut writer = FortranIterWriter::new(&fmt);
+            writer.set_advance(false);
+            writer.write_value(&mut out, &"ab".to_string()).unwrap();
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        {
+            let mut writer = FortranIterWriter::new(&fmt);
+            writer.write_value(&mut out, &"c".to_string()).unwrap();
+            writer.write_value(&mut out, &7).unwrap();
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "Name? abc  7\n");
+    }
+
+    #[test]
+    fn missing_values() {
+        let fmt = parse_format("(2I4, F8.2, L2, A3, '|')").unwrap();
+        let write = |missing| {
+            let config = WriterConfig { missing, ..Default::default() };
+            let mut out = Vec::new();
+            {
+                let mut writer = FortranIterWriter::with_config(&fmt, config);
+                writer.write_value(&mut out, &vec![Some(1i32), None]).unwrap();
+                writer.write_value(&mut out, &None::<f64>).unwrap();
+                writer.write_value(&mut out, &None::<bool>).unwrap();
+                writer.write_value(&mut out, &None::<String>).unwrap();
+                writer.write_constants(&mut out, false).unwrap();
+            }
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(write(None), "   1                 |\n");
+        assert_eq!(write(Some(-999.0)), "   1-999 -999.00     |\n");
+
+        let config = WriterConfig { missing: Some(-1.0), ..Default::default() };
+        let mut out = Vec::new();
+        let mut writer = FortranDefaultWriter::with_config(config);
+        writer.write_value(&mut out, &vec![Some(2u8), None]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "    2   -1");
+    }
+
+    #[test]
+    fn display() {
+        let real = FormatNode::Real(RealFormat::F, 8, Some(3), None);
+        let fmt = parse_format("('n=', I2.2)").unwrap();
+        assert_eq!(format!("[{}]", FortranDisplay::new(&-1.5, &real)), "[  -1.500]");
+        assert_eq!(format!("[{:<8}|{}]", FortranDisplay::new(&7, &fmt), FortranDisplay::new(&vec![1.0, 2.0], &real)),
+                   "[n=07    |   1.000\n   2.000]");
+
+        let int = FormatNode::Int(IntFormat::I, 3, None);
+        let text = "abc".to_string();
+        assert_eq!(format!("[{:>3}]", FortranDisplay::new(&text, &int)), "[  *]");
+        match FortranDisplay::new(&text, &int).try_to_string() {
+            Err(WriteErr::InvalidEditing(_, FortranTag::Strin)) => {},
+            x => panic!("expected InvalidEditing, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn strict_mismatch() {
+        let fmt = parse_format("(F8.3)").unwrap();
+        let mut out = Vec::new();
+        let mut writer = FortranIterWriter::new(&fmt);
+        match writer.write_value(&mut out, &42i32) {
+            Err(WriteErr::InvalidEditing(_, FortranTag::Int4)) => {},
+            x => panic!("expected InvalidEditing, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn lenient_coercion() {
+        let fmt = parse_format("(F8.3, I4, L3, I3, Z9)").unwrap();
+        let config = WriterConfig { strict: false, ..Default::default() };
+        let mut out = Vec::new();
+        {
+            let mut writer = FortranIterWriter::with_config(&fmt, config);
+            writer.write_value(&mut out, &42i32).unwrap();
+            writer.write_value(&mut out, &-2.75f64).unwrap();
+            writer.write_value(&mut out, &3u8).unwrap();
+            writer.write_value(&mut out, &true).unwrap();
+            writer.write_value(&mut out, &1.0f32).unwrap();
+            writer.write_constants(&mut out, false).unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "  42.000  -2  T -1 3f800000\n");
+    }
+
+    #[test]
+    fn character_never_coerced() {
+        let fmt = parse_format("(I4)").unwrap();
+        let config = WriterConfig { strict: false, ..Default::default() };
+        let mut out = Vec::new();
+        let mut writer = FortranIterWriter::with_config(&fmt, config);
+        match writer.write_value(&mut out, &"abc".to_string()) {
+            Err(WriteErr::InvalidEditing(_, FortranTag::Strin)) => {},
+            x => panic!("expected InvalidEditing, got {:?}", x),
+        }
+    }
+}